@@ -1,5 +1,48 @@
+use crate::dto::config_cron_list_response::{BuzzerOutput, BuzzerPattern};
+use crate::dto::config_response::Transport;
+use crate::helper::outbound_queue_helper::OverflowPolicy;
+
+// fallback WiFi network when none was entered in the setup page, leave empty to provision every device
 pub const WIFI_SSID: &str = "";
 pub const WIFI_PASS: &str = "";
+// a connection attempt gives up after this delay
+pub const WIFI_CONNECT_TIMEOUT_MILLISECONDS: u32 = 30000;
+// failed connections in a row after which the next saved network is tried
+pub const WIFI_FAILOVER_AFTER_FAILURES: u32 = 2;
+// failed connections, over all the saved networks, after which the setup portal starts
+pub const PROVISIONING_AFTER_FAILED_CONNECTIONS: u32 = 6;
+// the setup portal gives up after this delay and tries the known network again, if there is one
+pub const PROVISIONING_TIMEOUT_MINUTES: u32 = 10;
+// name of the setup access point, followed by the end of the MAC address
+pub const PROVISIONING_AP_SSID_PREFIX: &str = "Elisys-Alarm-";
+// the WPA2 passphrase of the setup access point is derived from the MAC address with this secret, to print on the device label
+pub const PROVISIONING_AP_SECRET: &str = "change-me";
+// the server of the URLs below, replaced by the server URL entered in the setup page
+pub const DEFAULT_SERVER_URL: &str = "http://192.168.1.102:8080";
+// look for the Elisys server on the local network with mDNS, the server URL above is the fallback
+pub const ENABLE_SERVER_DISCOVERY: bool = true;
+// DNS-SD service type the Elisys server announces over TCP
+pub const SERVER_MDNS_SERVICE: &str = "_elisys";
+pub const SERVER_DISCOVERY_TIMEOUT_MILLISECONDS: u32 = 3000;
+// failed deliveries in a row after which the server is looked for again, at most every SERVER_DISCOVERY_RETRY_MINUTES
+pub const SERVER_DISCOVERY_AFTER_FAILURES: u32 = 3;
+pub const SERVER_DISCOVERY_RETRY_MINUTES: u32 = 5;
+// DNS-SD service type the device announces, with its local API port
+pub const DEVICE_MDNS_SERVICE: &str = "_elisys-clock";
+// CA certificate (PEM) the https:// servers must chain to, None for the CA bundle of ESP-IDF
+pub const SERVER_CA_CERTIFICATE: Option<&str> = None;
+// SHA-256 (hexadecimal) of the server certificate or of its public key, e.g. from
+// openssl x509 -in server.pem -pubkey -noout | openssl pkey -pubin -outform der | sha256sum
+// when set, an https:// server is accepted only if it presents one of them, whatever its CA
+pub const SERVER_CERTIFICATE_PINS: &[&str] = &[];
+// refuse the http:// server URLs, including the ones announced with mDNS
+pub const REQUIRE_HTTPS: bool = false;
+// Ed25519 public key (64 hexadecimal digits) the server signs the configurations with; it wins over
+// the key entered in the setup page, None uses that one or accepts the unsigned configurations
+pub const CONFIGURATION_PUBLIC_KEY: Option<&str> = None;
+// with a public key, false only logs the configurations whose signature is missing or invalid, or
+// which are for another device or older than the one in use, and the unsigned unchanged answers
+pub const REJECT_UNVERIFIED_CONFIGURATION: bool = true;
 // should be retrieved from server
 pub const DEFAULT_CRONTAB: &[&str; 2] = &[
     "0   45   8     1-31       Jan-Dec  Mon,Tue,Wed,Thu,Fri  2023-2100",
@@ -7,8 +50,16 @@ pub const DEFAULT_CRONTAB: &[&str; 2] = &[
 ];
 // should be retrieved from server
 pub const DEFAULT_ALARM_INTERVAL_MINUTES: u32 = 1;
+// sound of the alarms that do not set a pattern
+pub const DEFAULT_ALARM_PATTERN: BuzzerPattern = BuzzerPattern::Alternate;
+// buzzers driven by the alarms that do not set outputs
+pub const DEFAULT_ALARM_OUTPUTS: &[BuzzerOutput] = &[BuzzerOutput::Buzzer1, BuzzerOutput::Buzzer2];
+// intensity an escalating alarm starts with, when the server does not set it
+pub const DEFAULT_ESCALATION_START_PERCENT: u8 = 10;
 // user timezone
-pub const DEFAULT_TIMEZONE: i32 = 1 * 60 * 60;
+pub const DEFAULT_TIMEZONE: i32 = 60 * 60;
+// user timezone with DST, IANA name (e.g. Some("Europe/Rome")) or POSIX TZ string, overrides DEFAULT_TIMEZONE
+pub const DEFAULT_TIMEZONE_NAME: Option<&str> = None;
 // I am alive endpoint
 pub const DEFAULT_I_AM_ALIVE_ENDPOINT: &str = "";
 // I am alive time interval
@@ -16,15 +67,66 @@ pub const DEFAULT_I_AM_ALIVE_INTERVAL_SECONDS: u32 = 30;
 // configuration download endpoint
 pub const DEFAULT_CONFIGURATION_URI: &str =
     "http://192.168.1.102:8080/api/v1/alarm-clock/configuration";
+// one-shot alarms that rang are reported to this endpoint
+pub const ALARM_CONSUMED_URL: &str =
+    "http://192.168.1.102:8080/api/v1/alarm-clock/alarm-consumed";
+// alarm events (fired, snoozed, dismissed, missed) are reported to this endpoint
+pub const ALARM_EVENT_URL: &str = "http://192.168.1.102:8080/api/v1/alarm-clock/alarm-event";
 // configuration check cron
 pub const CHECK_INTERVAL_CONFIGURATION_CRON: &str =
     "0   0-59   0-23      1-31       Jan-Dec  Mon,Tue,Wed,Thu,Fri,Sat,Sun          2023-2100";
 pub const ENABLE_I_AM_ALIVE_ACK: bool = false;
 // Device registration endpoint
 pub const REGISTER_DEVICE_URL: &str = "http://192.168.1.102:8080/api/v1/device/register";
+// Transport::Http polls the configuration, Transport::Mqtt receives it from the broker; can be overridden by the server
+pub const DEFAULT_TRANSPORT: Transport = Transport::Http;
+// MQTT broker used with Transport::Mqtt
+pub const MQTT_BROKER_URL: &str = "mqtt://192.168.1.102:1883";
+// MQTT topics are <MQTT_TOPIC_PREFIX>/<mac address>/<config|status|device|heartbeat|events/...>
+pub const MQTT_TOPIC_PREFIX: &str = "elisys/alarm-clock";
+// delay between two attempts to connect to the MQTT broker
+pub const MQTT_RECONNECT_DELAY_MILLISECONDS: u32 = 5000;
+// publishes Home Assistant MQTT discovery payloads and handles their commands, with Transport::Mqtt
+pub const ENABLE_HOME_ASSISTANT_DISCOVERY: bool = true;
+// discovery topics are <HOME_ASSISTANT_DISCOVERY_PREFIX>/<component>/<node id>/<object id>/config
+pub const HOME_ASSISTANT_DISCOVERY_PREFIX: &str = "homeassistant";
+// serves the local REST API (status, alarms, test buzz, dismiss) on API_SERVER_PORT
+pub const ENABLE_API_SERVER: bool = true;
+// port of the local REST API, not 80: the setup portal needs it
+pub const API_SERVER_PORT: u16 = 8080;
+// larger request bodies are rejected
+pub const API_MAX_REQUEST_SIZE: usize = 4 * 1024;
+// a request not answered by then gets a 503, e.g. while the device synchronizes its clock
+pub const API_RESPONSE_TIMEOUT_MILLISECONDS: u32 = 5000;
+// duration of a test buzz when the request does not tell
+pub const DEFAULT_TEST_BUZZ_SECONDS: u32 = 3;
 // Device name
 pub const DEVICE_NAME: &str = "Alarm Clock";
 // Device description
 pub const DEVICE_DESCRIPTION: &str = "Alarm Clock Device";
 // Device type
 pub const DEVICE_TYPE: &str = "AlarmClock";
+// snooze button GPIO (None if no button is wired), can be overridden by the server
+pub const DEFAULT_SNOOZE_GPIO: Option<i32> = Some(4);
+// minutes before a snoozed alarm rings again
+pub const DEFAULT_SNOOZE_MINUTES: u32 = 5;
+// snoozes allowed for a single alarm, then only a long press silences it
+pub const DEFAULT_MAX_SNOOZE_COUNT: u32 = 3;
+// holding the snooze button this long dismisses the alarm
+pub const DEFAULT_DISMISS_LONG_PRESS_MILLISECONDS: u32 = 2000;
+// a saved configuration older than this is still used, but logged as stale
+pub const STORED_CONFIGURATION_MAX_AGE_HOURS: i64 = 24;
+// larger HTTP responses and MQTT messages are rejected instead of being truncated
+pub const HTTP_MAX_RESPONSE_SIZE: usize = 16 * 1024;
+// retries after a timeout or a 5xx response from the server
+pub const HTTP_RETRY_COUNT: u32 = 2;
+pub const HTTP_RETRY_DELAY_MILLISECONDS: u32 = 500;
+// messages for the server (heartbeats, alarm events, diagnostics) kept while it is unreachable
+pub const OUTBOUND_QUEUE_CAPACITY: usize = 32;
+// which message is lost when the queue is full: DropOldest or DropNewest
+pub const OUTBOUND_QUEUE_POLICY: OverflowPolicy = OverflowPolicy::DropOldest;
+// delay after a failed delivery, doubled after each consecutive failure
+pub const OUTBOUND_QUEUE_INITIAL_BACKOFF_MILLISECONDS: u32 = 2000;
+pub const OUTBOUND_QUEUE_MAX_BACKOFF_MILLISECONDS: u32 = 5 * 60 * 1000;
+// diagnostics (e.g. failed configuration downloads) are reported to this endpoint
+pub const DIAGNOSTIC_URL: &str = "http://192.168.1.102:8080/api/v1/alarm-clock/diagnostic";
@@ -0,0 +1,25 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[warn(non_snake_case)]
+pub struct AlarmConsumedRequest {
+    #[serde(rename = "macAddress")]
+    mac_address: String,
+    at: DateTime<FixedOffset>,
+    description: String,
+}
+
+impl AlarmConsumedRequest {
+    pub fn new(
+        mac_address: String,
+        at: DateTime<FixedOffset>,
+        description: String,
+    ) -> AlarmConsumedRequest {
+        AlarmConsumedRequest {
+            mac_address,
+            at,
+            description,
+        }
+    }
+}
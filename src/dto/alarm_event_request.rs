@@ -0,0 +1,47 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlarmOutcome {
+    Fired,
+    Snoozed,
+    Dismissed,
+    /// the alarm window passed without ringing, e.g. while the device was busy
+    Missed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[warn(non_snake_case)]
+pub struct AlarmEventRequest {
+    #[serde(rename = "macAddress")]
+    mac_address: String,
+    #[serde(rename = "alarmId", skip_serializing_if = "Option::is_none")]
+    alarm_id: Option<String>,
+    description: String,
+    #[serde(rename = "scheduledAt")]
+    scheduled_at: DateTime<FixedOffset>,
+    #[serde(rename = "occurredAt")]
+    occurred_at: DateTime<FixedOffset>,
+    outcome: AlarmOutcome,
+}
+
+impl AlarmEventRequest {
+    pub fn new(
+        mac_address: String,
+        alarm_id: Option<String>,
+        description: String,
+        scheduled_at: DateTime<FixedOffset>,
+        occurred_at: DateTime<FixedOffset>,
+        outcome: AlarmOutcome,
+    ) -> AlarmEventRequest {
+        AlarmEventRequest {
+            mac_address,
+            alarm_id,
+            description,
+            scheduled_at,
+            occurred_at,
+            outcome,
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use serde::Serialize;
+
+use super::config_cron_list_response::CronListResponse;
+
+/// An alarm listed by `GET /api/alarms`.
+#[derive(Serialize, Debug)]
+#[warn(non_snake_case)]
+pub struct AlarmResponse {
+    /// identifies the alarm in `DELETE /api/alarms/<key>`, see `alarm_key`
+    pub key: String,
+    /// added through the local API, instead of sent by the server
+    pub local: bool,
+    /// `enabled` and not switched off on the device
+    #[serde(rename = "switchedOn")]
+    pub switched_on: bool,
+    #[serde(flatten)]
+    pub alarm: CronListResponse,
+}
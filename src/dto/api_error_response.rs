@@ -0,0 +1,13 @@
+use serde::Serialize;
+
+/// Body of the local API responses with an error status.
+#[derive(Serialize, Debug)]
+pub struct ApiErrorResponse {
+    error: String,
+}
+
+impl ApiErrorResponse {
+    pub fn new(error: String) -> ApiErrorResponse {
+        ApiErrorResponse { error }
+    }
+}
@@ -1,14 +1,112 @@
-use serde::Deserialize;
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Clone, Debug)]
+use super::{escalation_response::EscalationResponse, pause_response::PauseResponse};
+use crate::config::config::{DEFAULT_ALARM_OUTPUTS, DEFAULT_ALARM_PATTERN};
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
 #[warn(non_snake_case)]
 pub struct CronListResponse {
+    /// Identifies the alarm in the events sent to the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub cron: String,
+
+    /// One-shot alarm: rings once at this RFC 3339 date time instead of
+    /// following `cron`, then it is reported to the server as consumed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at: Option<DateTime<FixedOffset>>,
+
     pub description: String,
+
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    /// Minutes the alarm rings, `alarmIntervalMinutes` of the configuration
+    /// when missing.
+    #[serde(
+        rename = "durationMinutes",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub duration_minutes: Option<u32>,
+
+    #[serde(default = "default_pattern")]
+    pub pattern: BuzzerPattern,
+
+    #[serde(default = "default_outputs")]
+    pub outputs: Vec<BuzzerOutput>,
+
+    /// RTTTL melody played instead of `pattern`, for passive buzzers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub melody: Option<String>,
+
+    /// Starts quiet and sparse and ramps up; full intensity when missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escalation: Option<EscalationResponse>,
+
+    /// Dates on which this alarm does not ring, on top of the global ones.
+    #[serde(rename = "skipDates", default, skip_serializing_if = "Vec::is_empty")]
+    pub skip_dates: Vec<NaiveDate>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pauses: Vec<PauseResponse>,
+
+    /// Does not ring on the `holidays` of the configuration.
+    #[serde(rename = "workdaysOnly", default)]
+    pub workdays_only: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BuzzerPattern {
+    /// 100 ms on each output in turn
+    Alternate,
+    /// 100 ms on, 100 ms off, all outputs together
+    Together,
+    /// 100 ms on, 900 ms off: a gentle chime
+    Chime,
+    /// always on
+    Continuous,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BuzzerOutput {
+    Buzzer1,
+    Buzzer2,
 }
 
 impl CronListResponse {
-    // pub fn new(cron: String, description: String) -> CronListResponse {
-    //     CronListResponse { cron, description }
-    // }
+    pub fn new(cron: String, description: String) -> CronListResponse {
+        CronListResponse {
+            id: None,
+            cron,
+            at: None,
+            description,
+            enabled: default_enabled(),
+            duration_minutes: None,
+            pattern: default_pattern(),
+            outputs: default_outputs(),
+            melody: None,
+            escalation: None,
+            skip_dates: Vec::new(),
+            pauses: Vec::new(),
+            workdays_only: false,
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_pattern() -> BuzzerPattern {
+    DEFAULT_ALARM_PATTERN
+}
+
+fn default_outputs() -> Vec<BuzzerOutput> {
+    DEFAULT_ALARM_OUTPUTS.to_vec()
 }
@@ -5,10 +5,15 @@ use serde::Serialize;
 pub struct ConfigRequest {
     #[serde(rename = "macAddress")]
     mac_address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
 }
 
 impl ConfigRequest {
-    pub fn new(mac_address: String) -> ConfigRequest {
-        ConfigRequest { mac_address }
+    pub fn new(mac_address: String, version: Option<String>) -> ConfigRequest {
+        ConfigRequest {
+            mac_address,
+            version,
+        }
     }
 }
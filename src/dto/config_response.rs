@@ -1,9 +1,35 @@
-use serde::Deserialize;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
 
-use super::config_cron_list_response::CronListResponse;
+use super::{config_cron_list_response::CronListResponse, pause_response::PauseResponse};
+use crate::config::config::{
+    DEFAULT_DISMISS_LONG_PRESS_MILLISECONDS, DEFAULT_MAX_SNOOZE_COUNT, DEFAULT_SNOOZE_GPIO,
+    DEFAULT_SNOOZE_MINUTES, DEFAULT_TIMEZONE_NAME, DEFAULT_TRANSPORT,
+};
 
-#[derive(Deserialize, Debug)]
+/// How the device talks with the server.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// configuration polled with HTTP POSTs
+    Http,
+    /// configuration pushed on a per-device topic of the MQTT broker
+    Mqtt,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct Configuration {
+    /// Sent back to the server to download the configuration only when it
+    /// changed; taken from the `ETag` header when the server sets one and
+    /// does not sign the configurations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    /// When the server signed the configuration: an older signed
+    /// configuration is refused, so that it cannot be replayed.
+    #[serde(rename = "issuedAt", default, skip_serializing_if = "Option::is_none")]
+    pub issued_at: Option<DateTime<Utc>>,
+
     #[serde(rename = "iamAliveEndpoint")]
     pub i_am_alive_endpoint: String,
 
@@ -16,6 +42,67 @@ pub struct Configuration {
     #[serde(rename = "timezoneSeconds")]
     pub timezone_seconds: i32,
 
+    /// IANA zone name such as `Europe/Rome` or POSIX TZ string; when set it
+    /// replaces `timezoneSeconds` and follows the DST transitions.
+    #[serde(default = "default_timezone", skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+
     #[serde(rename = "alarmIntervalMinutes")]
     pub alarm_interval_minutes: u32,
+
+    /// Dates on which no alarm rings, e.g. holidays.
+    #[serde(rename = "skipDates", default, skip_serializing_if = "Vec::is_empty")]
+    pub skip_dates: Vec<NaiveDate>,
+
+    /// Vacations: no alarm rings during these ranges.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pauses: Vec<PauseResponse>,
+
+    /// Public holidays, skipped by the `workdaysOnly` alarms: dates
+    /// (`2024-12-24`), yearly dates (`12-25`) or days from Easter
+    /// (`easter+1`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub holidays: Vec<String>,
+
+    #[serde(rename = "snoozeGpio", default = "default_snooze_gpio")]
+    pub snooze_gpio: Option<i32>,
+
+    #[serde(rename = "snoozeMinutes", default = "default_snooze_minutes")]
+    pub snooze_minutes: u32,
+
+    #[serde(rename = "maxSnoozeCount", default = "default_max_snooze_count")]
+    pub max_snooze_count: u32,
+
+    #[serde(
+        rename = "dismissLongPressMilliseconds",
+        default = "default_dismiss_long_press_milliseconds"
+    )]
+    pub dismiss_long_press_milliseconds: u32,
+
+    #[serde(default = "default_transport")]
+    pub transport: Transport,
+}
+
+fn default_timezone() -> Option<String> {
+    DEFAULT_TIMEZONE_NAME.map(|name| name.to_owned())
+}
+
+fn default_transport() -> Transport {
+    DEFAULT_TRANSPORT
+}
+
+fn default_snooze_gpio() -> Option<i32> {
+    DEFAULT_SNOOZE_GPIO
+}
+
+fn default_snooze_minutes() -> u32 {
+    DEFAULT_SNOOZE_MINUTES
+}
+
+fn default_max_snooze_count() -> u32 {
+    DEFAULT_MAX_SNOOZE_COUNT
+}
+
+fn default_dismiss_long_press_milliseconds() -> u32 {
+    DEFAULT_DISMISS_LONG_PRESS_MILLISECONDS
 }
@@ -0,0 +1,8 @@
+use serde::Deserialize;
+
+/// Body returned instead of `304 Not Modified` by servers that answer the
+/// `version` of `ConfigRequest`.
+#[derive(Deserialize, Debug)]
+pub struct ConfigUnchangedResponse {
+    pub unchanged: bool,
+}
@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+/// Returned by the server at registration to authenticate the next requests
+/// of the device: a bearer `token`, or a `secret` signing them.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct DeviceCredentials {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[warn(non_snake_case)]
+pub struct DiagnosticRequest {
+    #[serde(rename = "macAddress")]
+    mac_address: String,
+    at: DateTime<Utc>,
+    message: String,
+}
+
+impl DiagnosticRequest {
+    pub fn new(mac_address: String, at: DateTime<Utc>, message: String) -> DiagnosticRequest {
+        DiagnosticRequest {
+            mac_address,
+            at,
+            message,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::config::DEFAULT_ESCALATION_START_PERCENT;
+
+/// The alarm starts at `startPercent` of its intensity and reaches full
+/// intensity after `rampMinutes`.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct EscalationResponse {
+    #[serde(rename = "startPercent", default = "default_start_percent")]
+    pub start_percent: u8,
+
+    /// The whole duration of the alarm when missing.
+    #[serde(
+        rename = "rampMinutes",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ramp_minutes: Option<u32>,
+}
+
+fn default_start_percent() -> u8 {
+    DEFAULT_ESCALATION_START_PERCENT
+}
@@ -0,0 +1,32 @@
+use serde::Serialize;
+
+/// The device all the entities of the alarm clock belong to.
+#[derive(Serialize, Clone, Debug)]
+pub struct HomeAssistantDevice {
+    pub identifiers: Vec<String>,
+    pub connections: Vec<(String, String)>,
+    pub name: String,
+    pub model: String,
+    pub manufacturer: String,
+}
+
+/// Payload of a Home Assistant MQTT discovery topic; the field names are
+/// the ones Home Assistant expects.
+#[derive(Serialize, Clone, Debug)]
+pub struct HomeAssistantDiscovery {
+    pub name: String,
+    pub unique_id: String,
+    /// last level of the discovery topic, not part of the payload
+    #[serde(skip)]
+    pub object_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command_topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_class: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    pub availability_topic: String,
+    pub device: HomeAssistantDevice,
+}
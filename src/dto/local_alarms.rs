@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+use super::config_cron_list_response::CronListResponse;
+
+/// Changes made on the device to the alarms of the configuration, as
+/// persisted in NVS: alarms switched off from Home Assistant and alarms
+/// added through the local API.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[warn(non_snake_case)]
+pub struct LocalAlarms {
+    #[serde(rename = "alarmsEnabled")]
+    pub alarms_enabled: bool,
+    /// keys of the disabled alarms, see `alarm_key`
+    #[serde(rename = "disabledAlarms", default)]
+    pub disabled_alarms: Vec<String>,
+    /// alarms added on the device, always with an `id`; they ring even when
+    /// the server is unreachable
+    #[serde(default)]
+    pub added: Vec<CronListResponse>,
+}
+
+impl Default for LocalAlarms {
+    fn default() -> Self {
+        LocalAlarms {
+            alarms_enabled: true,
+            disabled_alarms: Vec::new(),
+            added: Vec::new(),
+        }
+    }
+}
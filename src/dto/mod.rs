@@ -1,5 +1,25 @@
+pub mod alarm_consumed_request;
+pub mod alarm_event_request;
+pub mod alarm_response;
+pub mod api_error_response;
 pub mod config_cron_list_response;
 pub mod config_request;
 pub mod config_response;
+pub mod config_unchanged_response;
+pub mod device_credentials;
+pub mod diagnostic_request;
+pub mod escalation_response;
+pub mod home_assistant_discovery;
+pub mod local_alarms;
+pub mod outbound_message;
+pub mod outbound_queue_status;
+pub mod pause_response;
 pub mod register_device;
 pub mod request_i_am_alive;
+pub mod signed_configuration;
+pub mod static_ip_settings;
+pub mod status_response;
+pub mod stored_configuration;
+pub mod test_buzz_request;
+pub mod wifi_credentials;
+pub mod wifi_settings;
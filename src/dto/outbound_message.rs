@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    alarm_consumed_request::AlarmConsumedRequest, alarm_event_request::AlarmEventRequest,
+    diagnostic_request::DiagnosticRequest, request_i_am_alive::RequestIAmAlive,
+};
+
+/// A message for the server, kept in the outbound queue until delivered.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum OutboundMessage {
+    Heartbeat {
+        endpoint: String,
+        request: RequestIAmAlive,
+    },
+    AlarmConsumed(AlarmConsumedRequest),
+    AlarmEvent(AlarmEventRequest),
+    Diagnostic(DiagnosticRequest),
+}
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[warn(non_snake_case)]
+pub struct OutboundQueueStatus {
+    /// messages waiting to be delivered
+    pub depth: usize,
+    pub capacity: usize,
+    /// messages lost because the queue was full, since the last reboot
+    pub dropped: u32,
+    /// consecutive failed delivery attempts
+    pub failures: u32,
+}
@@ -0,0 +1,9 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+/// Alarms do not ring from `from` until `until`, both included.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct PauseResponse {
+    pub from: NaiveDate,
+    pub until: NaiveDate,
+}
@@ -1,14 +1,28 @@
-use serde::Serialize;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 
-#[derive(Serialize)]
+use super::outbound_queue_status::OutboundQueueStatus;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[warn(non_snake_case)]
 pub struct RequestIAmAlive {
     #[serde(rename = "macAddress")]
     mac_address: String,
+    at: DateTime<Utc>,
+    #[serde(rename = "outboundQueue")]
+    outbound_queue: OutboundQueueStatus,
 }
 
 impl RequestIAmAlive {
-    pub fn new(mac_address: String) -> RequestIAmAlive {
-        RequestIAmAlive { mac_address }
+    pub fn new(
+        mac_address: String,
+        at: DateTime<Utc>,
+        outbound_queue: OutboundQueueStatus,
+    ) -> RequestIAmAlive {
+        RequestIAmAlive {
+            mac_address,
+            at,
+            outbound_queue,
+        }
     }
 }
@@ -0,0 +1,23 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Configuration signed by the server, over HTTP and MQTT alike. The
+/// `configuration` JSON is kept as a string so that the signed bytes are
+/// exactly the received ones, whatever the spacing and order of its keys.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SignedConfiguration {
+    pub configuration: String,
+    /// Ed25519 signature of the UTF-8 bytes of `configuration`, in
+    /// hexadecimal
+    pub signature: String,
+}
+
+/// Fields of a signed `configuration` that bind it to a device and a time,
+/// so that it cannot be sent to another device or replayed later.
+#[derive(Deserialize, Debug)]
+pub struct SignedConfigurationBinding {
+    #[serde(rename = "macAddress")]
+    pub mac_address: Option<String>,
+    #[serde(rename = "issuedAt")]
+    pub issued_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,15 @@
+use std::net::Ipv4Addr;
+
+use serde::{Deserialize, Serialize};
+
+/// Fixed IPv4 addressing of the station interface, instead of DHCP.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[warn(non_snake_case)]
+pub struct StaticIpSettings {
+    pub address: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    /// primary and optional secondary DNS server
+    #[serde(rename = "dnsServers", default)]
+    pub dns_servers: Vec<Ipv4Addr>,
+}
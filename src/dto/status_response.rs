@@ -0,0 +1,41 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::Serialize;
+
+use super::config_response::Transport;
+
+/// Where the alarm clock is in the ringing cycle of the last alarm.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlarmState {
+    Idle,
+    Ringing,
+    Snoozed,
+    Dismissed,
+}
+
+/// Answer of `GET /api/status`.
+#[derive(Serialize, Debug)]
+#[warn(non_snake_case)]
+pub struct StatusResponse {
+    #[serde(rename = "macAddress")]
+    pub mac_address: String,
+    pub now: DateTime<FixedOffset>,
+    #[serde(rename = "nextAlarm")]
+    pub next_alarm: Option<DateTime<FixedOffset>>,
+    #[serde(rename = "nextAlarmDescription")]
+    pub next_alarm_description: Option<String>,
+    #[serde(rename = "alarmState")]
+    pub alarm_state: AlarmState,
+    /// when the clock was last synchronized with NTP, `null` before
+    #[serde(rename = "clockSynchronizedAt")]
+    pub clock_synchronized_at: Option<DateTime<Utc>>,
+    /// dBm, `null` when the WiFi is disconnected
+    #[serde(rename = "wifiRssi")]
+    pub wifi_rssi: Option<i32>,
+    #[serde(rename = "uptimeSeconds")]
+    pub uptime_seconds: u64,
+    /// `version` of the configuration in use, `null` for the default one
+    #[serde(rename = "configurationVersion")]
+    pub configuration_version: Option<String>,
+    pub transport: Transport,
+}
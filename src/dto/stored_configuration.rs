@@ -0,0 +1,13 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::config_response::Configuration;
+
+/// Last configuration received from the server, as persisted in NVS.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct StoredConfiguration {
+    pub version: u32,
+    #[serde(rename = "savedAt")]
+    pub saved_at: DateTime<Utc>,
+    pub configuration: Configuration,
+}
@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+use super::config_cron_list_response::{BuzzerOutput, BuzzerPattern};
+use crate::config::config::{
+    DEFAULT_ALARM_OUTPUTS, DEFAULT_ALARM_PATTERN, DEFAULT_TEST_BUZZ_SECONDS,
+};
+
+/// Body of `POST /api/buzz`, every field is optional.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[warn(non_snake_case)]
+pub struct TestBuzzRequest {
+    #[serde(default = "default_seconds")]
+    pub seconds: u32,
+    #[serde(default = "default_pattern")]
+    pub pattern: BuzzerPattern,
+    #[serde(default = "default_outputs")]
+    pub outputs: Vec<BuzzerOutput>,
+}
+
+impl Default for TestBuzzRequest {
+    fn default() -> Self {
+        TestBuzzRequest {
+            seconds: default_seconds(),
+            pattern: default_pattern(),
+            outputs: default_outputs(),
+        }
+    }
+}
+
+fn default_seconds() -> u32 {
+    DEFAULT_TEST_BUZZ_SECONDS
+}
+
+fn default_pattern() -> BuzzerPattern {
+    DEFAULT_ALARM_PATTERN
+}
+
+fn default_outputs() -> Vec<BuzzerOutput> {
+    DEFAULT_ALARM_OUTPUTS.to_vec()
+}
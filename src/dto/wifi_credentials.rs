@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+use super::static_ip_settings::StaticIpSettings;
+
+/// A saved WiFi network.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[warn(non_snake_case)]
+pub struct WifiCredentials {
+    pub ssid: String,
+    pub password: String,
+    /// the highest is tried first when the last connected network is not
+    /// in range
+    #[serde(default)]
+    pub priority: i32,
+    /// DHCP when absent
+    #[serde(rename = "staticIp", default, skip_serializing_if = "Option::is_none")]
+    pub static_ip: Option<StaticIpSettings>,
+}
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+use super::wifi_credentials::WifiCredentials;
+
+/// Entered in the setup page, as persisted in NVS.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+#[warn(non_snake_case)]
+pub struct WifiSettings {
+    pub networks: Vec<WifiCredentials>,
+    /// SSID of the network of the last successful connection, saved apart
+    /// so that the fallback network of `config.rs` is never persisted
+    #[serde(skip)]
+    pub last_connected: Option<String>,
+    /// replaces `DEFAULT_SERVER_URL` in the server URLs, e.g.
+    /// `http://192.168.1.20:8080`
+    #[serde(rename = "serverUrl", default, skip_serializing_if = "Option::is_none")]
+    pub server_url: Option<String>,
+    /// used without `CONFIGURATION_PUBLIC_KEY`, in hexadecimal; the setup
+    /// page cannot replace it
+    #[serde(
+        rename = "configurationPublicKey",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub configuration_public_key: Option<String>,
+}
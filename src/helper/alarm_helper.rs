@@ -0,0 +1,69 @@
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use log::error;
+
+use super::{
+    date_helper::{calculate_next_scheduled_time, is_time_to_buzz},
+    escalation_helper::Escalation,
+    melody_helper::{parse_rtttl, Melody},
+    timezone_helper::UserTimeZone,
+};
+use crate::{
+    dto::config_cron_list_response::{BuzzerOutput, BuzzerPattern},
+    ConfigurationResponse,
+};
+
+/// Next alarm of the cron list, with the settings it rings with.
+#[derive(Clone, Debug)]
+pub struct ScheduledAlarm {
+    pub id: Option<String>,
+    pub date_time: DateTime<FixedOffset>,
+    pub description: String,
+    pub duration_minutes: u32,
+    pub pattern: BuzzerPattern,
+    pub outputs: Vec<BuzzerOutput>,
+    pub melody: Option<Melody>,
+    pub escalation: Option<Escalation>,
+}
+
+impl ScheduledAlarm {
+    pub fn is_time_to_buzz(&self, now: DateTime<FixedOffset>) -> bool {
+        is_time_to_buzz(self.date_time, now, self.duration_minutes)
+    }
+
+    pub fn is_over(&self, now: DateTime<FixedOffset>) -> bool {
+        now >= self.date_time + Duration::minutes(self.duration_minutes as i64)
+    }
+}
+
+/// `None` when no alarm of the configuration is left to ring.
+pub fn calculate_next_alarm(
+    now: &DateTime<Utc>,
+    configuration: &ConfigurationResponse,
+    timezone: &UserTimeZone,
+) -> Option<ScheduledAlarm> {
+    calculate_next_scheduled_time(now, configuration, timezone).map(|(date_time, cron)| {
+        // an alarm rings at least one minute
+        let duration_minutes = cron
+            .duration_minutes
+            .unwrap_or(configuration.alarm_interval_minutes)
+            .max(1);
+        ScheduledAlarm {
+            id: cron.id.clone(),
+            date_time,
+            description: cron.description.clone(),
+            duration_minutes,
+            pattern: cron.pattern,
+            outputs: cron.outputs.clone(),
+            // an invalid melody falls back to the pattern
+            melody: cron.melody.as_ref().and_then(|melody| {
+                parse_rtttl(melody)
+                    .map_err(|e| error!("invalid melody of {}: {}", cron.description, e))
+                    .ok()
+            }),
+            escalation: cron.escalation.as_ref().map(|escalation| Escalation {
+                start_percent: escalation.start_percent,
+                ramp_ms: escalation.ramp_minutes.unwrap_or(duration_minutes) as u64 * 60_000,
+            }),
+        }
+    })
+}
@@ -1,12 +1,23 @@
 use crate::ConfigurationResponse;
 use crate::{
     config::config::{
-        DEFAULT_ALARM_INTERVAL_MINUTES, DEFAULT_CRONTAB, DEFAULT_I_AM_ALIVE_ENDPOINT,
-        DEFAULT_I_AM_ALIVE_INTERVAL_SECONDS, DEFAULT_TIMEZONE,
+        DEFAULT_ALARM_INTERVAL_MINUTES, DEFAULT_CRONTAB, DEFAULT_DISMISS_LONG_PRESS_MILLISECONDS,
+        DEFAULT_I_AM_ALIVE_ENDPOINT, DEFAULT_I_AM_ALIVE_INTERVAL_SECONDS, DEFAULT_MAX_SNOOZE_COUNT,
+        DEFAULT_SNOOZE_GPIO, DEFAULT_SNOOZE_MINUTES, DEFAULT_TIMEZONE, DEFAULT_TIMEZONE_NAME,
+        DEFAULT_TRANSPORT, STORED_CONFIGURATION_MAX_AGE_HOURS,
     },
-    dto::config_cron_list_response::CronListResponse,
+    dto::{config_cron_list_response::CronListResponse, stored_configuration::StoredConfiguration},
+    helper::{date_helper::parse_schedule, timezone_helper::parse_timezone},
+    platform::storage::Storage,
 };
-use log::error;
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use log::{error, info, warn};
+
+const STORED_CONFIGURATION_KEY: &str = "configuration";
+// bump when `StoredConfiguration` changes in an incompatible way
+const STORED_CONFIGURATION_VERSION: u32 = 1;
+// an unchanged configuration is written again only after this delay, to spare the flash
+const STORED_CONFIGURATION_REFRESH_MINUTES: i64 = 60;
 
 pub fn get_default_configuration(e: anyhow::Error) -> ConfigurationResponse {
     error!(
@@ -14,15 +25,249 @@ pub fn get_default_configuration(e: anyhow::Error) -> ConfigurationResponse {
         e
     );
     ConfigurationResponse {
+        version: None,
+        issued_at: None,
         i_am_alive_endpoint: DEFAULT_I_AM_ALIVE_ENDPOINT.to_owned(),
         i_am_alive_interval_seconds: DEFAULT_I_AM_ALIVE_INTERVAL_SECONDS,
         cron_list: DEFAULT_CRONTAB
-            .map(|item| CronListResponse {
-                cron: item.to_owned(),
-                description: "alarm".to_owned(),
-            })
+            .map(|item| CronListResponse::new(item.to_owned(), "alarm".to_owned()))
             .to_vec(),
         timezone_seconds: DEFAULT_TIMEZONE,
+        timezone: DEFAULT_TIMEZONE_NAME.map(|name| name.to_owned()),
         alarm_interval_minutes: DEFAULT_ALARM_INTERVAL_MINUTES,
+        skip_dates: Vec::new(),
+        pauses: Vec::new(),
+        holidays: Vec::new(),
+        snooze_gpio: DEFAULT_SNOOZE_GPIO,
+        snooze_minutes: DEFAULT_SNOOZE_MINUTES,
+        max_snooze_count: DEFAULT_MAX_SNOOZE_COUNT,
+        dismiss_long_press_milliseconds: DEFAULT_DISMISS_LONG_PRESS_MILLISECONDS,
+        transport: DEFAULT_TRANSPORT,
+    }
+}
+
+/// Falls back to the last configuration saved with `save_configuration`,
+/// and only if there is none to the compile-time defaults.
+pub fn get_saved_or_default_configuration(
+    saved_configuration: Option<ConfigurationResponse>,
+    e: anyhow::Error,
+) -> ConfigurationResponse {
+    match saved_configuration {
+        Some(configuration) => {
+            error!(
+                "Error while trying to load configuration from remote server, using the saved one: {:?}",
+                e
+            );
+            configuration
+        }
+        None => get_default_configuration(e),
+    }
+}
+
+/// Checks what the alarm loop relies on, so that a configuration that would
+/// stop it is neither applied nor saved: the crontabs and `timezoneSeconds`.
+/// A `timezone` the device does not know is only logged, the alarms then
+/// follow `timezoneSeconds`.
+pub fn validate_configuration(configuration: &ConfigurationResponse) -> Result<(), String> {
+    for cron in &configuration.cron_list {
+        if cron.at.is_none() && !cron.cron.is_empty() {
+            parse_schedule(&cron.cron)?;
+        }
+    }
+    if FixedOffset::east_opt(configuration.timezone_seconds).is_none() {
+        return Err(format!(
+            "invalid timezoneSeconds: {}",
+            configuration.timezone_seconds
+        ));
+    }
+    if let Some(timezone) = &configuration.timezone {
+        if parse_timezone(timezone).is_none() {
+            warn!(
+                "unknown timezone {}, the alarms follow timezoneSeconds",
+                timezone
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn save_configuration(
+    storage: &mut dyn Storage,
+    now: DateTime<Utc>,
+    configuration: &ConfigurationResponse,
+) {
+    let stored_configuration = StoredConfiguration {
+        version: STORED_CONFIGURATION_VERSION,
+        saved_at: now,
+        configuration: configuration.clone(),
+    };
+    if is_saved_recently(storage, &stored_configuration) {
+        return;
+    }
+    let saved = serde_json::to_string(&stored_configuration)
+        .map_err(anyhow::Error::from)
+        .and_then(|value| storage.set(STORED_CONFIGURATION_KEY, &value));
+    if let Err(e) = saved {
+        error!("unable to save the configuration: {:?}", e);
+    }
+}
+
+pub fn is_same_configuration(
+    configuration: &ConfigurationResponse,
+    other: &ConfigurationResponse,
+) -> bool {
+    serde_json::to_string(configuration).ok() == serde_json::to_string(other).ok()
+}
+
+fn is_saved_recently(
+    storage: &mut dyn Storage,
+    stored_configuration: &StoredConfiguration,
+) -> bool {
+    let saved = storage
+        .get(STORED_CONFIGURATION_KEY)
+        .ok()
+        .flatten()
+        .and_then(|value| serde_json::from_str::<StoredConfiguration>(&value).ok());
+    match saved {
+        None => false,
+        Some(saved) => {
+            saved.version == stored_configuration.version
+                && stored_configuration.saved_at - saved.saved_at
+                    < Duration::minutes(STORED_CONFIGURATION_REFRESH_MINUTES)
+                && is_same_configuration(&saved.configuration, &stored_configuration.configuration)
+        }
+    }
+}
+
+pub fn load_saved_configuration(
+    storage: &mut dyn Storage,
+    now: DateTime<Utc>,
+) -> Option<ConfigurationResponse> {
+    let value = match storage.get(STORED_CONFIGURATION_KEY) {
+        Ok(Some(value)) => value,
+        Ok(None) => return None,
+        Err(e) => {
+            error!("unable to read the saved configuration: {:?}", e);
+            return None;
+        }
+    };
+    let stored_configuration = match serde_json::from_str::<StoredConfiguration>(&value) {
+        Ok(stored_configuration) => stored_configuration,
+        Err(e) => {
+            error!("invalid saved configuration: {}", e);
+            return None;
+        }
+    };
+    if let Err(e) = validate_configuration(&stored_configuration.configuration) {
+        warn!("ignoring the saved configuration: {}", e);
+        return None;
+    }
+    if stored_configuration.version != STORED_CONFIGURATION_VERSION {
+        warn!(
+            "ignoring saved configuration version {}, expected {}",
+            stored_configuration.version, STORED_CONFIGURATION_VERSION
+        );
+        return None;
+    }
+    let age = now - stored_configuration.saved_at;
+    if age > Duration::hours(STORED_CONFIGURATION_MAX_AGE_HOURS) {
+        warn!(
+            "saved configuration is stale: saved at {} ({} hours ago)",
+            stored_configuration.saved_at,
+            age.num_hours()
+        );
+    } else {
+        info!(
+            "saved configuration loaded, saved at {}",
+            stored_configuration.saved_at
+        );
+    }
+    Some(stored_configuration.configuration)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::platform::storage::MemoryStorage;
+
+    fn configuration() -> ConfigurationResponse {
+        let mut configuration = get_default_configuration(anyhow::Error::msg("test"));
+        configuration.cron_list = vec![CronListResponse::new(
+            "0 30 7 * * Mon-Fri *".to_owned(),
+            "wake up".to_owned(),
+        )];
+        configuration
+    }
+
+    fn at(hours: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 4, 7, 0, 0).unwrap() + Duration::hours(hours)
+    }
+
+    #[test]
+    fn saved_configuration_is_loaded_back() {
+        let mut storage = MemoryStorage::new();
+        save_configuration(&mut storage, at(0), &configuration());
+        let loaded = load_saved_configuration(&mut storage, at(48)).unwrap();
+        assert!(is_same_configuration(&loaded, &configuration()));
+    }
+
+    #[test]
+    fn nothing_saved_falls_back_to_the_defaults() {
+        let mut storage = MemoryStorage::new();
+        let saved = load_saved_configuration(&mut storage, at(0));
+        let fallback = get_saved_or_default_configuration(saved, anyhow::Error::msg("offline"));
+        assert_eq!(fallback.cron_list.len(), DEFAULT_CRONTAB.len());
+    }
+
+    #[test]
+    fn unchanged_configuration_is_not_written_again_right_away() {
+        let mut storage = MemoryStorage::new();
+        save_configuration(&mut storage, at(0), &configuration());
+        let first = storage.get(STORED_CONFIGURATION_KEY).unwrap();
+        save_configuration(&mut storage, at(0) + Duration::minutes(5), &configuration());
+        assert_eq!(storage.get(STORED_CONFIGURATION_KEY).unwrap(), first);
+        save_configuration(&mut storage, at(2), &configuration());
+        assert_ne!(storage.get(STORED_CONFIGURATION_KEY).unwrap(), first);
+    }
+
+    #[test]
+    fn other_stored_versions_are_ignored() {
+        let mut storage = MemoryStorage::new();
+        save_configuration(&mut storage, at(0), &configuration());
+        let stored = storage.get(STORED_CONFIGURATION_KEY).unwrap().unwrap();
+        let stored = stored.replacen("\"version\":1", "\"version\":0", 1);
+        storage.set(STORED_CONFIGURATION_KEY, &stored).unwrap();
+        assert!(load_saved_configuration(&mut storage, at(0)).is_none());
+    }
+
+    #[test]
+    fn invalid_crontab_or_timezone_offset_is_rejected() {
+        assert!(validate_configuration(&configuration()).is_ok());
+        let mut invalid = configuration();
+        invalid.cron_list[0].cron = "every morning".to_owned();
+        assert!(validate_configuration(&invalid).is_err());
+        let mut invalid = configuration();
+        invalid.timezone_seconds = 30 * 60 * 60;
+        assert!(validate_configuration(&invalid).is_err());
+    }
+
+    #[test]
+    fn unknown_timezone_is_accepted() {
+        let mut unknown = configuration();
+        unknown.timezone = Some("Europe/Luxembourg".to_owned());
+        assert!(validate_configuration(&unknown).is_ok());
+        unknown.timezone = Some("Mars/Olympus_Mons".to_owned());
+        assert!(validate_configuration(&unknown).is_ok());
+    }
+
+    #[test]
+    fn invalid_saved_configuration_is_not_loaded() {
+        let mut storage = MemoryStorage::new();
+        let mut invalid = configuration();
+        invalid.cron_list[0].cron = "every morning".to_owned();
+        save_configuration(&mut storage, at(0), &invalid);
+        assert!(load_saved_configuration(&mut storage, at(0)).is_none());
     }
 }
@@ -1,42 +1,99 @@
 use std::str::FromStr;
 
-use chrono::{DateTime, Datelike, FixedOffset, Timelike, Utc};
+use chrono::{
+    DateTime, Duration, FixedOffset, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc,
+};
 use cron::Schedule;
 use log::{error, info};
 
+use super::{
+    holiday_helper::{is_holiday, parse_holiday_rules, HolidayRule},
+    timezone_helper::UserTimeZone,
+};
 use crate::{
-    config::config::DEFAULT_ALARM_INTERVAL_MINUTES,
-    dto::config_cron_list_response::CronListResponse,
+    dto::{config_cron_list_response::CronListResponse, pause_response::PauseResponse},
+    ConfigurationResponse,
 };
+
+/// Skip dates and pauses that apply to one alarm: the global ones of the
+/// configuration and its own, plus the holidays for a workdays only alarm.
+pub struct Suppressions<'a> {
+    skip_dates: [&'a [NaiveDate]; 2],
+    pauses: [&'a [PauseResponse]; 2],
+    holidays: &'a [HolidayRule],
+}
+
+impl<'a> Suppressions<'a> {
+    pub fn for_alarm(
+        configuration: &'a ConfigurationResponse,
+        cron: &'a CronListResponse,
+        holidays: &'a [HolidayRule],
+    ) -> Suppressions<'a> {
+        Suppressions {
+            skip_dates: [&configuration.skip_dates, &cron.skip_dates],
+            pauses: [&configuration.pauses, &cron.pauses],
+            holidays: if cron.workdays_only { holidays } else { &[] },
+        }
+    }
+
+    /// Last date of the skip date or pause that suppresses `date`, if any.
+    pub fn suppressed_until(&self, date: NaiveDate) -> Option<NaiveDate> {
+        let skipped = self
+            .skip_dates
+            .iter()
+            .flat_map(|skip_dates| skip_dates.iter())
+            .filter(|skip_date| **skip_date == date)
+            .copied();
+        let paused = self
+            .pauses
+            .iter()
+            .flat_map(|pauses| pauses.iter())
+            .filter(|pause| pause.from <= date && date <= pause.until)
+            .map(|pause| pause.until);
+        let holiday = Some(date).filter(|date| is_holiday(*date, self.holidays));
+        skipped.chain(paused).chain(holiday).max()
+    }
+}
+
+pub fn parse_schedule(cron_string: &str) -> Result<Schedule, String> {
+    Schedule::from_str(cron_string).map_err(|e| format!("invalid crontab {}: {}", cron_string, e))
+}
+
+/// Next occurrence of `cron_string` that is not suppressed, `None` when the
+/// schedule ends before.
 pub fn from_str_to_date_time(
+    now: &DateTime<Utc>,
     cron_string: &str,
-    offset_crontab: &FixedOffset,
-) -> DateTime<FixedOffset> {
-    let schedule_result = Schedule::from_str(&cron_string);
-    let schedule;
-    if schedule_result.is_err() {
-        error!("invalid crontab value");
-        panic!();
-    } else {
-        schedule = schedule_result.unwrap();
-    }
-    calculate_next_date_time(&schedule, offset_crontab)
+    timezone: &UserTimeZone,
+    suppressions: &Suppressions,
+) -> Result<Option<DateTime<FixedOffset>>, String> {
+    let schedule = parse_schedule(cron_string)?;
+    let mut after = timezone.local_time(now).naive_local();
+    loop {
+        let processed = match calculate_next_date_time(now, &after, &schedule, timezone) {
+            Some(processed) => processed,
+            None => return Ok(None),
+        };
+        match suppressions.suppressed_until(processed.date_naive()) {
+            None => return Ok(Some(processed)),
+            Some(until) => {
+                info!("--> {} suppressed until {}", processed, until);
+                after = until.and_hms_opt(23, 59, 59).unwrap();
+            }
+        }
+    }
 }
 
+/// Next occurrence of `cron_string` after `date_time`, an error when the
+/// crontab is invalid or has no next occurrence.
 pub fn from_str_to_date_time_after(
     date_time: &DateTime<FixedOffset>,
     cron_string: &str,
     offset_crontab: &FixedOffset,
-) -> DateTime<FixedOffset> {
-    let schedule_result = Schedule::from_str(&cron_string);
-    let schedule;
-    if schedule_result.is_err() {
-        error!("invalid crontab value");
-        panic!();
-    } else {
-        schedule = schedule_result.unwrap();
-    }
+) -> Result<DateTime<FixedOffset>, String> {
+    let schedule = parse_schedule(cron_string)?;
     calculate_next_date_time2(date_time, &schedule, offset_crontab)
+        .ok_or_else(|| format!("crontab {} has no next occurrence", cron_string))
 }
 
 pub fn is_same_time(alarm: DateTime<FixedOffset>, now: DateTime<FixedOffset>) -> bool {
@@ -51,60 +108,299 @@ pub fn is_same_sec(second_number: u32, now: DateTime<FixedOffset>) -> bool {
     now.second() == second_number
 }
 
-pub fn calculate_next_date_time(
+/// The schedule is matched against the wall clock of the user timezone: an
+/// alarm inside the hour skipped when DST starts rings as soon as the clock
+/// jumps forward, one inside the hour repeated when DST ends rings only the
+/// first time.
+fn calculate_next_date_time(
+    now: &DateTime<Utc>,
+    after: &NaiveDateTime,
     schedule: &Schedule,
-    offset: &FixedOffset,
-) -> DateTime<FixedOffset> {
-    schedule
-        .upcoming(Utc)
-        .take(1)
-        .into_iter()
-        .last()
-        .unwrap()
-        .with_timezone(offset)
+    timezone: &UserTimeZone,
+) -> Option<DateTime<FixedOffset>> {
+    // cron sees the local time as if it were UTC, so it never shifts hours
+    let mut after = Utc.from_utc_datetime(after);
+    loop {
+        let local = schedule.after(&after).next()?;
+        match timezone.resolve_local(&local.naive_utc()) {
+            LocalResult::Single(date_time) => return Some(date_time),
+            LocalResult::Ambiguous(first, _) if first > *now => return Some(first),
+            LocalResult::Ambiguous(_, _) => after = local,
+            LocalResult::None => return Some(first_time_after_gap(&local, timezone)),
+        }
+    }
+}
+
+fn first_time_after_gap(local: &DateTime<Utc>, timezone: &UserTimeZone) -> DateTime<FixedOffset> {
+    let mut local = *local;
+    loop {
+        local += Duration::minutes(1);
+        if let Some(date_time) = timezone.resolve_local(&local.naive_utc()).earliest() {
+            return date_time;
+        }
+    }
 }
 
 pub fn calculate_next_date_time2(
     after: &DateTime<FixedOffset>,
     schedule: &Schedule,
     offset: &FixedOffset,
-) -> DateTime<FixedOffset> {
+) -> Option<DateTime<FixedOffset>> {
     schedule
         .after(after)
-        .take(1)
-        .into_iter()
-        .last()
-        .unwrap()
-        .with_timezone(offset)
+        .next()
+        .map(|date_time| date_time.with_timezone(offset))
 }
 
-pub fn calculate_next_scheduled_time(
-    configuration_crontab: &Vec<CronListResponse>,
-    offset: &FixedOffset,
-) -> DateTime<FixedOffset> {
-    if configuration_crontab.len() > 0 {
-        let now = Utc::now().with_timezone(offset);
-        let mut lower_date_time =
-            from_str_to_date_time(configuration_crontab.get(0).unwrap().cron.as_str(), offset);
-        for cron in configuration_crontab.iter() {
-            let processed = from_str_to_date_time(cron.cron.as_str(), offset);
-            info!("--> processed: {}, now: {}", processed, now);
-            if processed < lower_date_time && processed >= now {
-                lower_date_time = processed;
+/// Picks the enabled alarm of the cron list that rings first, cron and
+/// one-shot alarms alike, jumping over skip dates, pauses and holidays.
+pub fn calculate_next_scheduled_time<'a>(
+    now: &DateTime<Utc>,
+    configuration: &'a ConfigurationResponse,
+    timezone: &UserTimeZone,
+) -> Option<(DateTime<FixedOffset>, &'a CronListResponse)> {
+    let local_now = timezone.local_time(now);
+    let holidays = parse_holiday_rules(&configuration.holidays);
+    let selected = configuration
+        .cron_list
+        .iter()
+        .filter(|cron| cron.enabled)
+        .filter_map(|cron| {
+            let suppressions = Suppressions::for_alarm(configuration, cron, &holidays);
+            let processed = match cron.at {
+                // a one-shot alarm in the past already rang, or was missed
+                Some(at) if at >= *now => timezone.local_time(&at.with_timezone(&Utc)),
+                Some(_) => return None,
+                None if cron.cron.is_empty() => {
+                    error!("alarm without cron nor date time: {}", cron.description);
+                    return None;
+                }
+                None => {
+                    match from_str_to_date_time(now, cron.cron.as_str(), timezone, &suppressions) {
+                        Ok(processed) => processed?,
+                        Err(e) => {
+                            error!("skipping alarm {}: {}", cron.description, e);
+                            return None;
+                        }
+                    }
+                }
+            };
+            if let Some(until) = suppressions.suppressed_until(processed.date_naive()) {
+                info!("--> {} suppressed until {}", processed, until);
+                return None;
             }
-        }
-        info!("--> selected: {}", lower_date_time);
-        return lower_date_time;
+            info!("--> processed: {}, now: {}", processed, local_now);
+            Some((processed, cron))
+        })
+        .min_by_key(|(processed, _)| *processed);
+    match &selected {
+        Some((date_time, cron)) => info!("--> selected: {} ({})", date_time, cron.description),
+        None => info!("--> no enabled alarm"),
     }
-    let offset = FixedOffset::east_opt(-60).unwrap();
-    return Utc::now().with_timezone(&offset);
+    selected
 }
 
-pub fn is_time_to_buzz(alarm: DateTime<FixedOffset>, now: DateTime<FixedOffset>) -> bool {
-    alarm.day() == now.day()
-        && alarm.month() == now.month()
-        && alarm.year() == now.year()
-        && alarm.hour() == now.hour()
-        && now.minute() >= alarm.minute()
-        && now.minute() <= alarm.minute() + (DEFAULT_ALARM_INTERVAL_MINUTES)
+pub fn is_time_to_buzz(
+    alarm: DateTime<FixedOffset>,
+    now: DateTime<FixedOffset>,
+    duration_minutes: u32,
+) -> bool {
+    now >= alarm && now < alarm + Duration::minutes(duration_minutes as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configuration(cron: &str) -> ConfigurationResponse {
+        let mut configuration = crate::helper::configuration_helper::get_default_configuration(
+            anyhow::Error::msg("test"),
+        );
+        configuration.cron_list = vec![CronListResponse::new(cron.to_owned(), "alarm".to_owned())];
+        configuration
+    }
+
+    fn utc(date_time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(date_time)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn utc_zone() -> UserTimeZone {
+        UserTimeZone::Fixed(FixedOffset::east_opt(0).unwrap())
+    }
+
+    #[test]
+    fn invalid_crontab_is_an_error() {
+        let configuration = configuration("every morning");
+        let suppressions =
+            Suppressions::for_alarm(&configuration, &configuration.cron_list[0], &[]);
+        let now = utc("2024-03-04T06:00:00Z");
+        assert!(from_str_to_date_time(&now, "every morning", &utc_zone(), &suppressions).is_err());
+        let local = now.with_timezone(&FixedOffset::east_opt(0).unwrap());
+        assert!(from_str_to_date_time_after(&local, "every morning", local.offset()).is_err());
+    }
+
+    #[test]
+    fn invalid_alarm_is_skipped() {
+        let mut configuration = configuration("0 30 7 * * * *");
+        configuration.cron_list.push(CronListResponse::new(
+            "every morning".to_owned(),
+            "invalid".to_owned(),
+        ));
+        let now = utc("2024-03-04T06:00:00Z");
+        let (date_time, cron) =
+            calculate_next_scheduled_time(&now, &configuration, &utc_zone()).unwrap();
+        assert_eq!(date_time, utc("2024-03-04T07:30:00Z"));
+        assert_eq!(cron.description, "alarm");
+    }
+
+    #[test]
+    fn next_occurrence_after() {
+        let local =
+            utc("2024-03-04T06:00:10Z").with_timezone(&FixedOffset::east_opt(3600).unwrap());
+        let next = from_str_to_date_time_after(&local, "0 * * * * * *", local.offset()).unwrap();
+        assert_eq!(next, utc("2024-03-04T06:01:00Z"));
+        assert_eq!(next.offset().local_minus_utc(), 3600);
+    }
+
+    fn rome_alarm(configuration: &ConfigurationResponse, now: &str) -> DateTime<FixedOffset> {
+        let rome = crate::helper::timezone_helper::parse_timezone("Europe/Rome").unwrap();
+        calculate_next_scheduled_time(&utc(now), configuration, &rome)
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn alarm_in_the_skipped_hour_rings_when_the_clock_jumps() {
+        let configuration = configuration("0 30 2 * * * *");
+        let next = rome_alarm(&configuration, "2024-03-30T23:00:00Z");
+        assert_eq!(next.to_rfc3339(), "2024-03-31T03:00:00+02:00");
+    }
+
+    #[test]
+    fn alarm_in_the_repeated_hour_rings_once() {
+        let configuration = configuration("0 30 2 * * * *");
+        let next = rome_alarm(&configuration, "2024-10-26T22:00:00Z");
+        assert_eq!(next.to_rfc3339(), "2024-10-27T02:30:00+02:00");
+        let next = rome_alarm(&configuration, "2024-10-27T00:31:00Z");
+        assert_eq!(next.to_rfc3339(), "2024-10-28T02:30:00+01:00");
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    fn next_alarm(configuration: &ConfigurationResponse, now: &str) -> Option<DateTime<Utc>> {
+        calculate_next_scheduled_time(&utc(now), configuration, &utc_zone())
+            .map(|(date_time, _)| date_time.with_timezone(&Utc))
+    }
+
+    #[test]
+    fn skip_dates_skip_one_occurrence() {
+        let mut global_skip_date = configuration("0 30 7 * * * *");
+        global_skip_date.skip_dates = vec![date("2024-03-04")];
+        assert_eq!(
+            next_alarm(&global_skip_date, "2024-03-04T06:00:00Z"),
+            Some(utc("2024-03-05T07:30:00Z"))
+        );
+
+        let mut own_skip_dates = configuration("0 30 7 * * * *");
+        own_skip_dates.cron_list[0].skip_dates = vec![date("2024-03-04"), date("2024-03-05")];
+        assert_eq!(
+            next_alarm(&own_skip_dates, "2024-03-04T06:00:00Z"),
+            Some(utc("2024-03-06T07:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn pauses_suppress_every_day_they_cover() {
+        let pause = PauseResponse {
+            from: date("2024-03-04"),
+            until: date("2024-03-10"),
+        };
+        let mut global_pause = configuration("0 30 7 * * * *");
+        global_pause.pauses = vec![pause.clone()];
+        assert_eq!(
+            next_alarm(&global_pause, "2024-03-01T06:00:00Z"),
+            Some(utc("2024-03-01T07:30:00Z"))
+        );
+        assert_eq!(
+            next_alarm(&global_pause, "2024-03-04T06:00:00Z"),
+            Some(utc("2024-03-11T07:30:00Z"))
+        );
+
+        // an alarm pause leaves the other alarms ringing
+        let mut own_pause = configuration("0 30 7 * * * *");
+        own_pause.cron_list[0].pauses = vec![pause];
+        own_pause.cron_list.push(CronListResponse::new(
+            "0 0 9 * * * *".to_owned(),
+            "late".to_owned(),
+        ));
+        assert_eq!(
+            next_alarm(&own_pause, "2024-03-04T06:00:00Z"),
+            Some(utc("2024-03-04T09:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn pauses_span_month_and_year_boundaries() {
+        let cases = [
+            (
+                "2024-01-29",
+                "2024-02-04",
+                "2024-01-29T06:00:00Z",
+                "2024-02-05T07:30:00Z",
+            ),
+            (
+                "2024-01-29",
+                "2024-02-04",
+                "2024-01-31T08:00:00Z",
+                "2024-02-05T07:30:00Z",
+            ),
+            (
+                "2024-12-23",
+                "2025-01-06",
+                "2024-12-23T06:00:00Z",
+                "2025-01-07T07:30:00Z",
+            ),
+            (
+                "2024-12-23",
+                "2025-01-06",
+                "2024-12-31T08:00:00Z",
+                "2025-01-07T07:30:00Z",
+            ),
+        ];
+        for (from, until, now, expected) in cases {
+            let pause = PauseResponse {
+                from: date(from),
+                until: date(until),
+            };
+            let mut global_pause = configuration("0 30 7 * * * *");
+            global_pause.pauses = vec![pause.clone()];
+            assert_eq!(
+                next_alarm(&global_pause, now),
+                Some(utc(expected)),
+                "{}",
+                now
+            );
+
+            let mut own_pause = configuration("0 30 7 * * * *");
+            own_pause.cron_list[0].pauses = vec![pause];
+            assert_eq!(next_alarm(&own_pause, now), Some(utc(expected)), "{}", now);
+        }
+    }
+
+    #[test]
+    fn one_shot_alarm_on_a_skipped_day_does_not_ring() {
+        let mut configuration = configuration("");
+        configuration.cron_list[0].at =
+            Some(DateTime::parse_from_rfc3339("2024-03-05T07:30:00Z").unwrap());
+        assert_eq!(
+            next_alarm(&configuration, "2024-03-04T06:00:00Z"),
+            Some(utc("2024-03-05T07:30:00Z"))
+        );
+        configuration.skip_dates = vec![date("2024-03-05")];
+        assert_eq!(next_alarm(&configuration, "2024-03-04T06:00:00Z"), None);
+    }
 }
@@ -0,0 +1,203 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use log::{error, warn};
+use sha2::Sha256;
+
+use crate::{
+    config::config::DEFAULT_SERVER_URL, dto::device_credentials::DeviceCredentials,
+    helper::tls_helper::to_hex, platform::storage::Storage,
+};
+
+const DEVICE_CREDENTIALS_KEY: &str = "device_auth";
+pub const AUTHORIZATION_HEADER: &str = "Authorization";
+pub const TIMESTAMP_HEADER: &str = "X-Elisys-Timestamp";
+pub const SIGNATURE_HEADER: &str = "X-Elisys-Signature";
+
+/// Authenticates the requests with the credentials of the device, when the
+/// server issued some.
+pub struct RequestSigner {
+    credentials: Option<DeviceCredentials>,
+    /// whether the Elisys server is reached over HTTPS
+    over_https: bool,
+    now: DateTime<Utc>,
+}
+
+impl RequestSigner {
+    /// `server_url` is where the requests for `DEFAULT_SERVER_URL` go.
+    pub fn new(
+        credentials: Option<DeviceCredentials>,
+        server_url: &str,
+        now: DateTime<Utc>,
+    ) -> RequestSigner {
+        RequestSigner {
+            credentials,
+            over_https: server_url.starts_with("https://"),
+            now,
+        }
+    }
+
+    /// `Authorization: Bearer` with a token over HTTPS, else the timestamp
+    /// and the signature of the request with a secret, else nothing: a token
+    /// sent in clear could be replayed by anyone on the network. The server
+    /// must refuse the timestamps outside a short window, the device cannot
+    /// tell a replayed signature apart. The URLs of other servers than the
+    /// Elisys one, e.g. an I am alive endpoint, never get the credentials.
+    pub fn headers(&self, url: &str, payload: &[u8]) -> Vec<(String, String)> {
+        let credentials = match &self.credentials {
+            Some(credentials) if url.starts_with(DEFAULT_SERVER_URL) => credentials,
+            _ => return Vec::new(),
+        };
+        match (&credentials.token, &credentials.secret) {
+            (Some(token), _) if self.over_https => {
+                vec![(AUTHORIZATION_HEADER.to_owned(), format!("Bearer {}", token))]
+            }
+            (_, Some(secret)) => {
+                let timestamp = self.now.timestamp();
+                vec![
+                    (TIMESTAMP_HEADER.to_owned(), timestamp.to_string()),
+                    (
+                        SIGNATURE_HEADER.to_owned(),
+                        sign_request(secret, timestamp, url, payload),
+                    ),
+                ]
+            }
+            (Some(_), None) => {
+                warn!("not sending the device token over plain HTTP");
+                Vec::new()
+            }
+            (None, None) => Vec::new(),
+        }
+    }
+}
+
+/// HMAC-SHA256, in hexadecimal, of `<timestamp>\n<path>\n<body>`. The path
+/// is signed rather than the URL, whose server can be rebased.
+pub fn sign_request(secret: &str, timestamp: i64, url: &str, payload: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}\n{}\n", timestamp, url_path(url)).as_bytes());
+    mac.update(payload);
+    to_hex(&mac.finalize().into_bytes())
+}
+
+/// The path of `url`, `/` when it has none.
+fn url_path(url: &str) -> &str {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    without_scheme
+        .find('/')
+        .map_or("/", |start| &without_scheme[start..])
+}
+
+/// The credentials of the last registration, if the server issued some.
+pub fn load_device_credentials(storage: &mut dyn Storage) -> Option<DeviceCredentials> {
+    let stored = storage.get(DEVICE_CREDENTIALS_KEY).unwrap_or_else(|e| {
+        error!("unable to read the device credentials: {:?}", e);
+        None
+    })?;
+    if stored.is_empty() {
+        return None;
+    }
+    serde_json::from_str(&stored)
+        .map_err(|e| error!("invalid saved device credentials: {}", e))
+        .ok()
+}
+
+pub fn save_device_credentials(storage: &mut dyn Storage, credentials: &DeviceCredentials) {
+    let saved = serde_json::to_string(credentials)
+        .map_err(anyhow::Error::from)
+        .and_then(|value| storage.set(DEVICE_CREDENTIALS_KEY, &value));
+    if let Err(e) = saved {
+        error!("unable to save the device credentials: {:?}", e);
+    }
+}
+
+pub fn forget_device_credentials(storage: &mut dyn Storage) {
+    if let Err(e) = storage.set(DEVICE_CREDENTIALS_KEY, "") {
+        error!("unable to forget the device credentials: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HTTPS_SERVER_URL: &str = "https://elisys.example";
+
+    fn signer(token: Option<&str>, secret: Option<&str>, server_url: &str) -> RequestSigner {
+        let credentials = DeviceCredentials {
+            token: token.map(str::to_owned),
+            secret: secret.map(str::to_owned),
+        };
+        let now = DateTime::from_timestamp(1_709_541_600, 0).unwrap();
+        RequestSigner::new(Some(credentials), server_url, now)
+    }
+
+    fn url(path: &str) -> String {
+        format!("{}{}", DEFAULT_SERVER_URL, path)
+    }
+
+    fn names(headers: &[(String, String)]) -> Vec<&str> {
+        headers.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    #[test]
+    fn token_is_sent_over_https_only() {
+        let url = url("/api/v1/alarm-clock/configuration");
+        let headers = signer(Some("t0ken"), None, HTTPS_SERVER_URL).headers(&url, b"{}");
+        assert_eq!(
+            headers,
+            [(AUTHORIZATION_HEADER.to_owned(), "Bearer t0ken".to_owned())]
+        );
+        assert!(signer(Some("t0ken"), None, DEFAULT_SERVER_URL)
+            .headers(&url, b"{}")
+            .is_empty());
+    }
+
+    #[test]
+    fn secret_signs_the_request_over_plain_http() {
+        let url = url("/api/v1/alarm-clock/configuration");
+        let headers =
+            signer(Some("t0ken"), Some("s3cret"), DEFAULT_SERVER_URL).headers(&url, b"{}");
+        assert_eq!(names(&headers), [TIMESTAMP_HEADER, SIGNATURE_HEADER]);
+        assert_eq!(headers[0].1, "1709541600");
+        assert_eq!(
+            headers[1].1,
+            sign_request("s3cret", 1_709_541_600, &url, b"{}")
+        );
+    }
+
+    #[test]
+    fn signature_covers_the_timestamp_the_path_and_the_body() {
+        let url = url("/api/v1/alarm-clock/configuration");
+        let signature = sign_request("s3cret", 1_709_541_600, &url, b"{}");
+        assert_eq!(signature.len(), 64);
+        // the server may be rebased, the path stays
+        assert_eq!(
+            signature,
+            sign_request(
+                "s3cret",
+                1_709_541_600,
+                "https://elisys.example/api/v1/alarm-clock/configuration",
+                b"{}"
+            )
+        );
+        assert_ne!(
+            signature,
+            sign_request("s3cret", 1_709_541_601, &url, b"{}")
+        );
+        assert_ne!(
+            signature,
+            sign_request("s3cret", 1_709_541_600, &url, b"[]")
+        );
+        assert_ne!(signature, sign_request("other", 1_709_541_600, &url, b"{}"));
+    }
+
+    #[test]
+    fn other_servers_get_no_credentials() {
+        let headers = signer(Some("t0ken"), Some("s3cret"), HTTPS_SERVER_URL)
+            .headers("https://monitoring.example/alive", b"{}");
+        assert!(headers.is_empty());
+        let anonymous = RequestSigner::new(None, HTTPS_SERVER_URL, Utc::now());
+        assert!(anonymous.headers(&url("/api"), b"{}").is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+use log::error;
+
+use crate::platform::{mdns::MdnsService, storage::Storage};
+
+const DISCOVERED_SERVER_KEY: &str = "server_url";
+const DEFAULT_SCHEME: &str = "http";
+
+/// `<scheme>://<address>:<port><path>` of an instance: its first IPv4
+/// address, else its `.local` hostname. The `scheme` TXT record may ask for
+/// `https`, the `path` one gives the base path of the server.
+pub fn server_url_of(service: &MdnsService) -> Option<String> {
+    let host = match service.addresses.first() {
+        Some(address) => address.to_string(),
+        None if !service.hostname.is_empty() => {
+            format!("{}.local", service.hostname.trim_end_matches(".local"))
+        }
+        None => return None,
+    };
+    let scheme = txt_record(service, "scheme").unwrap_or(DEFAULT_SCHEME);
+    if scheme != "http" && scheme != "https" {
+        return None;
+    }
+    let path = txt_record(service, "path").map_or("", |path| path.trim_matches('/'));
+    if path.contains(|c: char| c.is_whitespace() || c == '?' || c == '#') {
+        return None;
+    }
+    match path.is_empty() {
+        true => Some(format!("{}://{}:{}", scheme, host, service.port)),
+        false => Some(format!("{}://{}:{}/{}", scheme, host, service.port, path)),
+    }
+}
+
+fn txt_record<'a>(service: &'a MdnsService, key: &str) -> Option<&'a str> {
+    service
+        .txt
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value.as_str())
+}
+
+/// The server among the announced ones: the cached one while it is still
+/// announced, else the first instance by name, so that all the devices
+/// pick the same; `None` when none is usable.
+pub fn select_server_url(services: &[MdnsService], cached: Option<&str>) -> Option<String> {
+    let mut services: Vec<&MdnsService> = services.iter().collect();
+    services.sort_by(|a, b| a.instance.cmp(&b.instance));
+    let urls: Vec<String> = services.into_iter().filter_map(server_url_of).collect();
+    match cached {
+        Some(cached) if urls.iter().any(|url| url == cached) => Some(cached.to_owned()),
+        _ => urls.into_iter().next(),
+    }
+}
+
+pub fn load_discovered_server_url(storage: &mut dyn Storage) -> Option<String> {
+    storage.get(DISCOVERED_SERVER_KEY).unwrap_or_else(|e| {
+        error!("unable to read the discovered server: {:?}", e);
+        None
+    })
+}
+
+pub fn save_discovered_server_url(storage: &mut dyn Storage, server_url: &str) {
+    if let Err(e) = storage.set(DISCOVERED_SERVER_KEY, server_url) {
+        error!("unable to save the discovered server: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::platform::storage::MemoryStorage;
+
+    fn server(instance: &str, address: Option<[u8; 4]>, txt: &[(&str, &str)]) -> MdnsService {
+        MdnsService {
+            instance: instance.to_owned(),
+            hostname: format!("{}-host", instance),
+            addresses: address.map(Ipv4Addr::from).into_iter().collect(),
+            port: 8080,
+            txt: txt
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn server_url_uses_the_address_else_the_hostname() {
+        assert_eq!(
+            server_url_of(&server("a", Some([192, 168, 1, 20]), &[])).as_deref(),
+            Some("http://192.168.1.20:8080")
+        );
+        assert_eq!(
+            server_url_of(&server("a", None, &[])).as_deref(),
+            Some("http://a-host.local:8080")
+        );
+        let mut nameless = server("a", None, &[]);
+        nameless.hostname = String::new();
+        assert_eq!(server_url_of(&nameless), None);
+    }
+
+    #[test]
+    fn txt_records_give_the_scheme_and_the_path() {
+        let address = Some([10, 0, 0, 9]);
+        assert_eq!(
+            server_url_of(&server("a", address, &[("Scheme", "https")])).as_deref(),
+            Some("https://10.0.0.9:8080")
+        );
+        assert_eq!(
+            server_url_of(&server("a", address, &[("scheme", "ftp")])),
+            None
+        );
+        assert_eq!(
+            server_url_of(&server("a", address, &[("path", "/elisys/")])).as_deref(),
+            Some("http://10.0.0.9:8080/elisys")
+        );
+        assert_eq!(
+            server_url_of(&server("a", address, &[("path", "/")])).as_deref(),
+            Some("http://10.0.0.9:8080")
+        );
+        assert_eq!(
+            server_url_of(&server("a", address, &[("path", "/a b")])),
+            None
+        );
+    }
+
+    #[test]
+    fn first_instance_by_name_is_chosen_unless_the_cached_one_is_announced() {
+        let services = [
+            server("kitchen", Some([10, 0, 0, 3]), &[]),
+            server("broken", Some([10, 0, 0, 1]), &[("scheme", "gopher")]),
+            server("attic", Some([10, 0, 0, 2]), &[]),
+        ];
+        assert_eq!(
+            select_server_url(&services, None).as_deref(),
+            Some("http://10.0.0.2:8080")
+        );
+        assert_eq!(
+            select_server_url(&services, Some("http://10.0.0.3:8080")).as_deref(),
+            Some("http://10.0.0.3:8080")
+        );
+        assert_eq!(
+            select_server_url(&services, Some("http://10.0.0.7:8080")).as_deref(),
+            Some("http://10.0.0.2:8080")
+        );
+        assert_eq!(select_server_url(&services[1..2], None), None);
+        assert_eq!(select_server_url(&[], Some("http://10.0.0.7:8080")), None);
+    }
+
+    #[test]
+    fn discovered_server_is_kept() {
+        let mut storage = MemoryStorage::new();
+        assert_eq!(load_discovered_server_url(&mut storage), None);
+        save_discovered_server_url(&mut storage, "http://10.0.0.2:8080");
+        assert_eq!(
+            load_discovered_server_url(&mut storage).as_deref(),
+            Some("http://10.0.0.2:8080")
+        );
+    }
+}
@@ -0,0 +1,75 @@
+// quietest intensity, below it a buzzer may not sound at all
+const MIN_INTENSITY_PERCENT: u8 = 1;
+// longest silence added after a pattern cycle, so the snooze button is read
+const MAX_SILENCE_MILLISECONDS: u32 = 2000;
+
+/// Ramp from `start_percent` to full intensity over `ramp_ms`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Escalation {
+    pub start_percent: u8,
+    pub ramp_ms: u64,
+}
+
+/// Intensity of the alarm `elapsed_ms` after it started ringing, from 1 to
+/// 100; always 100 without escalation.
+pub fn intensity_percent(escalation: Option<&Escalation>, elapsed_ms: u64) -> u8 {
+    let escalation = match escalation {
+        Some(escalation) if elapsed_ms < escalation.ramp_ms => escalation,
+        _ => return 100,
+    };
+    let start = escalation.start_percent.clamp(MIN_INTENSITY_PERCENT, 100) as u64;
+    (start + (100 - start) * elapsed_ms / escalation.ramp_ms) as u8
+}
+
+/// Silence after a pattern cycle of `cycle_ms`, so that at low intensity the
+/// beeps are sparse: none at 100%, nine cycles at 10%.
+pub fn silence_after_cycle_ms(intensity_percent: u8, cycle_ms: u32) -> u32 {
+    let intensity = intensity_percent.clamp(MIN_INTENSITY_PERCENT, 100) as u32;
+    (cycle_ms * (100 - intensity) / intensity).min(MAX_SILENCE_MILLISECONDS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ESCALATION: Escalation = Escalation {
+        start_percent: 20,
+        ramp_ms: 60_000,
+    };
+
+    #[test]
+    fn intensity_ramps_up_to_full() {
+        assert_eq!(intensity_percent(Some(&ESCALATION), 0), 20);
+        assert_eq!(intensity_percent(Some(&ESCALATION), 30_000), 60);
+        assert_eq!(intensity_percent(Some(&ESCALATION), 59_999), 99);
+        assert_eq!(intensity_percent(Some(&ESCALATION), 60_000), 100);
+        assert_eq!(intensity_percent(Some(&ESCALATION), 600_000), 100);
+    }
+
+    #[test]
+    fn no_escalation_or_no_ramp_is_full_intensity() {
+        assert_eq!(intensity_percent(None, 0), 100);
+        let instant = Escalation {
+            start_percent: 20,
+            ramp_ms: 0,
+        };
+        assert_eq!(intensity_percent(Some(&instant), 0), 100);
+    }
+
+    #[test]
+    fn start_is_never_silent() {
+        let silent = Escalation {
+            start_percent: 0,
+            ramp_ms: 60_000,
+        };
+        assert_eq!(intensity_percent(Some(&silent), 0), MIN_INTENSITY_PERCENT);
+    }
+
+    #[test]
+    fn silence_shrinks_as_intensity_grows() {
+        assert_eq!(silence_after_cycle_ms(100, 200), 0);
+        assert_eq!(silence_after_cycle_ms(50, 200), 200);
+        assert_eq!(silence_after_cycle_ms(10, 200), 1800);
+        assert_eq!(silence_after_cycle_ms(0, 200), MAX_SILENCE_MILLISECONDS);
+    }
+}
@@ -0,0 +1,144 @@
+use chrono::{Datelike, Duration, NaiveDate};
+use log::error;
+
+/// One entry of the holiday list of the configuration.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HolidayRule {
+    /// `2024-12-24`: that date only
+    Date(NaiveDate),
+    /// `12-25`: every year
+    Yearly { month: u32, day: u32 },
+    /// `easter`, `easter+1`, `easter-2`: days from Easter Sunday
+    Easter { days: i64 },
+}
+
+impl HolidayRule {
+    pub fn matches(&self, date: NaiveDate) -> bool {
+        match self {
+            HolidayRule::Date(holiday) => *holiday == date,
+            HolidayRule::Yearly { month, day } => date.month() == *month && date.day() == *day,
+            HolidayRule::Easter { days } => easter_sunday(date.year())
+                .is_some_and(|easter| easter + Duration::days(*days) == date),
+        }
+    }
+}
+
+fn parse_holiday_rule(rule: &str) -> Option<HolidayRule> {
+    let rule = rule.trim();
+    if let Some(days) = rule.strip_prefix("easter") {
+        let days = match days {
+            "" => 0,
+            days if days.starts_with('+') || days.starts_with('-') => days.parse().ok()?,
+            _ => return None,
+        };
+        return Some(HolidayRule::Easter { days });
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(rule, "%Y-%m-%d") {
+        return Some(HolidayRule::Date(date));
+    }
+    let (month, day) = rule.split_once('-')?;
+    let (month, day) = (month.parse().ok()?, day.parse().ok()?);
+    // 2000 is a leap year, so 02-29 is accepted
+    NaiveDate::from_ymd_opt(2000, month, day)?;
+    Some(HolidayRule::Yearly { month, day })
+}
+
+/// Parses the holiday list, leaving out (and logging) the invalid entries.
+pub fn parse_holiday_rules(rules: &[String]) -> Vec<HolidayRule> {
+    rules
+        .iter()
+        .filter_map(|rule| {
+            let parsed = parse_holiday_rule(rule);
+            if parsed.is_none() {
+                error!("invalid holiday: {}", rule);
+            }
+            parsed
+        })
+        .collect()
+}
+
+pub fn is_holiday(date: NaiveDate, rules: &[HolidayRule]) -> bool {
+    rules.iter().any(|rule| rule.matches(date))
+}
+
+/// Gregorian Easter Sunday (anonymous Gregorian algorithm).
+fn easter_sunday(year: i32) -> Option<NaiveDate> {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn easter_sunday_of_known_years() {
+        for easter in [
+            "1961-04-02",
+            "2000-04-23",
+            "2008-03-23",
+            "2011-04-24",
+            "2024-03-31",
+            "2025-04-20",
+            "2038-04-25",
+        ] {
+            let easter = date(easter);
+            assert_eq!(easter_sunday(easter.year()), Some(easter));
+        }
+    }
+
+    #[test]
+    fn rules_are_parsed_and_matched() {
+        let rules = parse_holiday_rules(&[
+            "2024-12-24".to_owned(),
+            " 12-25 ".to_owned(),
+            "easter+1".to_owned(),
+            "easter-2".to_owned(),
+        ]);
+        assert_eq!(
+            rules,
+            vec![
+                HolidayRule::Date(date("2024-12-24")),
+                HolidayRule::Yearly { month: 12, day: 25 },
+                HolidayRule::Easter { days: 1 },
+                HolidayRule::Easter { days: -2 },
+            ]
+        );
+        assert!(is_holiday(date("2024-12-24"), &rules));
+        assert!(!is_holiday(date("2025-12-24"), &rules));
+        assert!(is_holiday(date("2031-12-25"), &rules));
+        // Easter Monday and Good Friday of 2024
+        assert!(is_holiday(date("2024-04-01"), &rules));
+        assert!(is_holiday(date("2024-03-29"), &rules));
+        assert!(!is_holiday(date("2024-03-31"), &rules));
+    }
+
+    #[test]
+    fn invalid_rules_are_left_out() {
+        let rules = parse_holiday_rules(&[
+            "02-29".to_owned(),
+            "02-30".to_owned(),
+            "easter1".to_owned(),
+            "christmas".to_owned(),
+            "2024-13-01".to_owned(),
+        ]);
+        assert_eq!(rules, vec![HolidayRule::Yearly { month: 2, day: 29 }]);
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{
+    dto::{config_cron_list_response::CronListResponse, local_alarms::LocalAlarms},
+    platform::storage::Storage,
+    ConfigurationResponse,
+};
+use log::error;
+
+const LOCAL_ALARMS_KEY: &str = "local_alarms";
+
+/// Identifies an alarm of the cron list in the Home Assistant entities and
+/// in the local API: its `id`, or its position when the server does not
+/// send one. Only the characters allowed in an MQTT discovery object id are
+/// kept.
+pub fn alarm_key(index: usize, cron: &CronListResponse) -> String {
+    match &cron.id {
+        Some(id) => id
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect(),
+        None => format!("alarm{}", index),
+    }
+}
+
+pub fn load_local_alarms(storage: &mut dyn Storage) -> LocalAlarms {
+    match storage.get(LOCAL_ALARMS_KEY) {
+        Ok(Some(value)) => serde_json::from_str(&value).unwrap_or_else(|e| {
+            error!("invalid saved local alarms: {}", e);
+            LocalAlarms::default()
+        }),
+        Ok(None) => LocalAlarms::default(),
+        Err(e) => {
+            error!("unable to read the local alarms: {:?}", e);
+            LocalAlarms::default()
+        }
+    }
+}
+
+pub fn save_local_alarms(storage: &mut dyn Storage, local_alarms: &LocalAlarms) {
+    let saved = serde_json::to_string(local_alarms)
+        .map_err(anyhow::Error::from)
+        .and_then(|value| storage.set(LOCAL_ALARMS_KEY, &value));
+    if let Err(e) = saved {
+        error!("unable to save the local alarms: {:?}", e);
+    }
+}
+
+/// Whether the alarm is enabled by the configuration and by its own
+/// switch, whatever the switch of all the alarms.
+pub fn is_alarm_switched_on(
+    index: usize,
+    cron: &CronListResponse,
+    local_alarms: &LocalAlarms,
+) -> bool {
+    cron.enabled
+        && !local_alarms
+            .disabled_alarms
+            .contains(&alarm_key(index, cron))
+}
+
+/// The configuration with the alarms added on the device, after the ones of
+/// the server.
+pub fn with_added_alarms(
+    configuration: &ConfigurationResponse,
+    local_alarms: &LocalAlarms,
+) -> ConfigurationResponse {
+    let mut configuration = configuration.clone();
+    configuration
+        .cron_list
+        .extend(local_alarms.added.iter().cloned());
+    configuration
+}
+
+/// The configuration the next alarm is calculated from: the alarms added on
+/// the device are included and the ones switched off are disabled.
+pub fn apply_local_alarms(
+    configuration: &ConfigurationResponse,
+    local_alarms: &LocalAlarms,
+) -> ConfigurationResponse {
+    let mut configuration = with_added_alarms(configuration, local_alarms);
+    for (index, cron) in configuration.cron_list.iter_mut().enumerate() {
+        cron.enabled =
+            local_alarms.alarms_enabled && is_alarm_switched_on(index, cron, local_alarms);
+    }
+    configuration
+}
@@ -0,0 +1,256 @@
+use std::fmt;
+
+// RTTTL defaults when the `d`, `o` and `b` settings are missing
+const DEFAULT_DURATION: u32 = 4;
+const DEFAULT_OCTAVE: u32 = 6;
+const DEFAULT_BEATS_PER_MINUTE: u32 = 63;
+// silence before the melody starts again
+const REPEAT_PAUSE_MILLISECONDS: u32 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Note {
+    /// 0 for a pause
+    pub frequency_hz: u32,
+    pub duration_ms: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Melody {
+    pub name: String,
+    pub notes: Vec<Note>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MelodyError {
+    MissingSection,
+    InvalidSetting(String),
+    InvalidNote(String),
+    Empty,
+}
+
+impl fmt::Display for MelodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MelodyError::MissingSection => write!(f, "expected name:settings:notes"),
+            MelodyError::InvalidSetting(setting) => write!(f, "invalid setting {}", setting),
+            MelodyError::InvalidNote(note) => write!(f, "invalid note {}", note),
+            MelodyError::Empty => write!(f, "no notes"),
+        }
+    }
+}
+
+impl std::error::Error for MelodyError {}
+
+impl Melody {
+    /// Note due `elapsed_ms` after the melody started, with the milliseconds
+    /// left before the next one. The melody repeats after a short pause.
+    pub fn note_at(&self, elapsed_ms: u64) -> (u32, u32) {
+        let total_ms: u64 = self
+            .notes
+            .iter()
+            .map(|note| note.duration_ms as u64)
+            .sum::<u64>()
+            + REPEAT_PAUSE_MILLISECONDS as u64;
+        let mut position = elapsed_ms % total_ms;
+        for note in self.notes.iter() {
+            if position < note.duration_ms as u64 {
+                return (
+                    note.frequency_hz,
+                    (note.duration_ms as u64 - position) as u32,
+                );
+            }
+            position -= note.duration_ms as u64;
+        }
+        (0, (REPEAT_PAUSE_MILLISECONDS as u64 - position) as u32)
+    }
+}
+
+/// Parses a melody in RTTTL, e.g. `beep:d=8,o=5,b=120:c,e,g,p,c6`.
+pub fn parse_rtttl(text: &str) -> Result<Melody, MelodyError> {
+    let mut sections = text.splitn(3, ':');
+    let (name, settings, notes) = match (sections.next(), sections.next(), sections.next()) {
+        (Some(name), Some(settings), Some(notes)) => (name, settings, notes),
+        _ => return Err(MelodyError::MissingSection),
+    };
+
+    let mut duration = DEFAULT_DURATION;
+    let mut octave = DEFAULT_OCTAVE;
+    let mut beats_per_minute = DEFAULT_BEATS_PER_MINUTE;
+    for setting in settings.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let invalid = || MelodyError::InvalidSetting(setting.to_owned());
+        let (key, value) = setting.split_once('=').ok_or_else(invalid)?;
+        let value: u32 = value.trim().parse().map_err(|_| invalid())?;
+        match key.trim() {
+            "d" if is_valid_duration(value) => duration = value,
+            "o" if (3..=8).contains(&value) => octave = value,
+            "b" if value > 0 => beats_per_minute = value,
+            _ => return Err(invalid()),
+        }
+    }
+
+    let whole_note_ms = 4 * 60_000 / beats_per_minute;
+    let notes = notes
+        .split(',')
+        .map(str::trim)
+        .filter(|note| !note.is_empty())
+        .map(|note| parse_note(note, duration, octave, whole_note_ms))
+        .collect::<Result<Vec<Note>, MelodyError>>()?;
+    if notes.is_empty() {
+        return Err(MelodyError::Empty);
+    }
+    Ok(Melody {
+        name: name.trim().to_owned(),
+        notes,
+    })
+}
+
+/// `[duration]note[#][.][octave][.]`, e.g. `8c#.6` or `4p`
+fn parse_note(
+    text: &str,
+    default_duration: u32,
+    default_octave: u32,
+    whole_note_ms: u32,
+) -> Result<Note, MelodyError> {
+    let invalid = || MelodyError::InvalidNote(text.to_owned());
+    let lowercase = text.to_ascii_lowercase();
+    let mut rest = lowercase.as_str();
+
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let duration = match digits {
+        0 => default_duration,
+        _ => rest[..digits].parse().map_err(|_| invalid())?,
+    };
+    if !is_valid_duration(duration) {
+        return Err(invalid());
+    }
+    rest = &rest[digits..];
+
+    let mut semitone: Option<i32> = match rest.chars().next() {
+        Some('c') => Some(0),
+        Some('d') => Some(2),
+        Some('e') => Some(4),
+        Some('f') => Some(5),
+        Some('g') => Some(7),
+        Some('a') => Some(9),
+        Some('b') | Some('h') => Some(11),
+        Some('p') => None,
+        _ => return Err(invalid()),
+    };
+    rest = &rest[1..];
+    if let Some(sharp) = rest.strip_prefix('#') {
+        semitone = semitone.map(|semitone| semitone + 1);
+        rest = sharp;
+    }
+
+    let mut dotted = false;
+    if let Some(dot) = rest.strip_prefix('.') {
+        dotted = true;
+        rest = dot;
+    }
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let octave = match digits {
+        0 => default_octave,
+        _ => rest[..digits].parse().map_err(|_| invalid())?,
+    };
+    rest = &rest[digits..];
+    if let Some(dot) = rest.strip_prefix('.') {
+        dotted = true;
+        rest = dot;
+    }
+    if !rest.is_empty() || !(3..=8).contains(&octave) {
+        return Err(invalid());
+    }
+
+    let mut duration_ms = whole_note_ms / duration;
+    if dotted {
+        duration_ms += duration_ms / 2;
+    }
+    Ok(Note {
+        frequency_hz: semitone.map_or(0, |semitone| frequency_hz(semitone, octave)),
+        duration_ms,
+    })
+}
+
+fn is_valid_duration(duration: u32) -> bool {
+    matches!(duration, 1 | 2 | 4 | 8 | 16 | 32)
+}
+
+/// Equal temperament, A4 = 440 Hz.
+fn frequency_hz(semitone: i32, octave: u32) -> u32 {
+    let from_a4 = semitone - 9 + (octave as i32 - 4) * 12;
+    (440.0 * 2f32.powf(from_a4 as f32 / 12.0)).round() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(frequency_hz: u32, duration_ms: u32) -> Note {
+        Note {
+            frequency_hz,
+            duration_ms,
+        }
+    }
+
+    #[test]
+    fn notes_use_the_default_duration_and_octave() {
+        let melody = parse_rtttl("beep:d=8,o=5,b=120:c,e,g,p,c6").unwrap();
+        assert_eq!(melody.name, "beep");
+        assert_eq!(
+            melody.notes,
+            vec![
+                note(523, 250),
+                note(659, 250),
+                note(784, 250),
+                note(0, 250),
+                note(1047, 250)
+            ]
+        );
+    }
+
+    #[test]
+    fn sharps_dots_and_missing_settings() {
+        let melody = parse_rtttl("tune::4a#,8A.4,2p.,16h7").unwrap();
+        // 63 beats per minute: a whole note lasts 3809 ms
+        assert_eq!(
+            melody.notes,
+            vec![
+                note(1865, 952),
+                note(440, 714),
+                note(0, 2856),
+                note(3951, 238)
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_melodies_are_rejected() {
+        assert_eq!(parse_rtttl("beep:c,e,g"), Err(MelodyError::MissingSection));
+        assert_eq!(
+            parse_rtttl("beep:d=3:c"),
+            Err(MelodyError::InvalidSetting("d=3".to_owned()))
+        );
+        assert_eq!(
+            parse_rtttl("beep:o=5:c,x,e"),
+            Err(MelodyError::InvalidNote("x".to_owned()))
+        );
+        assert_eq!(
+            parse_rtttl("beep::c9"),
+            Err(MelodyError::InvalidNote("c9".to_owned()))
+        );
+        assert_eq!(parse_rtttl("beep:b=100: , "), Err(MelodyError::Empty));
+    }
+
+    #[test]
+    fn melody_repeats_after_a_pause() {
+        let melody = parse_rtttl("beep:d=4,b=60:c,e").unwrap();
+        assert_eq!(melody.note_at(0), (1047, 1000));
+        assert_eq!(melody.note_at(1400), (1319, 600));
+        assert_eq!(melody.note_at(2500), (0, 500));
+        assert_eq!(melody.note_at(3000), (1047, 1000));
+    }
+}
@@ -1,3 +1,19 @@
+pub mod alarm_helper;
 pub mod configuration_helper;
 pub mod date_helper;
+pub mod device_auth_helper;
+pub mod discovery_helper;
+pub mod escalation_helper;
+pub mod holiday_helper;
+pub mod local_alarm_helper;
+pub mod melody_helper;
 pub mod orchestrator_helper;
+pub mod outbound_queue_helper;
+pub mod provisioning_helper;
+pub mod response_body_helper;
+pub mod signature_helper;
+pub mod snooze_helper;
+pub mod static_ip_helper;
+pub mod timezone_helper;
+pub mod tls_helper;
+pub mod wifi_network_helper;
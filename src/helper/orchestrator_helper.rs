@@ -1,33 +1,99 @@
-use chrono::{DateTime, FixedOffset};
-use esp_idf_svc::wifi::EspWifi;
+use chrono::{DateTime, Duration, FixedOffset, Utc};
 
+use super::alarm_helper::{calculate_next_alarm, ScheduledAlarm};
 use super::date_helper::{
-    calculate_next_scheduled_time, from_str_to_date_time_after, is_same_sec, is_same_time,
-    is_same_time_sec,
+    from_str_to_date_time_after, is_same_sec, is_same_time, is_same_time_sec,
+};
+use super::local_alarm_helper::{alarm_key, is_alarm_switched_on, save_local_alarms};
+use super::outbound_queue_helper::OutboundQueue;
+use super::snooze_helper::{SnoozeCommand, SnoozeState, SnoozeStateMachine};
+use super::timezone_helper::UserTimeZone;
+use crate::config::config::{
+    CHECK_INTERVAL_CONFIGURATION_CRON, DEFAULT_CONFIGURATION_URI, DEFAULT_SERVER_URL,
+    DEFAULT_TRANSPORT, ENABLE_SERVER_DISCOVERY, HTTP_RETRY_COUNT, HTTP_RETRY_DELAY_MILLISECONDS,
+    MQTT_RECONNECT_DELAY_MILLISECONDS, SERVER_DISCOVERY_AFTER_FAILURES,
+    SERVER_DISCOVERY_RETRY_MINUTES,
+};
+use crate::dto::alarm_consumed_request::AlarmConsumedRequest;
+use crate::dto::alarm_event_request::{AlarmEventRequest, AlarmOutcome};
+use crate::dto::config_response::Transport;
+use crate::dto::diagnostic_request::DiagnosticRequest;
+use crate::dto::local_alarms::LocalAlarms;
+use crate::dto::outbound_message::OutboundMessage;
+use crate::dto::request_i_am_alive::RequestIAmAlive;
+use crate::helper::configuration_helper::{
+    get_default_configuration, get_saved_or_default_configuration, is_same_configuration,
+    load_saved_configuration, save_configuration,
+};
+use crate::helper::device_auth_helper::{
+    forget_device_credentials, load_device_credentials, save_device_credentials, RequestSigner,
+};
+use crate::platform::device::Device;
+use crate::service::api_service::{route, ApiAction, ApiContext};
+use crate::service::client_service::{
+    get_configuration, register_device, send_outbound_message, ClientError, ConfigurationUpdate,
 };
-use crate::config::config::{CHECK_INTERVAL_CONFIGURATION_CRON, DEFAULT_CONFIGURATION_URI};
-use crate::helper::configuration_helper::get_default_configuration;
-use crate::service::client_service::{get_configuration, register_device, send_i_am_alive};
 use crate::service::clock_service::synchronize_clock_insistently_and_connect_wifi_if_necessary;
+use crate::service::discovery_service::locate_server;
+use crate::service::home_assistant_service::{
+    publish_discovery, publish_state, HomeAssistantState,
+};
+use crate::service::mqtt_service::{
+    connect_to_broker, publish_outbound_message, receive_mqtt_messages, start_mqtt_session,
+    MqttCommand, MqttInbound,
+};
+use crate::service::peripheral_service::buzz;
 use crate::service::wifi_service::reconnect_to_wifi_insistently_if_needed;
 use crate::ConfigurationResponse;
 use log::{error, info, warn};
 
+/// What the main loop keeps from one iteration to the next.
+pub struct LoopState {
+    pub mac_address: String,
+    /// entered in the setup page, which restarts the device when saved
+    pub public_key: Option<String>,
+    pub configuration: ConfigurationResponse,
+    pub user_timezone: UserTimeZone,
+    /// alarms added or switched off on the device
+    pub local_alarms: LocalAlarms,
+    pub alarm: Option<ScheduledAlarm>,
+    /// the alarm that opened the last window, the one a snoozed alarm rings with
+    pub ringing_alarm: Option<ScheduledAlarm>,
+    pub is_calculated_alarm_next_date_time: bool,
+    /// one-shot alarms of the configuration already reported as consumed
+    pub consumed_one_shots: Vec<(DateTime<FixedOffset>, String)>,
+    pub snooze: SnoozeStateMachine,
+    /// heartbeats, alarm events and diagnostics waiting for the server
+    pub outbound_queue: OutboundQueue,
+    pub mqtt_session: MqttSession,
+    pub ntp_sync_time: DateTime<FixedOffset>,
+    pub ntp_synchronized: bool,
+    /// next download of the configuration
+    pub cron_time: DateTime<FixedOffset>,
+    pub is_last_config_sync: bool,
+    pub i_am_alive_cron_time: u32,
+    pub i_am_alive_sent: bool,
+}
+
 pub fn send_i_am_alive_if_necessary(
     i_am_alive_sent: &mut bool,
     second_number: u32,
     now: DateTime<FixedOffset>,
-    mac_address: &String,
+    mac_address: &str,
     configuration: &mut ConfigurationResponse,
-    wifi_driver: &mut EspWifi<'static>,
+    outbound_queue: &mut OutboundQueue,
 ) {
     if is_same_sec(second_number, now) && !*i_am_alive_sent {
-        reconnect_to_wifi_insistently_if_needed(wifi_driver, true);
-        let res = send_i_am_alive(mac_address, &configuration.i_am_alive_endpoint);
-        if res.is_err() {
-            error!("send i am alive ack failed");
-        }
-        warn!("i am alive sent :)");
+        let request = RequestIAmAlive::new(
+            mac_address.to_owned(),
+            now.with_timezone(&Utc),
+            outbound_queue.status(),
+        );
+        outbound_queue.push(OutboundMessage::Heartbeat {
+            endpoint: configuration.i_am_alive_endpoint.clone(),
+            request,
+        });
+        warn!("i am alive queued :)");
         *i_am_alive_sent = true;
     }
     if !is_same_sec(second_number, now) && *i_am_alive_sent {
@@ -39,76 +105,1042 @@ pub fn sync_system_clock_if_necessary(
     ntp_synchronized: &mut bool,
     ntp_sync_time: DateTime<FixedOffset>,
     now: DateTime<FixedOffset>,
-    wifi_driver: &mut EspWifi<'static>,
+    device: &mut Device,
 ) {
     if !*ntp_synchronized && is_same_time(ntp_sync_time, now) {
-        synchronize_clock_insistently_and_connect_wifi_if_necessary(wifi_driver, true);
+        synchronize_clock_insistently_and_connect_wifi_if_necessary(device, true);
         *ntp_synchronized = true;
     } else if *ntp_synchronized && !is_same_time(ntp_sync_time, now) {
         *ntp_synchronized = false;
     }
 }
 
-pub fn try_register_device(mac_address: &String) {
-    let register_device_result = register_device(mac_address);
-    if register_device_result.is_err() {
-        error!(
+/// Retries timeouts and 5xx responses a few times, and registers the device
+/// again when the server answers 404 because it does not know it, or 401
+/// because it refused its credentials.
+fn request_with_recovery<T, F>(
+    device: &mut Device,
+    mac_address: &str,
+    mut request: F,
+) -> Result<T, ClientError>
+where
+    F: FnMut(&mut Device) -> Result<T, ClientError>,
+{
+    let mut retries = 0;
+    let mut registered_again = false;
+    loop {
+        match request(device) {
+            Err(e) if e.is_not_found() && !registered_again => {
+                warn!("the server does not know this device, registering it again");
+                try_register_device(device, mac_address);
+                registered_again = true;
+            }
+            Err(e) if e.is_unauthorized() && !registered_again => {
+                warn!("the server refused the device credentials, registering it again");
+                forget_device_credentials(&mut *device.storage);
+                try_register_device(device, mac_address);
+                registered_again = true;
+            }
+            Err(e) if e.is_transient() && retries < HTTP_RETRY_COUNT => {
+                retries += 1;
+                warn!("{}, retrying ({}/{})", e, retries, HTTP_RETRY_COUNT);
+                device.clock.delay_ms(HTTP_RETRY_DELAY_MILLISECONDS);
+            }
+            result => return result,
+        }
+    }
+}
+
+pub fn try_register_device(device: &mut Device, mac_address: &str) {
+    let register_device_result = register_device(&mut *device.http, mac_address);
+    match register_device_result {
+        Err(_) => error!(
             "Failed to register the device: {:?}",
             register_device_result
-        );
-    } else {
-        info!("Device registered successfully!");
+        ),
+        Ok(Some(credentials)) => {
+            info!("Device registered successfully, with credentials!");
+            save_device_credentials(&mut *device.storage, &credentials);
+        }
+        Ok(None) => info!("Device registered successfully!"),
     }
 }
 
+/// Signs the requests with the credentials of the last registration.
+fn request_signer(device: &mut Device) -> RequestSigner {
+    let server_url = device
+        .server_url
+        .borrow()
+        .clone()
+        .unwrap_or_else(|| DEFAULT_SERVER_URL.to_owned());
+    RequestSigner::new(
+        load_device_credentials(&mut *device.storage),
+        &server_url,
+        device.clock.now(),
+    )
+}
+
 pub fn calculate_alarm_next_date_time(
     is_calculated_alarm_next_date_time: &mut bool,
-    alarm: &mut DateTime<FixedOffset>,
+    alarm: &mut Option<ScheduledAlarm>,
     configuration: &ConfigurationResponse,
-    offset: FixedOffset,
+    timezone: &UserTimeZone,
+    now: DateTime<FixedOffset>,
 ) {
     if !*is_calculated_alarm_next_date_time {
-        *alarm = calculate_next_scheduled_time(&configuration.cron_list, &offset);
+        *alarm = calculate_next_alarm(&now.with_timezone(&Utc), configuration, timezone);
         *is_calculated_alarm_next_date_time = true;
     }
 }
 
-pub fn retrieve_config_if_necessary(
-    downloaded: &mut bool,
-    cron_time: &mut DateTime<FixedOffset>,
-    offset: FixedOffset,
+pub fn queue_alarm_event(
+    outbound_queue: &mut OutboundQueue,
+    mac_address: &str,
+    alarm: &ScheduledAlarm,
     now: DateTime<FixedOffset>,
-    mac_address: &String,
-    configuration: &mut ConfigurationResponse,
-    wifi_driver: &mut EspWifi<'static>,
+    outcome: AlarmOutcome,
+) {
+    info!("alarm {} {:?}", alarm.description, outcome);
+    outbound_queue.push(OutboundMessage::AlarmEvent(AlarmEventRequest::new(
+        mac_address.to_owned(),
+        alarm.id.clone(),
+        alarm.description.clone(),
+        alarm.date_time,
+        now,
+        outcome,
+    )));
+}
+
+pub fn queue_diagnostic(
+    outbound_queue: &mut OutboundQueue,
+    mac_address: &str,
+    now: DateTime<Utc>,
+    message: String,
+) {
+    outbound_queue.push(OutboundMessage::Diagnostic(DiagnosticRequest::new(
+        mac_address.to_owned(),
+        now,
+        message,
+    )));
+}
+
+/// Returns whether the window of `alarm` is open. When a new window opens
+/// the alarm becomes `ringing_alarm` and a fired event is queued.
+pub fn track_ringing_alarm(
+    alarm: &Option<ScheduledAlarm>,
+    now: DateTime<FixedOffset>,
+    ringing_alarm: &mut Option<ScheduledAlarm>,
+    outbound_queue: &mut OutboundQueue,
+    mac_address: &str,
+) -> bool {
+    let alarm = match alarm {
+        Some(alarm) if alarm.is_time_to_buzz(now) => alarm,
+        _ => return false,
+    };
+    let is_new_window = ringing_alarm.as_ref().map_or(true, |ringing_alarm| {
+        ringing_alarm.date_time != alarm.date_time
+    });
+    if is_new_window {
+        queue_alarm_event(outbound_queue, mac_address, alarm, now, AlarmOutcome::Fired);
+        *ringing_alarm = Some(alarm.clone());
+    }
+    true
+}
+
+/// Reports to the server the one-shot alarms of `configuration` whose time
+/// has passed, so that it removes them: the ones that rang, and the ones
+/// missed while the device was off or suppressed by a skip date or a pause.
+/// `reported` keeps the ones already queued while the server still sends
+/// them.
+pub fn report_consumed_one_shot_alarms(
+    configuration: &ConfigurationResponse,
+    now: DateTime<FixedOffset>,
+    reported: &mut Vec<(DateTime<FixedOffset>, String)>,
+    outbound_queue: &mut OutboundQueue,
+    mac_address: &str,
+) {
+    let consumed: Vec<(DateTime<FixedOffset>, String)> = configuration
+        .cron_list
+        .iter()
+        .filter_map(|cron| {
+            cron.at
+                .filter(|at| *at <= now)
+                .map(|at| (at, cron.description.clone()))
+        })
+        .collect();
+    reported.retain(|one_shot| consumed.contains(one_shot));
+    for (at, description) in consumed {
+        if reported.contains(&(at, description.clone())) {
+            continue;
+        }
+        info!("one-shot alarm {} of {} consumed", description, at);
+        outbound_queue.push(OutboundMessage::AlarmConsumed(AlarmConsumedRequest::new(
+            mac_address.to_owned(),
+            at,
+            description.clone(),
+        )));
+        reported.push((at, description));
+    }
+}
+
+/// An alarm whose window closed before the loop could ring it is reported
+/// as missed, and the next one is calculated.
+pub fn track_missed_alarm(
+    alarm: &Option<ScheduledAlarm>,
+    now: DateTime<FixedOffset>,
+    ringing_alarm: &Option<ScheduledAlarm>,
     is_calculated_alarm_next_date_time: &mut bool,
+    outbound_queue: &mut OutboundQueue,
+    mac_address: &str,
+) {
+    let alarm = match alarm {
+        Some(alarm) if *is_calculated_alarm_next_date_time && alarm.is_over(now) => alarm,
+        _ => return,
+    };
+    let rang = ringing_alarm
+        .as_ref()
+        .is_some_and(|ringing_alarm| ringing_alarm.date_time == alarm.date_time);
+    if !rang {
+        warn!("alarm of {} missed", alarm.date_time);
+        queue_alarm_event(
+            outbound_queue,
+            mac_address,
+            alarm,
+            now,
+            AlarmOutcome::Missed,
+        );
+    }
+    *is_calculated_alarm_next_date_time = false;
+}
+
+/// Outcome to report when the snooze button changed the state of the alarm.
+pub fn snooze_outcome(previous: SnoozeState, current: SnoozeState) -> Option<AlarmOutcome> {
+    match (previous, current) {
+        (SnoozeState::Ringing { .. }, SnoozeState::Snoozed { .. }) => Some(AlarmOutcome::Snoozed),
+        (SnoozeState::Ringing { .. } | SnoozeState::Snoozed { .. }, SnoozeState::Dismissed) => {
+            Some(AlarmOutcome::Dismissed)
+        }
+        _ => None,
+    }
+}
+
+/// Sends the queued messages in order, stopping at the first failure: the
+/// queue waits for its backoff before trying again. A message the server
+/// refuses is dropped, it would block the queue forever.
+pub fn deliver_outbound_messages(
+    outbound_queue: &mut OutboundQueue,
+    mac_address: &str,
+    transport: Transport,
+    device: &mut Device,
+) {
+    if outbound_queue.is_due(device.clock.now()) {
+        reconnect_to_wifi_insistently_if_needed(device, true);
+    }
+    while outbound_queue.is_due(device.clock.now()) {
+        let message = outbound_queue.front().unwrap();
+        let result = match transport {
+            Transport::Http => request_with_recovery(device, mac_address, |device| {
+                let signer = request_signer(device);
+                send_outbound_message(&mut *device.http, &signer, message)
+            }),
+            Transport::Mqtt => publish_outbound_message(&mut *device.mqtt, mac_address, message),
+        };
+        match result {
+            Ok(()) => outbound_queue.pop(),
+            Err(e) if e.is_rejected() => {
+                error!("message refused by the server, dropping it: {}", e);
+                outbound_queue.pop();
+            }
+            Err(e) => {
+                error!("unable to deliver the queued messages: {}", e);
+                outbound_queue.failed(device.clock.now());
+            }
+        }
+    }
+    outbound_queue.save(&mut *device.storage);
+}
+
+/// When the unreachable server was last looked for again.
+pub struct ServerDiscovery {
+    last_attempt: Option<DateTime<Utc>>,
+}
+
+impl ServerDiscovery {
+    pub fn new() -> ServerDiscovery {
+        ServerDiscovery { last_attempt: None }
+    }
+}
+
+/// Looks for the server again with mDNS once the queued messages failed
+/// `SERVER_DISCOVERY_AFTER_FAILURES` times in a row, e.g. because it moved.
+pub fn rediscover_server_if_necessary(
+    discovery: &mut ServerDiscovery,
+    outbound_queue: &mut OutboundQueue,
+    device: &mut Device,
+) {
+    if !ENABLE_SERVER_DISCOVERY
+        || outbound_queue.status().failures < SERVER_DISCOVERY_AFTER_FAILURES
+    {
+        return;
+    }
+    let now = device.clock.now();
+    let retry = Duration::minutes(SERVER_DISCOVERY_RETRY_MINUTES as i64);
+    if discovery
+        .last_attempt
+        .is_some_and(|last_attempt| now - last_attempt < retry)
+    {
+        return;
+    }
+    discovery.last_attempt = Some(now);
+    warn!("the server is unreachable, looking for it again");
+    if locate_server(device) {
+        outbound_queue.retry_now();
+    }
+}
+
+pub struct MqttSession {
+    started: bool,
+    last_connect_attempt: Option<DateTime<Utc>>,
+}
+
+impl Default for MqttSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MqttSession {
+    pub fn new() -> MqttSession {
+        MqttSession {
+            started: false,
+            last_connect_attempt: None,
+        }
+    }
+}
+
+/// With `Transport::Mqtt` keeps the session with the broker alive, instead
+/// of polling the configuration.
+pub fn sync_mqtt_if_necessary(
+    mqtt_session: &mut MqttSession,
+    mac_address: &str,
+    device: &mut Device,
+    outbound_queue: &mut OutboundQueue,
+) {
+    let now = device.clock.now();
+    let reconnect_delay = Duration::milliseconds(MQTT_RECONNECT_DELAY_MILLISECONDS as i64);
+    if !device.mqtt.is_connected() {
+        mqtt_session.started = false;
+        let must_connect = mqtt_session
+            .last_connect_attempt
+            .map_or(true, |attempt| now - attempt >= reconnect_delay);
+        if must_connect {
+            mqtt_session.last_connect_attempt = Some(now);
+            reconnect_to_wifi_insistently_if_needed(device, true);
+            if let Err(e) = connect_to_broker(&mut *device.mqtt, mac_address) {
+                error!("unable to connect to the MQTT broker: {}", e);
+            }
+        }
+    }
+    if device.mqtt.is_connected() && !mqtt_session.started {
+        match start_mqtt_session(&mut *device.mqtt, mac_address) {
+            Ok(()) => {
+                mqtt_session.started = true;
+                outbound_queue.retry_now();
+            }
+            Err(e) => error!("unable to start the MQTT session: {}", e),
+        }
+    }
+}
+
+/// Applies the configurations pushed by the server and the commands of the
+/// Home Assistant entities; called at each iteration, even while ringing.
+pub fn handle_mqtt_messages(state: &mut LoopState, device: &mut Device) {
+    let inbound_messages = receive_mqtt_messages(
+        &mut *device.mqtt,
+        &state.mac_address,
+        state.public_key.as_deref(),
+        state.configuration.issued_at,
+    );
+    for inbound in inbound_messages {
+        match inbound {
+            MqttInbound::Configuration(new_configuration) => {
+                save_configuration(&mut *device.storage, device.clock.now(), &new_configuration);
+                if !is_same_configuration(&state.configuration, &new_configuration) {
+                    state.configuration = new_configuration;
+                    state.is_calculated_alarm_next_date_time = false;
+                }
+            }
+            MqttInbound::Command(MqttCommand::Snooze) => {
+                state.snooze.request(SnoozeCommand::Snooze)
+            }
+            MqttInbound::Command(MqttCommand::Dismiss) => {
+                state.snooze.request(SnoozeCommand::Dismiss)
+            }
+            MqttInbound::Command(MqttCommand::EnableAlarms(enabled)) => {
+                state.local_alarms.alarms_enabled = enabled;
+                save_local_alarms(&mut *device.storage, &state.local_alarms);
+                state.is_calculated_alarm_next_date_time = false;
+            }
+            MqttInbound::Command(MqttCommand::EnableAlarm(key, enabled)) => {
+                let local_alarms = &mut state.local_alarms;
+                local_alarms
+                    .disabled_alarms
+                    .retain(|disabled| *disabled != key);
+                if !enabled {
+                    local_alarms.disabled_alarms.push(key);
+                }
+                save_local_alarms(&mut *device.storage, local_alarms);
+                state.is_calculated_alarm_next_date_time = false;
+            }
+        }
+    }
+}
+
+/// Answers the requests of the local API, then applies what they asked.
+pub fn serve_api_requests(state: &mut LoopState, now: DateTime<FixedOffset>, device: &mut Device) {
+    let mut actions = Vec::new();
+    let clock = &device.clock;
+    let wifi = &mut device.wifi;
+    device.api.serve(&mut |request| {
+        let context = ApiContext {
+            mac_address: &state.mac_address,
+            now,
+            configuration: &state.configuration,
+            local_alarms: &state.local_alarms,
+            alarm: &state.alarm,
+            snooze_state: state.snooze.state(),
+            clock_synchronized_at: clock.synchronized_at(),
+            wifi_rssi: wifi.rssi(),
+            uptime_ms: clock.uptime_ms(),
+        };
+        let (response, action) = route(request, &context);
+        info!(
+            "[local api] {} {} => {}",
+            request.method, request.path, response.status
+        );
+        actions.extend(action);
+        response
+    });
+    let local_alarms = &mut state.local_alarms;
+    for action in actions {
+        match action {
+            ApiAction::AddAlarm(cron) => {
+                local_alarms.added.push(cron);
+                save_local_alarms(&mut *device.storage, local_alarms);
+                state.is_calculated_alarm_next_date_time = false;
+            }
+            ApiAction::RemoveAlarm(key) => {
+                local_alarms
+                    .added
+                    .retain(|cron| cron.id.as_deref() != Some(key.as_str()));
+                local_alarms
+                    .disabled_alarms
+                    .retain(|disabled| *disabled != key);
+                save_local_alarms(&mut *device.storage, local_alarms);
+                state.is_calculated_alarm_next_date_time = false;
+            }
+            ApiAction::TestBuzz(request) => {
+                warn!("test buzz for {} seconds", request.seconds);
+                let until = device.clock.now() + Duration::seconds(request.seconds as i64);
+                while device.clock.now() < until {
+                    buzz(device, request.pattern, &request.outputs, 100);
+                }
+            }
+            ApiAction::Dismiss => state.snooze.request(SnoozeCommand::Dismiss),
+        }
+    }
+}
+
+/// What was last published to Home Assistant, `None` until the next
+/// (re)connection to the broker.
+#[derive(Default)]
+pub struct HomeAssistantPublisher {
+    alarms: Option<Vec<(String, String)>>,
+    state: Option<HomeAssistantState>,
+}
+
+impl HomeAssistantPublisher {
+    pub fn new() -> HomeAssistantPublisher {
+        HomeAssistantPublisher::default()
+    }
+}
+
+/// Publishes the discovery payloads when the alarms change, and the state
+/// of the entities when it changes.
+pub fn publish_home_assistant_if_necessary(
+    publisher: &mut HomeAssistantPublisher,
+    mac_address: &str,
+    configuration: &ConfigurationResponse,
+    alarm: &Option<ScheduledAlarm>,
+    ringing: bool,
+    local_alarms: &LocalAlarms,
+    device: &mut Device,
+) {
+    if !device.mqtt.is_connected() {
+        *publisher = HomeAssistantPublisher::new();
+        return;
+    }
+    // the alarms added on the device come after the ones of the server
+    let cron_list = || configuration.cron_list.iter().chain(&local_alarms.added);
+    let alarms: Vec<(String, String)> = cron_list()
+        .enumerate()
+        .map(|(index, cron)| (alarm_key(index, cron), cron.description.clone()))
+        .collect();
+    if publisher.alarms.as_ref() != Some(&alarms) {
+        let removed: Vec<String> = publisher
+            .alarms
+            .iter()
+            .flatten()
+            .map(|(key, _)| key.clone())
+            .filter(|key| !alarms.iter().any(|(other, _)| other == key))
+            .collect();
+        match publish_discovery(&mut *device.mqtt, mac_address, &alarms, &removed) {
+            Ok(()) => publisher.alarms = Some(alarms),
+            Err(e) => {
+                error!("unable to publish the Home Assistant discovery: {}", e);
+                return;
+            }
+        }
+    }
+    let state = HomeAssistantState {
+        next_alarm: alarm.as_ref().map(|alarm| alarm.date_time),
+        ringing,
+        alarms_enabled: local_alarms.alarms_enabled,
+        alarms: cron_list()
+            .enumerate()
+            .map(|(index, cron)| {
+                (
+                    alarm_key(index, cron),
+                    is_alarm_switched_on(index, cron, local_alarms),
+                )
+            })
+            .collect(),
+    };
+    if publisher.state.as_ref() != Some(&state) {
+        match publish_state(
+            &mut *device.mqtt,
+            mac_address,
+            publisher.state.as_ref(),
+            &state,
+        ) {
+            Ok(()) => publisher.state = Some(state),
+            Err(e) => error!("unable to publish the Home Assistant state: {}", e),
+        }
+    }
+}
+
+/// The transport of the saved configuration, used until the server sends
+/// a new one.
+pub fn get_boot_transport(device: &mut Device) -> Transport {
+    load_saved_configuration(&mut *device.storage, device.clock.now())
+        .map_or(DEFAULT_TRANSPORT, |configuration| configuration.transport)
+}
+
+pub fn retrieve_config_if_necessary(
+    state: &mut LoopState,
+    now: DateTime<FixedOffset>,
+    device: &mut Device,
 ) {
-    let old_cron_time = *cron_time;
-    *cron_time = from_str_to_date_time_after(&now, CHECK_INTERVAL_CONFIGURATION_CRON, &offset);
+    let old_cron_time = state.cron_time;
+    state.cron_time =
+        from_str_to_date_time_after(&now, CHECK_INTERVAL_CONFIGURATION_CRON, now.offset())
+            .expect("CHECK_INTERVAL_CONFIGURATION_CRON must be a valid crontab");
 
-    if is_same_time_sec(old_cron_time, now) && !*downloaded {
-        reconnect_to_wifi_insistently_if_needed(wifi_driver, true);
-        let configuration_result = get_configuration(DEFAULT_CONFIGURATION_URI, mac_address);
+    let mac_address = state.mac_address.as_str();
+    let public_key = state.public_key.as_deref();
+    let configuration = &mut state.configuration;
+    let outbound_queue = &mut state.outbound_queue;
+    if is_same_time_sec(old_cron_time, now) && !state.is_last_config_sync {
+        reconnect_to_wifi_insistently_if_needed(device, true);
+        let configuration_result = request_with_recovery(device, mac_address, |device| {
+            let signer = request_signer(device);
+            get_configuration(
+                &mut *device.http,
+                &signer,
+                DEFAULT_CONFIGURATION_URI,
+                mac_address,
+                Some(&*configuration),
+                public_key,
+            )
+        });
         warn!("configuration requested :)");
-        *configuration = match configuration_result {
-            Err(e) => get_default_configuration(e),
-            Ok(data) => data,
+        let now = device.clock.now();
+        if configuration_result.is_ok() {
+            outbound_queue.retry_now();
+        }
+        let new_configuration = match configuration_result {
+            Err(e) => {
+                queue_diagnostic(
+                    outbound_queue,
+                    mac_address,
+                    now,
+                    format!("configuration download failed: {}", e),
+                );
+                get_saved_or_default_configuration(
+                    load_saved_configuration(&mut *device.storage, now),
+                    e.into(),
+                )
+            }
+            Ok(ConfigurationUpdate::Unchanged) => {
+                save_configuration(&mut *device.storage, now, configuration);
+                configuration.clone()
+            }
+            Ok(ConfigurationUpdate::Changed(data)) => {
+                save_configuration(&mut *device.storage, now, &data);
+                *data
+            }
         };
+        // the next alarm is computed again only when something changed
+        if !is_same_configuration(configuration, &new_configuration) {
+            *configuration = new_configuration;
+            state.is_calculated_alarm_next_date_time = false;
+        }
 
-        *downloaded = true;
-        *is_calculated_alarm_next_date_time = false;
+        state.is_last_config_sync = true;
     }
-    if !is_same_time_sec(old_cron_time, now) && *downloaded {
-        *downloaded = false;
+    if !is_same_time_sec(old_cron_time, now) && state.is_last_config_sync {
+        state.is_last_config_sync = false;
     }
 }
 
 pub fn load_remote_configuration_or_default(
-    mac_address: &String,
+    device: &mut Device,
+    mac_address: &str,
+    transport: Transport,
+    outbound_queue: &mut OutboundQueue,
+    public_key: Option<&str>,
 ) -> crate::dto::config_response::Configuration {
-    let configuration = match get_configuration(DEFAULT_CONFIGURATION_URI, mac_address) {
-        Err(e) => get_default_configuration(e),
-        Ok(data) => data,
+    let now = device.clock.now();
+    let saved_configuration = load_saved_configuration(&mut *device.storage, now);
+    if transport == Transport::Mqtt {
+        info!("[config downloader]: the configuration will come from the MQTT broker");
+        return saved_configuration.unwrap_or_else(|| {
+            get_default_configuration(anyhow::Error::msg(
+                "no configuration received from the MQTT broker yet",
+            ))
+        });
+    }
+    let configuration_result = request_with_recovery(device, mac_address, |device| {
+        let signer = request_signer(device);
+        get_configuration(
+            &mut *device.http,
+            &signer,
+            DEFAULT_CONFIGURATION_URI,
+            mac_address,
+            saved_configuration.as_ref(),
+            public_key,
+        )
+    });
+    let configuration = match configuration_result {
+        Ok(ConfigurationUpdate::Changed(data)) => *data,
+        // only possible with a saved version
+        Ok(ConfigurationUpdate::Unchanged) => match saved_configuration {
+            Some(saved_configuration) => saved_configuration,
+            None => {
+                return get_default_configuration(anyhow::Error::msg(
+                    "unexpected unchanged configuration",
+                ))
+            }
+        },
+        Err(e) => {
+            queue_diagnostic(
+                outbound_queue,
+                mac_address,
+                now,
+                format!("configuration download failed at boot: {}", e),
+            );
+            return get_saved_or_default_configuration(saved_configuration, e.into());
+        }
     };
+    save_configuration(&mut *device.storage, now, &configuration);
     configuration
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::config::config::{DIAGNOSTIC_URL, HTTP_RETRY_COUNT, REGISTER_DEVICE_URL};
+    use crate::dto::config_cron_list_response::CronListResponse;
+    use crate::helper::snooze_helper::SnoozeSettings;
+    use crate::platform::http::{HttpClient, HttpResponse, HttpTransportError};
+    use crate::platform::storage::MemoryStorage;
+    use crate::simulator::{
+        api_stub::ApiStub,
+        mdns_stub::MdnsStub,
+        mqtt_stub::MqttStub,
+        simulated_clock::SimulatedClock,
+        simulated_gpio::{SimulatedButtons, SimulatedBuzzer},
+        simulated_system::SimulatedSystem,
+        simulated_wifi::SimulatedWifi,
+    };
+
+    const MAC_ADDRESS: &str = "02:00:00:00:00:01";
+    // answered as a transport timeout instead of a status
+    const TIMEOUT: u16 = 0;
+
+    type Requests = Rc<RefCell<Vec<String>>>;
+
+    /// Answers the registrations with 200 and the other requests with the
+    /// next of `statuses`, 200 once they are used up; keeps the URLs.
+    struct FakeHttp {
+        statuses: VecDeque<u16>,
+        requests: Requests,
+    }
+
+    impl HttpClient for FakeHttp {
+        fn post(
+            &mut self,
+            url: &str,
+            _headers: &[(&str, &str)],
+            _payload: &[u8],
+        ) -> Result<HttpResponse, anyhow::Error> {
+            self.requests.borrow_mut().push(url.to_owned());
+            let status = match url {
+                REGISTER_DEVICE_URL => 200,
+                _ => self.statuses.pop_front().unwrap_or(200),
+            };
+            if status == TIMEOUT {
+                return Err(HttpTransportError::Timeout("no answer".to_owned()).into());
+            }
+            Ok(HttpResponse {
+                status,
+                headers: Vec::new(),
+                body: "{}".to_owned(),
+            })
+        }
+    }
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 4, 6, 0, 0).unwrap()
+    }
+
+    /// A simulated device whose requests to the server get `statuses`.
+    fn simulated_device(statuses: &[u16]) -> (Device, Requests) {
+        let now = Rc::new(Cell::new(start()));
+        let requests = Requests::default();
+        let timezone = UserTimeZone::Fixed(FixedOffset::east_opt(0).unwrap());
+        let rings = Rc::new(Cell::new(0));
+        let device = Device {
+            clock: Box::new(SimulatedClock::new(now.clone(), 0)),
+            wifi: Box::new(SimulatedWifi::new(MAC_ADDRESS.to_owned(), Vec::new())),
+            http: Box::new(FakeHttp {
+                statuses: statuses.iter().copied().collect(),
+                requests: requests.clone(),
+            }),
+            server_url: Rc::new(RefCell::new(None)),
+            mdns: Box::new(MdnsStub::new(Vec::new())),
+            mqtt: Box::new(MqttStub::new()),
+            buzzer1: Box::new(SimulatedBuzzer::new(
+                "buzzer1".to_owned(),
+                now.clone(),
+                timezone,
+                rings.clone(),
+            )),
+            buzzer2: Box::new(SimulatedBuzzer::new(
+                "buzzer2".to_owned(),
+                now.clone(),
+                timezone,
+                rings,
+            )),
+            buttons: Box::new(SimulatedButtons::new(now.clone(), Vec::new())),
+            storage: Box::new(MemoryStorage::new()),
+            api: Box::new(ApiStub::new("api", now.clone(), Vec::new())),
+            portal: Box::new(ApiStub::new("portal", now, Vec::new())),
+            system: Box::new(SimulatedSystem),
+        };
+        (device, requests)
+    }
+
+    fn send_diagnostic(device: &mut Device) -> Result<(), ClientError> {
+        let message = OutboundMessage::Diagnostic(DiagnosticRequest::new(
+            MAC_ADDRESS.to_owned(),
+            start(),
+            "test".to_owned(),
+        ));
+        request_with_recovery(device, MAC_ADDRESS, |device| {
+            let signer = request_signer(device);
+            send_outbound_message(&mut *device.http, &signer, &message)
+        })
+    }
+
+    fn count(requests: &Requests, url: &str) -> usize {
+        requests
+            .borrow()
+            .iter()
+            .filter(|request| *request == url)
+            .count()
+    }
+
+    #[test]
+    fn server_errors_and_timeouts_are_retried() {
+        let (mut device, requests) = simulated_device(&[500, TIMEOUT]);
+        assert!(send_diagnostic(&mut device).is_ok());
+        assert_eq!(count(&requests, DIAGNOSTIC_URL), 3);
+
+        let (mut device, requests) = simulated_device(&[503; 5]);
+        assert!(matches!(
+            send_diagnostic(&mut device),
+            Err(ClientError::HttpStatus { status: 503, .. })
+        ));
+        assert_eq!(
+            count(&requests, DIAGNOSTIC_URL),
+            HTTP_RETRY_COUNT as usize + 1
+        );
+        assert_eq!(count(&requests, REGISTER_DEVICE_URL), 0);
+    }
+
+    #[test]
+    fn too_many_requests_and_request_timeout_are_retried() {
+        let (mut device, requests) = simulated_device(&[429, 408]);
+        assert!(send_diagnostic(&mut device).is_ok());
+        assert_eq!(count(&requests, DIAGNOSTIC_URL), 3);
+    }
+
+    #[test]
+    fn client_errors_are_not_retried() {
+        for status in [400, 403, 410] {
+            let (mut device, requests) = simulated_device(&[status]);
+            assert!(send_diagnostic(&mut device).is_err());
+            assert_eq!(count(&requests, DIAGNOSTIC_URL), 1);
+            assert_eq!(count(&requests, REGISTER_DEVICE_URL), 0);
+        }
+    }
+
+    #[test]
+    fn not_found_registers_again_then_retries_once() {
+        let (mut device, requests) = simulated_device(&[404]);
+        assert!(send_diagnostic(&mut device).is_ok());
+        assert_eq!(
+            *requests.borrow(),
+            [DIAGNOSTIC_URL, REGISTER_DEVICE_URL, DIAGNOSTIC_URL]
+        );
+
+        let (mut device, requests) = simulated_device(&[404, 404]);
+        assert!(matches!(
+            send_diagnostic(&mut device),
+            Err(ClientError::HttpStatus { status: 404, .. })
+        ));
+        assert_eq!(count(&requests, DIAGNOSTIC_URL), 2);
+        assert_eq!(count(&requests, REGISTER_DEVICE_URL), 1);
+    }
+
+    fn local(date_time: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(date_time).unwrap()
+    }
+
+    fn one_shot_configuration(at: &str) -> ConfigurationResponse {
+        let mut configuration = get_default_configuration(anyhow::Error::msg("test"));
+        let mut one_shot = CronListResponse::new(String::new(), "train".to_owned());
+        one_shot.at = Some(local(at));
+        configuration.cron_list = vec![one_shot];
+        configuration
+    }
+
+    #[test]
+    fn past_one_shot_alarm_does_not_ring_and_is_consumed_once() {
+        let configuration = one_shot_configuration("2024-03-04T05:00:00Z");
+        let timezone = UserTimeZone::Fixed(FixedOffset::east_opt(0).unwrap());
+        assert!(calculate_next_alarm(&start(), &configuration, &timezone).is_none());
+
+        let mut reported = Vec::new();
+        let mut outbound_queue = OutboundQueue::load(&mut MemoryStorage::new());
+        for now in ["2024-03-04T06:00:00Z", "2024-03-04T06:01:00Z"] {
+            report_consumed_one_shot_alarms(
+                &configuration,
+                local(now),
+                &mut reported,
+                &mut outbound_queue,
+                MAC_ADDRESS,
+            );
+        }
+        assert_eq!(outbound_queue.status().depth, 1);
+        let consumed = serde_json::to_value(outbound_queue.front().unwrap()).unwrap();
+        assert_eq!(consumed["kind"], "alarmConsumed");
+        assert_eq!(consumed["description"], "train");
+        assert_eq!(consumed["at"], "2024-03-04T05:00:00Z");
+    }
+
+    #[test]
+    fn suppressed_one_shot_alarm_is_consumed_once_its_time_passed() {
+        let mut configuration = one_shot_configuration("2024-03-04T07:00:00Z");
+        configuration.skip_dates = vec![local("2024-03-04T07:00:00Z").date_naive()];
+        let mut reported = Vec::new();
+        let mut outbound_queue = OutboundQueue::load(&mut MemoryStorage::new());
+        for (now, depth) in [("2024-03-04T06:59:00Z", 0), ("2024-03-04T07:00:00Z", 1)] {
+            report_consumed_one_shot_alarms(
+                &configuration,
+                local(now),
+                &mut reported,
+                &mut outbound_queue,
+                MAC_ADDRESS,
+            );
+            assert_eq!(outbound_queue.status().depth, depth, "{}", now);
+        }
+    }
+
+    const SETTINGS: SnoozeSettings = SnoozeSettings {
+        snooze_minutes: 5,
+        max_snooze_count: 2,
+        dismiss_long_press_milliseconds: 2000,
+        ring_minutes: 1,
+    };
+
+    /// A ten minutes alarm with an id, at `at`.
+    fn scheduled_alarm(at: &str) -> ScheduledAlarm {
+        let mut configuration = one_shot_configuration(at);
+        configuration.cron_list[0].id = Some("train-1".to_owned());
+        configuration.cron_list[0].duration_minutes = Some(10);
+        let timezone = UserTimeZone::Fixed(FixedOffset::east_opt(0).unwrap());
+        calculate_next_alarm(&start(), &configuration, &timezone).unwrap()
+    }
+
+    /// The alarm events queued, emptying the queue.
+    fn queued_events(outbound_queue: &mut OutboundQueue) -> Vec<serde_json::Value> {
+        let mut events = Vec::new();
+        while let Some(message) = outbound_queue.front() {
+            if let OutboundMessage::AlarmEvent(event) = message {
+                events.push(serde_json::to_value(event).unwrap());
+            }
+            outbound_queue.pop();
+        }
+        events
+    }
+
+    /// The alarm part of the loop of `orchestrate`.
+    struct AlarmLoop {
+        alarm: Option<ScheduledAlarm>,
+        ringing_alarm: Option<ScheduledAlarm>,
+        snooze: SnoozeStateMachine,
+        outbound_queue: OutboundQueue,
+    }
+
+    impl AlarmLoop {
+        fn tick(&mut self, now: &str, pressed: bool) {
+            let now = local(now);
+            let window_open = track_ringing_alarm(
+                &self.alarm,
+                now,
+                &mut self.ringing_alarm,
+                &mut self.outbound_queue,
+                MAC_ADDRESS,
+            );
+            let previous = self.snooze.state();
+            self.snooze
+                .update(now.with_timezone(&Utc), window_open, pressed, &SETTINGS);
+            if let (Some(outcome), Some(ringing_alarm)) = (
+                snooze_outcome(previous, self.snooze.state()),
+                &self.ringing_alarm,
+            ) {
+                queue_alarm_event(
+                    &mut self.outbound_queue,
+                    MAC_ADDRESS,
+                    ringing_alarm,
+                    now,
+                    outcome,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ringing_snoozing_and_dismissing_queue_their_events() {
+        let mut alarm_loop = AlarmLoop {
+            alarm: Some(scheduled_alarm("2024-03-04T07:00:00Z")),
+            ringing_alarm: None,
+            snooze: SnoozeStateMachine::new(),
+            outbound_queue: OutboundQueue::load(&mut MemoryStorage::new()),
+        };
+        alarm_loop.tick("2024-03-04T06:59:59Z", false);
+        alarm_loop.tick("2024-03-04T07:00:00Z", false);
+        alarm_loop.tick("2024-03-04T07:00:01Z", false);
+        // a short press snoozes
+        alarm_loop.tick("2024-03-04T07:00:02Z", true);
+        alarm_loop.tick("2024-03-04T07:00:03Z", false);
+        // rings again, still the same window
+        alarm_loop.tick("2024-03-04T07:05:04Z", false);
+        // a long press dismisses
+        for second in 5..9 {
+            alarm_loop.tick(&format!("2024-03-04T07:05:0{}Z", second), true);
+        }
+        alarm_loop.tick("2024-03-04T07:05:09Z", false);
+
+        let events = queued_events(&mut alarm_loop.outbound_queue);
+        let outcomes: Vec<&str> = events
+            .iter()
+            .map(|event| event["outcome"].as_str().unwrap())
+            .collect();
+        assert_eq!(outcomes, ["fired", "snoozed", "dismissed"]);
+        assert_eq!(events[0]["alarmId"], "train-1");
+        assert_eq!(events[0]["macAddress"], MAC_ADDRESS);
+        assert_eq!(events[0]["scheduledAt"], "2024-03-04T07:00:00Z");
+        assert_eq!(events[1]["occurredAt"], "2024-03-04T07:00:03Z");
+    }
+
+    #[test]
+    fn snooze_outcomes() {
+        let ringing = SnoozeState::Ringing { until: None };
+        let snoozed = SnoozeState::Snoozed { until: start() };
+        assert_eq!(
+            snooze_outcome(ringing, snoozed),
+            Some(AlarmOutcome::Snoozed)
+        );
+        assert_eq!(
+            snooze_outcome(ringing, SnoozeState::Dismissed),
+            Some(AlarmOutcome::Dismissed)
+        );
+        assert_eq!(
+            snooze_outcome(snoozed, SnoozeState::Dismissed),
+            Some(AlarmOutcome::Dismissed)
+        );
+        assert_eq!(snooze_outcome(SnoozeState::Idle, ringing), None);
+        assert_eq!(snooze_outcome(ringing, SnoozeState::Idle), None);
+        assert_eq!(snooze_outcome(snoozed, ringing), None);
+    }
+
+    #[test]
+    fn alarm_over_without_ringing_is_missed() {
+        let alarm = Some(scheduled_alarm("2024-03-04T07:00:00Z"));
+        let mut outbound_queue = OutboundQueue::load(&mut MemoryStorage::new());
+        let mut is_calculated = true;
+        track_missed_alarm(
+            &alarm,
+            local("2024-03-04T07:05:00Z"),
+            &None,
+            &mut is_calculated,
+            &mut outbound_queue,
+            MAC_ADDRESS,
+        );
+        assert!(is_calculated);
+        track_missed_alarm(
+            &alarm,
+            local("2024-03-04T07:10:00Z"),
+            &None,
+            &mut is_calculated,
+            &mut outbound_queue,
+            MAC_ADDRESS,
+        );
+        assert!(!is_calculated);
+        let events = queued_events(&mut outbound_queue);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["outcome"], "missed");
+
+        // the alarm that rang is not missed
+        is_calculated = true;
+        track_missed_alarm(
+            &alarm,
+            local("2024-03-04T07:10:00Z"),
+            &alarm,
+            &mut is_calculated,
+            &mut outbound_queue,
+            MAC_ADDRESS,
+        );
+        assert!(!is_calculated);
+        assert!(queued_events(&mut outbound_queue).is_empty());
+    }
+}
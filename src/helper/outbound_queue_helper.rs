@@ -0,0 +1,272 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info, warn};
+
+use crate::{
+    config::config::{
+        OUTBOUND_QUEUE_CAPACITY, OUTBOUND_QUEUE_INITIAL_BACKOFF_MILLISECONDS,
+        OUTBOUND_QUEUE_MAX_BACKOFF_MILLISECONDS, OUTBOUND_QUEUE_POLICY,
+    },
+    dto::{outbound_message::OutboundMessage, outbound_queue_status::OutboundQueueStatus},
+    platform::storage::Storage,
+};
+
+const OUTBOUND_QUEUE_KEY: &str = "outbox";
+
+/// What to do with a new message when the queue is full.
+#[allow(dead_code)] // one of them is selected in config.rs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverflowPolicy {
+    DropOldest,
+    DropNewest,
+}
+
+/// Messages for the server waiting to be delivered, in order. The queue is
+/// saved in the storage so that it survives a reboot, and kept only in
+/// memory if the storage cannot be written.
+pub struct OutboundQueue {
+    messages: VecDeque<OutboundMessage>,
+    dropped: u32,
+    failures: u32,
+    next_attempt: Option<DateTime<Utc>>,
+    persistent: bool,
+    // what the storage holds, to write it only when something changed
+    saved: Option<String>,
+}
+
+impl OutboundQueue {
+    pub fn load(storage: &mut dyn Storage) -> OutboundQueue {
+        let mut queue = OutboundQueue {
+            messages: VecDeque::new(),
+            dropped: 0,
+            failures: 0,
+            next_attempt: None,
+            persistent: true,
+            saved: None,
+        };
+        match storage.get(OUTBOUND_QUEUE_KEY) {
+            Ok(None) => {}
+            Ok(Some(value)) => match serde_json::from_str::<VecDeque<OutboundMessage>>(&value) {
+                Ok(messages) => {
+                    info!("{} queued messages restored", messages.len());
+                    queue.messages = messages;
+                    queue.saved = Some(value);
+                }
+                Err(e) => error!("invalid saved outbound queue: {}", e),
+            },
+            Err(e) => {
+                error!(
+                    "unable to read the outbound queue, keeping it in memory: {:?}",
+                    e
+                );
+                queue.persistent = false;
+            }
+        }
+        while queue.messages.len() > OUTBOUND_QUEUE_CAPACITY {
+            queue.messages.pop_front();
+            queue.dropped += 1;
+        }
+        queue
+    }
+
+    /// A heartbeat replaces the one still queued, if any: only the last one
+    /// tells something to the server. A diagnostic already queued is not
+    /// queued again, e.g. while every configuration download fails.
+    pub fn push(&mut self, message: OutboundMessage) {
+        match &message {
+            OutboundMessage::Heartbeat { .. } => self
+                .messages
+                .retain(|queued| !matches!(queued, OutboundMessage::Heartbeat { .. })),
+            OutboundMessage::Diagnostic(diagnostic) => {
+                let queued = self.messages.iter().any(|queued| {
+                    matches!(queued, OutboundMessage::Diagnostic(queued)
+                        if queued.message() == diagnostic.message())
+                });
+                if queued {
+                    return;
+                }
+            }
+            _ => {}
+        }
+        if self.messages.len() >= OUTBOUND_QUEUE_CAPACITY {
+            self.dropped += 1;
+            match OUTBOUND_QUEUE_POLICY {
+                OverflowPolicy::DropOldest => {
+                    warn!("outbound queue full, dropping the oldest message");
+                    self.messages.pop_front();
+                }
+                OverflowPolicy::DropNewest => {
+                    warn!("outbound queue full, dropping {:?}", message);
+                    return;
+                }
+            }
+        }
+        self.messages.push_back(message);
+    }
+
+    pub fn status(&self) -> OutboundQueueStatus {
+        OutboundQueueStatus {
+            depth: self.messages.len(),
+            capacity: OUTBOUND_QUEUE_CAPACITY,
+            dropped: self.dropped,
+            failures: self.failures,
+        }
+    }
+
+    /// Whether there is something to deliver and the backoff is over.
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        !self.messages.is_empty() && self.next_attempt.map_or(true, |next| now >= next)
+    }
+
+    pub fn front(&self) -> Option<&OutboundMessage> {
+        self.messages.front()
+    }
+
+    /// Removes the first message, delivered or refused for good.
+    pub fn pop(&mut self) {
+        self.messages.pop_front();
+        self.failures = 0;
+        self.next_attempt = None;
+    }
+
+    /// Waits twice as long after each consecutive failure, up to
+    /// `OUTBOUND_QUEUE_MAX_BACKOFF_MILLISECONDS`.
+    pub fn failed(&mut self, now: DateTime<Utc>) {
+        self.failures += 1;
+        let backoff = OUTBOUND_QUEUE_INITIAL_BACKOFF_MILLISECONDS
+            .saturating_mul(1 << (self.failures - 1).min(20))
+            .min(OUTBOUND_QUEUE_MAX_BACKOFF_MILLISECONDS);
+        self.next_attempt = Some(now + Duration::milliseconds(backoff as i64));
+        warn!(
+            "{} messages queued, next attempt in {} ms",
+            self.messages.len(),
+            backoff
+        );
+    }
+
+    /// The server answered again, no need to wait for the backoff.
+    pub fn retry_now(&mut self) {
+        self.next_attempt = None;
+    }
+
+    pub fn save(&mut self, storage: &mut dyn Storage) {
+        if !self.persistent {
+            return;
+        }
+        let value = match serde_json::to_string(&self.messages) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("unable to serialize the outbound queue: {}", e);
+                return;
+            }
+        };
+        if self.saved.as_deref().unwrap_or("[]") == value {
+            return;
+        }
+        match storage.set(OUTBOUND_QUEUE_KEY, &value) {
+            Ok(()) => self.saved = Some(value),
+            Err(e) => {
+                error!(
+                    "unable to save the outbound queue, keeping it in memory: {:?}",
+                    e
+                );
+                self.persistent = false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dto::diagnostic_request::DiagnosticRequest, platform::storage::MemoryStorage};
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-03-04T06:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+            + Duration::seconds(seconds)
+    }
+
+    fn diagnostic(message: &str) -> OutboundMessage {
+        OutboundMessage::Diagnostic(DiagnosticRequest::new(
+            "02:00:00:00:00:01".to_owned(),
+            at(0),
+            message.to_owned(),
+        ))
+    }
+
+    fn empty_queue() -> OutboundQueue {
+        OutboundQueue::load(&mut MemoryStorage::new())
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let mut queue = empty_queue();
+        queue.push(diagnostic("offline"));
+        assert!(queue.is_due(at(0)));
+
+        let mut backoff = OUTBOUND_QUEUE_INITIAL_BACKOFF_MILLISECONDS as i64;
+        for _ in 0..30 {
+            queue.failed(at(0));
+            let next_attempt = at(0) + Duration::milliseconds(backoff);
+            assert!(!queue.is_due(next_attempt - Duration::milliseconds(1)));
+            assert!(queue.is_due(next_attempt));
+            backoff = (backoff * 2).min(OUTBOUND_QUEUE_MAX_BACKOFF_MILLISECONDS as i64);
+        }
+        assert_eq!(queue.status().failures, 30);
+
+        queue.retry_now();
+        assert!(queue.is_due(at(0)));
+        queue.failed(at(0));
+        queue.pop();
+        assert_eq!(queue.status().failures, 0);
+        assert!(!queue.is_due(at(0)));
+    }
+
+    #[test]
+    fn queue_survives_a_reboot() {
+        let mut storage = MemoryStorage::new();
+        let mut queue = OutboundQueue::load(&mut storage);
+        queue.push(diagnostic("first"));
+        queue.push(diagnostic("second"));
+        queue.save(&mut storage);
+
+        let mut restored = OutboundQueue::load(&mut storage);
+        assert_eq!(restored.status().depth, 2);
+        for expected in ["first", "second"] {
+            match restored.front() {
+                Some(OutboundMessage::Diagnostic(diagnostic)) => {
+                    assert_eq!(diagnostic.message(), expected)
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+            restored.pop();
+        }
+        restored.save(&mut storage);
+        assert_eq!(OutboundQueue::load(&mut storage).status().depth, 0);
+    }
+
+    #[test]
+    fn invalid_saved_queue_is_ignored() {
+        let mut storage = MemoryStorage::new();
+        storage.set(OUTBOUND_QUEUE_KEY, "not json").unwrap();
+        assert_eq!(OutboundQueue::load(&mut storage).status().depth, 0);
+    }
+
+    #[test]
+    fn duplicates_are_not_queued_and_the_queue_is_bounded() {
+        let mut queue = empty_queue();
+        queue.push(diagnostic("offline"));
+        queue.push(diagnostic("offline"));
+        assert_eq!(queue.status().depth, 1);
+
+        for index in 0..OUTBOUND_QUEUE_CAPACITY {
+            queue.push(diagnostic(&index.to_string()));
+        }
+        let status = queue.status();
+        assert_eq!(status.depth, OUTBOUND_QUEUE_CAPACITY);
+        assert_eq!(status.dropped, 1);
+    }
+}
@@ -0,0 +1,506 @@
+use crate::{
+    config::config::{
+        CONFIGURATION_PUBLIC_KEY, DEFAULT_SERVER_URL, PROVISIONING_AP_SECRET,
+        PROVISIONING_AP_SSID_PREFIX, WIFI_PASS, WIFI_SSID,
+    },
+    dto::{wifi_credentials::WifiCredentials, wifi_settings::WifiSettings},
+    helper::{
+        signature_helper::parse_public_key, static_ip_helper::parse_static_ip, tls_helper::to_hex,
+    },
+    platform::storage::Storage,
+};
+use hmac::{Hmac, Mac};
+use log::error;
+use sha2::Sha256;
+
+const WIFI_SETTINGS_KEY: &str = "wifi";
+const LAST_CONNECTED_KEY: &str = "wifi_last";
+// limits of the 802.11 standard and of WPA2
+const MAX_SSID_LENGTH: usize = 32;
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_PASSWORD_LENGTH: usize = 64;
+const PROVISIONING_AP_PASSWORD_LENGTH: usize = 12;
+const DNS_HEADER_LENGTH: usize = 12;
+const DNS_TYPE_A: u16 = 1;
+const DNS_TTL_SECONDS: u32 = 60;
+
+/// What the setup page posts.
+#[derive(Debug, PartialEq)]
+pub struct SetupForm {
+    pub credentials: WifiCredentials,
+    pub server_url: Option<String>,
+    pub configuration_public_key: Option<String>,
+}
+
+/// The networks entered in the setup page, else the `WIFI_SSID` and
+/// `WIFI_PASS` of `config.rs` when they are set.
+pub fn load_wifi_settings(storage: &mut dyn Storage) -> WifiSettings {
+    let stored = match storage.get(WIFI_SETTINGS_KEY) {
+        Ok(stored) => stored,
+        Err(e) => {
+            error!("unable to read the WiFi settings: {:?}", e);
+            None
+        }
+    };
+    let mut settings = stored
+        .and_then(|value| {
+            serde_json::from_str::<WifiSettings>(&value)
+                .map_err(|e| error!("invalid saved WiFi settings: {}", e))
+                .ok()
+        })
+        .unwrap_or_default();
+    settings.last_connected = storage.get(LAST_CONNECTED_KEY).unwrap_or_else(|e| {
+        error!("unable to read the last connected WiFi network: {:?}", e);
+        None
+    });
+    if settings.networks.is_empty() && !WIFI_SSID.is_empty() {
+        settings.networks.push(WifiCredentials {
+            ssid: WIFI_SSID.to_owned(),
+            password: WIFI_PASS.to_owned(),
+            priority: 0,
+            static_ip: None,
+        });
+    }
+    settings
+}
+
+pub fn save_wifi_settings(
+    storage: &mut dyn Storage,
+    settings: &WifiSettings,
+) -> Result<(), anyhow::Error> {
+    storage.set(WIFI_SETTINGS_KEY, &serde_json::to_string(settings)?)
+}
+
+pub fn save_last_connected_network(
+    storage: &mut dyn Storage,
+    ssid: &str,
+) -> Result<(), anyhow::Error> {
+    storage.set(LAST_CONNECTED_KEY, ssid)
+}
+
+/// Saves the network of the form, replacing the one with the same SSID,
+/// with a priority above the saved ones so that it is preferred to the
+/// other saved networks in range. The configuration public key of the form
+/// is only kept while none is set: whoever joins the setup access point
+/// must not swap it for their own.
+pub fn apply_setup_form(settings: &mut WifiSettings, form: SetupForm) {
+    let priority = settings
+        .networks
+        .iter()
+        .filter(|network| network.ssid != form.credentials.ssid)
+        .map(|network| network.priority)
+        .max()
+        .map_or(0, |priority| priority.saturating_add(1));
+    settings
+        .networks
+        .retain(|network| network.ssid != form.credentials.ssid);
+    settings.networks.insert(
+        0,
+        WifiCredentials {
+            priority,
+            ..form.credentials
+        },
+    );
+    settings.server_url = form.server_url;
+    if configuration_public_key(settings).is_none() {
+        settings.configuration_public_key = form.configuration_public_key;
+    }
+}
+
+/// `CONFIGURATION_PUBLIC_KEY`, else the key saved from the setup page; once
+/// set, only erasing the NVS removes the saved key.
+pub fn configuration_public_key(settings: &WifiSettings) -> Option<&str> {
+    CONFIGURATION_PUBLIC_KEY.or(settings.configuration_public_key.as_deref())
+}
+
+/// Name of the setup access point: the prefix and the last 4 hexadecimal
+/// digits of the MAC address, to tell several devices apart.
+pub fn provisioning_ssid(mac_address: &str) -> String {
+    let digits: String = mac_address.chars().filter(|c| *c != ':').collect();
+    let suffix = &digits[digits.len().saturating_sub(4)..];
+    format!("{}{}", PROVISIONING_AP_SSID_PREFIX, suffix.to_uppercase())
+}
+
+/// WPA2 passphrase of the setup access point: the first hexadecimal digits
+/// of the HMAC-SHA256 of the lowercase MAC address with
+/// `PROVISIONING_AP_SECRET`, so that only who holds the device label can
+/// join it.
+pub fn provisioning_password(mac_address: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(PROVISIONING_AP_SECRET.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(mac_address.to_lowercase().as_bytes());
+    let mut password = to_hex(&mac.finalize().into_bytes());
+    password.truncate(PROVISIONING_AP_PASSWORD_LENGTH);
+    password
+}
+
+/// `url` with `DEFAULT_SERVER_URL` replaced by `server_url`; the URLs of
+/// other servers are kept.
+pub fn rebase_server_url(url: &str, server_url: &str) -> String {
+    match url.strip_prefix(DEFAULT_SERVER_URL) {
+        Some(path) => format!("{}{}", server_url.trim_end_matches('/'), path),
+        None => url.to_owned(),
+    }
+}
+
+/// Reads and validates the `application/x-www-form-urlencoded` body posted
+/// by the setup page.
+pub fn parse_setup_form(body: &str) -> Result<SetupForm, String> {
+    let mut ssid = None;
+    let mut password = String::new();
+    let mut server_url = None;
+    let mut configuration_public_key = None;
+    // static IPv4 address, gateway, netmask and DNS servers
+    let mut addressing: [String; 4] = Default::default();
+    for pair in body.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = decode_form_value(value).ok_or("invalid form encoding")?;
+        match name {
+            "ssid" => ssid = Some(value),
+            "password" => password = value,
+            "server" if !value.trim().is_empty() => server_url = Some(value.trim().to_owned()),
+            "configkey" if !value.trim().is_empty() => {
+                configuration_public_key = Some(value.trim().to_lowercase())
+            }
+            "ip" => addressing[0] = value,
+            "gateway" => addressing[1] = value,
+            "netmask" => addressing[2] = value,
+            "dns" => addressing[3] = value,
+            _ => {}
+        }
+    }
+    let ssid = ssid.unwrap_or_default();
+    if ssid.is_empty() || ssid.len() > MAX_SSID_LENGTH {
+        return Err(format!(
+            "the network name must have 1 to {} characters",
+            MAX_SSID_LENGTH
+        ));
+    }
+    if !password.is_empty()
+        && (password.len() < MIN_PASSWORD_LENGTH || password.len() > MAX_PASSWORD_LENGTH)
+    {
+        return Err(format!(
+            "the password must be empty or have {} to {} characters",
+            MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+        ));
+    }
+    if let Some(server_url) = &server_url {
+        let host = server_url
+            .strip_prefix("http://")
+            .or_else(|| server_url.strip_prefix("https://"));
+        if host.map_or(true, |host| host.is_empty() || host.contains(' ')) {
+            return Err("the server URL must start with http:// or https://".to_owned());
+        }
+    }
+    if let Some(public_key) = &configuration_public_key {
+        parse_public_key(public_key)
+            .map_err(|_| "the configuration public key must be 64 hexadecimal digits".to_owned())?;
+    }
+    let [address, gateway, netmask, dns_servers] = &addressing;
+    let static_ip = parse_static_ip(address, gateway, netmask, dns_servers)?;
+    Ok(SetupForm {
+        credentials: WifiCredentials {
+            ssid,
+            password,
+            priority: 0,
+            static_ip,
+        },
+        server_url,
+        configuration_public_key,
+    })
+}
+
+/// `+` and `%XX` decoding, `None` for an invalid escape or UTF-8 sequence.
+fn decode_form_value(value: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+    while let Some(byte) = input.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let high = (input.next()? as char).to_digit(16)?;
+                let low = (input.next()? as char).to_digit(16)?;
+                bytes.push((high * 16 + low) as u8);
+            }
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// The setup page, with the saved networks, server URL and configuration
+/// public key and the validation error of the previous submission. The key
+/// can only be entered while none is set.
+pub fn setup_page(device_name: &str, settings: &WifiSettings, error: Option<&str>) -> String {
+    let server_url = settings.server_url.as_deref().unwrap_or("");
+    let public_key = match configuration_public_key(settings) {
+        Some(public_key) => format!(
+            "<p>The configurations are checked with the public key {}.</p>",
+            escape_html(public_key)
+        ),
+        None => "<label>Configuration public key<input name=\"configkey\" maxlength=\"64\" \
+placeholder=\"Ed25519, in hexadecimal\"></label>"
+            .to_owned(),
+    };
+    let saved_networks = match settings.networks.is_empty() {
+        true => String::new(),
+        false => {
+            let ssids: Vec<String> = settings
+                .networks
+                .iter()
+                .map(|network| escape_html(&network.ssid))
+                .collect();
+            format!("<p>Saved networks: {}</p>", ssids.join(", "))
+        }
+    };
+    let error = error.map_or(String::new(), |error| {
+        format!("<p class=\"error\">{}</p>", escape_html(error))
+    });
+    format!(
+        "<!DOCTYPE html>\
+<html><head><meta charset=\"utf-8\"><meta name=\"viewport\" content=\"width=device-width\">\
+<title>{name} setup</title>\
+<style>body{{font-family:sans-serif;max-width:24em;margin:auto;padding:1em}}\
+label,input{{display:block;width:100%;margin-bottom:.5em}}.error{{color:#b00}}</style>\
+</head><body><h1>{name}</h1>{saved_networks}{error}\
+<form method=\"post\" action=\"/setup\">\
+<label>WiFi network<input name=\"ssid\" maxlength=\"{max_ssid}\" required></label>\
+<label>Password<input name=\"password\" type=\"password\" maxlength=\"{max_password}\"></label>\
+<fieldset><legend>Static IP, leave empty for DHCP</legend>\
+<label>IP address<input name=\"ip\" placeholder=\"192.168.1.50\"></label>\
+<label>Gateway<input name=\"gateway\" placeholder=\"192.168.1.1\"></label>\
+<label>Netmask<input name=\"netmask\" placeholder=\"255.255.255.0\"></label>\
+<label>DNS servers<input name=\"dns\" placeholder=\"the gateway\"></label>\
+</fieldset>\
+<label>Server URL<input name=\"server\" value=\"{server}\" placeholder=\"{default_server}\"></label>\
+{public_key}\
+<input type=\"submit\" value=\"Save and restart\">\
+</form></body></html>",
+        name = escape_html(device_name),
+        saved_networks = saved_networks,
+        error = error,
+        max_ssid = MAX_SSID_LENGTH,
+        max_password = MAX_PASSWORD_LENGTH,
+        server = escape_html(server_url),
+        default_server = DEFAULT_SERVER_URL,
+        public_key = public_key,
+    )
+}
+
+/// The page shown once the credentials are saved.
+pub fn saved_page(device_name: &str, credentials: &WifiCredentials) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{name} setup</title></head>\
+<body><h1>{name}</h1><p>Saved, the alarm clock restarts and connects to {ssid}.</p></body></html>",
+        name = escape_html(device_name),
+        ssid = escape_html(&credentials.ssid),
+    )
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Answers any DNS query with `ip`, so that the phones joining the setup
+/// access point open the setup page; `None` for what is not a query.
+#[cfg_attr(not(target_os = "espidf"), allow(dead_code))] // the simulator has no DNS
+pub fn captive_dns_response(query: &[u8], ip: [u8; 4]) -> Option<Vec<u8>> {
+    if query.len() <= DNS_HEADER_LENGTH {
+        return None;
+    }
+    let flags = u16::from_be_bytes([query[2], query[3]]);
+    let question_count = u16::from_be_bytes([query[4], query[5]]);
+    // only standard queries, with the opcode 0
+    if flags & 0xf800 != 0 || question_count == 0 {
+        return None;
+    }
+    // the first question: labels up to the root, then type and class
+    let mut end = DNS_HEADER_LENGTH;
+    while *query.get(end)? != 0 {
+        end += 1 + query[end] as usize;
+    }
+    end += 1 + 4;
+    let question = query.get(DNS_HEADER_LENGTH..end)?;
+    let question_type =
+        u16::from_be_bytes([question[question.len() - 4], question[question.len() - 3]]);
+    let answer_count: u16 = if question_type == DNS_TYPE_A { 1 } else { 0 };
+
+    let mut response = Vec::with_capacity(end + 16);
+    response.extend_from_slice(&query[0..2]);
+    // response, recursion desired copied from the query, recursion available
+    response.extend_from_slice(&(0x8080 | (flags & 0x0100)).to_be_bytes());
+    response.extend_from_slice(&1u16.to_be_bytes());
+    response.extend_from_slice(&answer_count.to_be_bytes());
+    response.extend_from_slice(&[0, 0, 0, 0]);
+    response.extend_from_slice(question);
+    if answer_count > 0 {
+        // pointer to the name of the question
+        response.extend_from_slice(&[0xc0, DNS_HEADER_LENGTH as u8]);
+        response.extend_from_slice(&DNS_TYPE_A.to_be_bytes());
+        response.extend_from_slice(&1u16.to_be_bytes());
+        response.extend_from_slice(&DNS_TTL_SECONDS.to_be_bytes());
+        response.extend_from_slice(&4u16.to_be_bytes());
+        response.extend_from_slice(&ip);
+    }
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(ssid: &str, priority: i32) -> WifiCredentials {
+        WifiCredentials {
+            ssid: ssid.to_owned(),
+            password: String::new(),
+            priority,
+            static_ip: None,
+        }
+    }
+
+    /// Query for `setup.example` of the given type, with recursion desired.
+    fn dns_query(question_type: u16) -> Vec<u8> {
+        let mut query = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        query.extend_from_slice(b"\x05setup\x07example\x00");
+        query.extend_from_slice(&question_type.to_be_bytes());
+        query.extend_from_slice(&[0, 1]);
+        query
+    }
+
+    #[test]
+    fn setup_form_is_decoded() {
+        let form = parse_setup_form(
+            "ssid=My+Home%21&password=secret%2Bpass&server=+http%3A%2F%2F10.0.0.9%3A8080+&configkey=",
+        )
+        .unwrap();
+        assert_eq!(form.credentials.ssid, "My Home!");
+        assert_eq!(form.credentials.password, "secret+pass");
+        assert_eq!(form.server_url.as_deref(), Some("http://10.0.0.9:8080"));
+        assert_eq!(form.configuration_public_key, None);
+        assert_eq!(form.credentials.static_ip, None);
+
+        let open_network = parse_setup_form("ssid=Cafe&password=").unwrap();
+        assert_eq!(open_network.credentials.password, "");
+    }
+
+    #[test]
+    fn invalid_setup_forms_are_explained() {
+        for body in [
+            "password=12345678",
+            "ssid=",
+            "ssid=123456789012345678901234567890123",
+            "ssid=Home&password=short",
+            "ssid=Home&server=ftp://10.0.0.9",
+            "ssid=Home&server=http://",
+            "ssid=Home&configkey=abcd",
+            "ssid=Home%2",
+            "ssid=Home%FF",
+        ] {
+            assert!(parse_setup_form(body).is_err(), "{}", body);
+        }
+    }
+
+    #[test]
+    fn saved_network_replaces_the_one_with_the_same_ssid() {
+        let mut settings = WifiSettings {
+            networks: vec![network("Office", 5), network("Home", 1)],
+            ..WifiSettings::default()
+        };
+        let form = parse_setup_form("ssid=Home&password=new-password").unwrap();
+        apply_setup_form(&mut settings, form);
+        assert_eq!(settings.networks.len(), 2);
+        assert_eq!(settings.networks[0].ssid, "Home");
+        assert_eq!(settings.networks[0].password, "new-password");
+        assert_eq!(settings.networks[0].priority, 6);
+        assert_eq!(settings.networks[1].ssid, "Office");
+    }
+
+    #[test]
+    fn saved_network_is_preferred_to_the_others() {
+        let mut settings = WifiSettings::default();
+        apply_setup_form(&mut settings, parse_setup_form("ssid=Home").unwrap());
+        assert_eq!(settings.networks[0].priority, 0);
+        apply_setup_form(&mut settings, parse_setup_form("ssid=Office").unwrap());
+        apply_setup_form(&mut settings, parse_setup_form("ssid=Office").unwrap());
+        assert_eq!(settings.networks[0].ssid, "Office");
+        assert_eq!(settings.networks[0].priority, 1);
+        apply_setup_form(&mut settings, parse_setup_form("ssid=Home").unwrap());
+        assert_eq!(settings.networks[0].ssid, "Home");
+        assert_eq!(settings.networks[0].priority, 2);
+    }
+
+    #[test]
+    fn setup_form_never_replaces_the_public_key() {
+        let saved_key = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+        let other_key = "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c";
+        let form = |key: &str| parse_setup_form(&format!("ssid=Home&configkey={}", key)).unwrap();
+
+        let mut settings = WifiSettings::default();
+        apply_setup_form(&mut settings, form(saved_key));
+        assert_eq!(
+            configuration_public_key(&settings),
+            Some(CONFIGURATION_PUBLIC_KEY.unwrap_or(saved_key))
+        );
+
+        let mut settings = WifiSettings {
+            configuration_public_key: Some(saved_key.to_owned()),
+            ..WifiSettings::default()
+        };
+        apply_setup_form(&mut settings, form(other_key));
+        assert_eq!(
+            settings.configuration_public_key.as_deref(),
+            Some(saved_key)
+        );
+        let page = setup_page("Alarm Clock", &settings, None);
+        assert!(!page.contains("configkey"));
+        assert!(page.contains(CONFIGURATION_PUBLIC_KEY.unwrap_or(saved_key)));
+    }
+
+    #[test]
+    fn access_point_password_is_derived_from_the_mac_address() {
+        let password = provisioning_password("02:00:00:00:3A:7F");
+        assert_eq!(password.len(), PROVISIONING_AP_PASSWORD_LENGTH);
+        assert!(password.len() >= MIN_PASSWORD_LENGTH);
+        assert_eq!(password, provisioning_password("02:00:00:00:3a:7f"));
+        assert_ne!(password, provisioning_password("02:00:00:00:3a:80"));
+    }
+
+    #[test]
+    fn access_point_is_named_after_the_mac_address() {
+        assert_eq!(
+            provisioning_ssid("02:00:00:00:3a:7f"),
+            format!("{}3A7F", PROVISIONING_AP_SSID_PREFIX)
+        );
+    }
+
+    #[test]
+    fn captive_dns_answers_every_name_with_the_device() {
+        let query = dns_query(DNS_TYPE_A);
+        let response = captive_dns_response(&query, [192, 168, 71, 1]).unwrap();
+        // same id, a response with recursion desired and available
+        assert_eq!(&response[0..4], &[0x12, 0x34, 0x81, 0x80]);
+        // one question, one answer
+        assert_eq!(&response[4..8], &[0, 1, 0, 1]);
+        assert_eq!(&response[12..query.len()], &query[12..]);
+        assert_eq!(&response[response.len() - 4..], &[192, 168, 71, 1]);
+
+        // AAAA: no answer, so the phone falls back to IPv4
+        let response = captive_dns_response(&dns_query(28), [192, 168, 71, 1]).unwrap();
+        assert_eq!(&response[4..8], &[0, 1, 0, 0]);
+        assert_eq!(response.len(), query.len());
+    }
+
+    #[test]
+    fn what_is_not_a_query_is_ignored() {
+        let mut response = dns_query(DNS_TYPE_A);
+        response[2] |= 0x80;
+        assert_eq!(captive_dns_response(&response, [192, 168, 71, 1]), None);
+        let truncated = &dns_query(DNS_TYPE_A)[..20];
+        assert_eq!(captive_dns_response(truncated, [192, 168, 71, 1]), None);
+        assert_eq!(captive_dns_response(&[0; 12], [192, 168, 71, 1]), None);
+    }
+}
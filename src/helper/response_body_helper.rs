@@ -0,0 +1,166 @@
+use std::fmt;
+
+const READ_CHUNK_SIZE: usize = 512;
+
+#[derive(Debug)]
+pub enum ResponseBodyError {
+    TooLarge {
+        max_size: usize,
+        content_length: Option<usize>,
+    },
+    Truncated {
+        expected: usize,
+        received: usize,
+    },
+    Read(String),
+    InvalidUtf8,
+}
+
+impl fmt::Display for ResponseBodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResponseBodyError::TooLarge {
+                max_size,
+                content_length: Some(content_length),
+            } => write!(
+                f,
+                "response body of {} bytes exceeds the limit of {} bytes",
+                content_length, max_size
+            ),
+            ResponseBodyError::TooLarge { max_size, .. } => {
+                write!(f, "response body exceeds the limit of {} bytes", max_size)
+            }
+            ResponseBodyError::Truncated { expected, received } => write!(
+                f,
+                "response body truncated: {} of {} bytes received",
+                received, expected
+            ),
+            ResponseBodyError::Read(e) => write!(f, "error while reading the response: {}", e),
+            ResponseBodyError::InvalidUtf8 => write!(f, "response body is not valid UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for ResponseBodyError {}
+
+/// Reads a whole response body from `read`, chunk by chunk, up to `max_size`
+/// bytes. With a `content_length` exactly that many bytes are expected;
+/// without one (chunked encoding, already decoded by the transport) the body
+/// ends when `read` returns 0.
+pub fn read_response_body<F>(
+    mut read: F,
+    content_length: Option<usize>,
+    max_size: usize,
+) -> Result<String, ResponseBodyError>
+where
+    F: FnMut(&mut [u8]) -> Result<usize, String>,
+{
+    if let Some(content_length) = content_length {
+        if content_length > max_size {
+            return Err(ResponseBodyError::TooLarge {
+                max_size,
+                content_length: Some(content_length),
+            });
+        }
+    }
+
+    let mut body = Vec::with_capacity(content_length.unwrap_or(READ_CHUNK_SIZE));
+    let mut chunk = [0u8; READ_CHUNK_SIZE];
+    loop {
+        let wanted = match content_length {
+            Some(content_length) => (content_length - body.len()).min(READ_CHUNK_SIZE),
+            None => READ_CHUNK_SIZE,
+        };
+        if wanted == 0 {
+            break;
+        }
+        let bytes_read = read(&mut chunk[..wanted]).map_err(ResponseBodyError::Read)?;
+        if bytes_read == 0 {
+            break;
+        }
+        if body.len() + bytes_read > max_size {
+            return Err(ResponseBodyError::TooLarge {
+                max_size,
+                content_length,
+            });
+        }
+        body.extend_from_slice(&chunk[..bytes_read]);
+    }
+
+    if let Some(content_length) = content_length {
+        if body.len() < content_length {
+            return Err(ResponseBodyError::Truncated {
+                expected: content_length,
+                received: body.len(),
+            });
+        }
+    }
+    String::from_utf8(body).map_err(|_| ResponseBodyError::InvalidUtf8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out `body` at most `step` bytes per read.
+    fn reader(body: &[u8], step: usize) -> impl FnMut(&mut [u8]) -> Result<usize, String> + '_ {
+        let mut position = 0;
+        move |buffer| {
+            let count = step.min(buffer.len()).min(body.len() - position);
+            buffer[..count].copy_from_slice(&body[position..position + count]);
+            position += count;
+            Ok(count)
+        }
+    }
+
+    #[test]
+    fn body_spanning_several_chunks_is_reassembled() {
+        let body = "x".repeat(READ_CHUNK_SIZE * 2 + 7);
+        let read = read_response_body(reader(body.as_bytes(), 100), Some(body.len()), 4096);
+        assert_eq!(read.unwrap(), body);
+        let read = read_response_body(reader(body.as_bytes(), 100), None, 4096);
+        assert_eq!(read.unwrap(), body);
+    }
+
+    #[test]
+    fn oversized_body_is_rejected() {
+        let body = "x".repeat(100);
+        assert!(matches!(
+            read_response_body(reader(body.as_bytes(), 10), Some(100), 50),
+            Err(ResponseBodyError::TooLarge {
+                content_length: Some(100),
+                ..
+            })
+        ));
+        assert!(matches!(
+            read_response_body(reader(body.as_bytes(), 10), None, 50),
+            Err(ResponseBodyError::TooLarge {
+                content_length: None,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn short_body_is_truncated() {
+        assert!(matches!(
+            read_response_body(reader(b"abc", 10), Some(5), 50),
+            Err(ResponseBodyError::Truncated {
+                expected: 5,
+                received: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn read_errors_and_invalid_utf8_are_reported() {
+        assert!(matches!(
+            read_response_body(|_| Err("reset".to_owned()), None, 50),
+            Err(ResponseBodyError::Read(_))
+        ));
+        assert!(matches!(
+            read_response_body(reader(&[0xff, 0xfe], 10), Some(2), 50),
+            Err(ResponseBodyError::InvalidUtf8)
+        ));
+    }
+}
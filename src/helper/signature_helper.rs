@@ -0,0 +1,254 @@
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, VerifyingKey, PUBLIC_KEY_LENGTH};
+use log::{error, warn};
+
+use crate::{
+    config::config::REJECT_UNVERIFIED_CONFIGURATION,
+    dto::signed_configuration::{SignedConfiguration, SignedConfigurationBinding},
+    helper::{
+        provisioning_helper::{configuration_public_key, load_wifi_settings},
+        tls_helper::from_hex,
+    },
+    platform::storage::Storage,
+};
+
+/// `CONFIGURATION_PUBLIC_KEY`, else the key entered in the setup page. An
+/// invalid key is still returned, so that no configuration passes the check.
+pub fn load_configuration_public_key(storage: &mut dyn Storage) -> Option<String> {
+    let settings = load_wifi_settings(storage);
+    let public_key = configuration_public_key(&settings).map(str::to_owned);
+    if let Some(Err(message)) = public_key.as_deref().map(parse_public_key) {
+        error!("{}, the configurations cannot be verified", message);
+    }
+    public_key
+}
+
+/// 64 hexadecimal digits, the raw key as in RFC 8032.
+pub fn parse_public_key(value: &str) -> Result<VerifyingKey, String> {
+    from_hex(value.trim())
+        .and_then(|bytes| <[u8; PUBLIC_KEY_LENGTH]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| format!("invalid Ed25519 public key: {}", value))
+}
+
+/// Checks the Ed25519 `signature`, in hexadecimal, of `message`.
+pub fn verify_signature(
+    public_key: &VerifyingKey,
+    message: &[u8],
+    signature: &str,
+) -> Result<(), String> {
+    let signature = from_hex(signature.trim())
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        .ok_or("the signature is not 128 hexadecimal digits")?;
+    public_key
+        .verify_strict(message, &signature)
+        .map_err(|_| "the signature does not match the configuration".to_owned())
+}
+
+/// Checks that a signed `configuration` is for the device with
+/// `mac_address` and not older than the one in use, issued at `issued_after`.
+fn check_binding(
+    configuration: &str,
+    mac_address: &str,
+    issued_after: Option<DateTime<Utc>>,
+) -> Result<(), String> {
+    let binding = serde_json::from_str::<SignedConfigurationBinding>(configuration)
+        .map_err(|e| format!("invalid signed configuration: {}", e))?;
+    match binding.mac_address {
+        Some(signed_for) if signed_for.eq_ignore_ascii_case(mac_address) => {}
+        Some(signed_for) => {
+            return Err(format!("the configuration is signed for {}", signed_for));
+        }
+        None => return Err("the signed configuration has no macAddress".to_owned()),
+    }
+    match (binding.issued_at, issued_after) {
+        (None, _) => Err("the signed configuration has no issuedAt".to_owned()),
+        (Some(issued_at), Some(issued_after)) if issued_at < issued_after => Err(format!(
+            "the configuration issued at {} is older than the one in use, issued at {}",
+            issued_at, issued_after
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The configuration JSON of a body sent by the server, a
+/// `SignedConfiguration` or a bare configuration. With a public key, a
+/// missing or invalid signature, or a configuration signed for another
+/// device or older than `issued_after`, is an error, only logged when
+/// `REJECT_UNVERIFIED_CONFIGURATION` is false; without one, the signature
+/// is not checked.
+pub fn verified_configuration(
+    body: &str,
+    public_key: Option<&str>,
+    mac_address: &str,
+    issued_after: Option<DateTime<Utc>>,
+) -> Result<String, String> {
+    let signed = serde_json::from_str::<SignedConfiguration>(body).ok();
+    if let Some(public_key) = public_key {
+        let verified = parse_public_key(public_key).and_then(|public_key| match &signed {
+            Some(signed) => verify_signature(
+                &public_key,
+                signed.configuration.as_bytes(),
+                &signed.signature,
+            )
+            .and_then(|()| check_binding(&signed.configuration, mac_address, issued_after)),
+            None => Err("the configuration is not signed".to_owned()),
+        });
+        if let Err(message) = verified {
+            if REJECT_UNVERIFIED_CONFIGURATION {
+                return Err(message);
+            }
+            warn!("accepting an unverified configuration: {}", message);
+        }
+    }
+    Ok(signed.map_or_else(|| body.to_owned(), |signed| signed.configuration))
+}
+
+/// With a public key, an `answer` the server cannot sign, such as "configuration
+/// unchanged", is refused like an unsigned configuration: a spoofed server
+/// could otherwise hold back the new configurations.
+pub fn check_unsigned_answer(answer: &str, public_key: Option<&str>) -> Result<(), String> {
+    if public_key.is_none() {
+        return Ok(());
+    }
+    let message = format!("{} is not signed", answer);
+    if REJECT_UNVERIFIED_CONFIGURATION {
+        return Err(message);
+    }
+    warn!("accepting an unverified answer: {}", message);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signer, SigningKey};
+
+    use super::*;
+    use crate::config::config::CONFIGURATION_PUBLIC_KEY;
+    use crate::dto::wifi_settings::WifiSettings;
+    use crate::helper::{provisioning_helper::save_wifi_settings, tls_helper::to_hex};
+    use crate::platform::storage::MemoryStorage;
+
+    // RFC 8032, test vector 1
+    const SECRET_KEY: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+    const PUBLIC_KEY: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    // RFC 8032, test vector 2
+    const OTHER_PUBLIC_KEY: &str =
+        "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c";
+    const MAC_ADDRESS: &str = "02:00:00:00:00:01";
+    const CONFIGURATION: &str =
+        r#"{"macAddress":"02:00:00:00:00:01","issuedAt":"2024-03-04T07:00:00Z","cronList":[]}"#;
+
+    fn sign(configuration: &str) -> String {
+        let secret_key = <[u8; 32]>::try_from(from_hex(SECRET_KEY).unwrap()).unwrap();
+        let signature = SigningKey::from_bytes(&secret_key).sign(configuration.as_bytes());
+        serde_json::to_string(&SignedConfiguration {
+            configuration: configuration.to_owned(),
+            signature: to_hex(&signature.to_bytes()),
+        })
+        .unwrap()
+    }
+
+    fn issued_at(date_time: &str) -> Option<DateTime<Utc>> {
+        Some(DateTime::parse_from_rfc3339(date_time).unwrap().into())
+    }
+
+    #[test]
+    fn rfc_8032_test_vectors_verify() {
+        let public_key = parse_public_key(PUBLIC_KEY).unwrap();
+        assert!(verify_signature(
+            &public_key,
+            b"",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        )
+        .is_ok());
+        let public_key = parse_public_key(OTHER_PUBLIC_KEY).unwrap();
+        assert!(verify_signature(
+            &public_key,
+            &[0x72],
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        )
+        .is_ok());
+        assert!(parse_public_key("d75a98").is_err());
+    }
+
+    #[test]
+    fn signed_configuration_is_verified() {
+        let body = sign(CONFIGURATION);
+        assert_eq!(
+            verified_configuration(&body, Some(PUBLIC_KEY), MAC_ADDRESS, None),
+            Ok(CONFIGURATION.to_owned())
+        );
+        // without a key the signature is not checked
+        assert_eq!(
+            verified_configuration(CONFIGURATION, None, MAC_ADDRESS, None),
+            Ok(CONFIGURATION.to_owned())
+        );
+    }
+
+    #[test]
+    fn tampered_unsigned_or_wrongly_keyed_configurations_are_refused() {
+        let tampered = sign(CONFIGURATION).replace("\\\"cronList\\\":[]", "\\\"cronList\\\":[1]");
+        assert_ne!(tampered, sign(CONFIGURATION));
+        assert!(verified_configuration(&tampered, Some(PUBLIC_KEY), MAC_ADDRESS, None).is_err());
+        assert!(
+            verified_configuration(CONFIGURATION, Some(PUBLIC_KEY), MAC_ADDRESS, None).is_err()
+        );
+        assert!(verified_configuration(
+            &sign(CONFIGURATION),
+            Some(OTHER_PUBLIC_KEY),
+            MAC_ADDRESS,
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn configuration_must_be_for_this_device_and_not_older() {
+        let body = sign(CONFIGURATION);
+        assert!(
+            verified_configuration(&body, Some(PUBLIC_KEY), "02:00:00:00:00:02", None).is_err()
+        );
+        let unbound = sign(r#"{"cronList":[]}"#);
+        assert!(verified_configuration(&unbound, Some(PUBLIC_KEY), MAC_ADDRESS, None).is_err());
+        assert!(verified_configuration(
+            &body,
+            Some(PUBLIC_KEY),
+            MAC_ADDRESS,
+            issued_at("2024-03-04T07:00:01Z")
+        )
+        .is_err());
+        // the same configuration sent again
+        assert!(verified_configuration(
+            &body,
+            Some(PUBLIC_KEY),
+            MAC_ADDRESS,
+            issued_at("2024-03-04T07:00:00Z")
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn unsigned_answers_need_no_key() {
+        assert!(check_unsigned_answer("the answer", None).is_ok());
+        assert!(check_unsigned_answer("the answer", Some(PUBLIC_KEY)).is_err());
+    }
+
+    #[test]
+    fn compiled_in_key_wins_over_the_saved_one() {
+        let mut storage = MemoryStorage::new();
+        assert_eq!(
+            load_configuration_public_key(&mut storage).as_deref(),
+            CONFIGURATION_PUBLIC_KEY
+        );
+        let settings = WifiSettings {
+            configuration_public_key: Some(OTHER_PUBLIC_KEY.to_owned()),
+            ..WifiSettings::default()
+        };
+        save_wifi_settings(&mut storage, &settings).unwrap();
+        assert_eq!(
+            load_configuration_public_key(&mut storage).as_deref(),
+            Some(CONFIGURATION_PUBLIC_KEY.unwrap_or(OTHER_PUBLIC_KEY))
+        );
+    }
+}
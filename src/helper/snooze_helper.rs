@@ -0,0 +1,279 @@
+use chrono::{DateTime, Duration, Utc};
+use log::warn;
+
+use super::alarm_helper::ScheduledAlarm;
+use crate::ConfigurationResponse;
+
+pub struct SnoozeSettings {
+    pub snooze_minutes: u32,
+    pub max_snooze_count: u32,
+    pub dismiss_long_press_milliseconds: u32,
+    pub ring_minutes: u32,
+}
+
+impl SnoozeSettings {
+    /// A snoozed alarm rings again for the duration of `alarm`.
+    pub fn from_configuration(
+        configuration: &ConfigurationResponse,
+        alarm: Option<&ScheduledAlarm>,
+    ) -> SnoozeSettings {
+        SnoozeSettings {
+            snooze_minutes: configuration.snooze_minutes,
+            max_snooze_count: configuration.max_snooze_count,
+            dismiss_long_press_milliseconds: configuration.dismiss_long_press_milliseconds,
+            ring_minutes: alarm.map_or(configuration.alarm_interval_minutes, |alarm| {
+                alarm.duration_minutes
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SnoozeState {
+    Idle,
+    /// `until` is `None` while the scheduled alarm window is open, and set
+    /// when the alarm rings again after a snooze.
+    Ringing {
+        until: Option<DateTime<Utc>>,
+    },
+    Snoozed {
+        until: DateTime<Utc>,
+    },
+    Dismissed,
+}
+
+/// Tracks the snooze button across loop iterations: a short press snoozes
+/// the ringing alarm, a long press dismisses it.
+pub struct SnoozeStateMachine {
+    state: SnoozeState,
+    snooze_count: u32,
+    pressed_since: Option<DateTime<Utc>>,
+    long_press_reported: bool,
+    /// a remote snooze or dismiss, applied at the next update
+    requested: Option<SnoozeCommand>,
+}
+
+/// Snooze or dismiss without the button, from Home Assistant.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SnoozeCommand {
+    Snooze,
+    Dismiss,
+}
+
+impl Default for SnoozeStateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SnoozeStateMachine {
+    pub fn new() -> SnoozeStateMachine {
+        SnoozeStateMachine {
+            state: SnoozeState::Idle,
+            snooze_count: 0,
+            pressed_since: None,
+            long_press_reported: false,
+            requested: None,
+        }
+    }
+
+    pub fn state(&self) -> SnoozeState {
+        self.state
+    }
+
+    /// Acts as a short (snooze) or long (dismiss) press at the next update.
+    pub fn request(&mut self, command: SnoozeCommand) {
+        self.requested = Some(command);
+    }
+
+    /// Advances the state machine and returns whether the buzzers should
+    /// ring. `alarm_window_open` is the result of `is_time_to_buzz` for the
+    /// scheduled alarm, `pressed` the current level of the snooze button.
+    pub fn update(
+        &mut self,
+        now: DateTime<Utc>,
+        alarm_window_open: bool,
+        pressed: bool,
+        settings: &SnoozeSettings,
+    ) -> bool {
+        let press = match (
+            self.read_button(now, pressed, settings),
+            self.requested.take(),
+        ) {
+            (_, Some(SnoozeCommand::Snooze)) => Press::Short,
+            (_, Some(SnoozeCommand::Dismiss)) => Press::Long,
+            (press, None) => press,
+        };
+
+        self.state = match self.state {
+            SnoozeState::Idle if alarm_window_open => {
+                self.snooze_count = 0;
+                SnoozeState::Ringing { until: None }
+            }
+            SnoozeState::Idle => SnoozeState::Idle,
+            SnoozeState::Ringing { .. } if press == Press::Long => {
+                warn!("alarm dismissed");
+                SnoozeState::Dismissed
+            }
+            SnoozeState::Ringing { until } if press == Press::Short => {
+                if self.snooze_count < settings.max_snooze_count {
+                    self.snooze_count += 1;
+                    warn!(
+                        "alarm snoozed ({}/{})",
+                        self.snooze_count, settings.max_snooze_count
+                    );
+                    SnoozeState::Snoozed {
+                        until: now + Duration::minutes(settings.snooze_minutes as i64),
+                    }
+                } else {
+                    warn!("snooze limit reached, long press to dismiss");
+                    SnoozeState::Ringing { until }
+                }
+            }
+            SnoozeState::Ringing { until: None } if !alarm_window_open => SnoozeState::Idle,
+            SnoozeState::Ringing { until: Some(until) } if now >= until => SnoozeState::Idle,
+            ringing @ SnoozeState::Ringing { .. } => ringing,
+            SnoozeState::Snoozed { .. } if press == Press::Long => {
+                warn!("snoozed alarm dismissed");
+                SnoozeState::Dismissed
+            }
+            SnoozeState::Snoozed { until } if now >= until => SnoozeState::Ringing {
+                until: Some(now + Duration::minutes(settings.ring_minutes as i64)),
+            },
+            snoozed @ SnoozeState::Snoozed { .. } => snoozed,
+            SnoozeState::Dismissed if !alarm_window_open => SnoozeState::Idle,
+            SnoozeState::Dismissed => SnoozeState::Dismissed,
+        };
+
+        matches!(self.state, SnoozeState::Ringing { .. })
+    }
+
+    fn read_button(
+        &mut self,
+        now: DateTime<Utc>,
+        pressed: bool,
+        settings: &SnoozeSettings,
+    ) -> Press {
+        let long_press = Duration::milliseconds(settings.dismiss_long_press_milliseconds as i64);
+        match (self.pressed_since, pressed) {
+            (None, true) => {
+                self.pressed_since = Some(now);
+                Press::None
+            }
+            (Some(since), true) if !self.long_press_reported && now - since >= long_press => {
+                // releasing the button afterwards is not a short press
+                self.long_press_reported = true;
+                Press::Long
+            }
+            (Some(_), false) if self.long_press_reported => {
+                self.pressed_since = None;
+                self.long_press_reported = false;
+                Press::None
+            }
+            (Some(_), false) => {
+                self.pressed_since = None;
+                Press::Short
+            }
+            _ => Press::None,
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum Press {
+    None,
+    Short,
+    Long,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const SETTINGS: SnoozeSettings = SnoozeSettings {
+        snooze_minutes: 5,
+        max_snooze_count: 2,
+        dismiss_long_press_milliseconds: 2000,
+        ring_minutes: 1,
+    };
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 4, 7, 0, 0).unwrap() + Duration::seconds(seconds)
+    }
+
+    /// A press from `from` to `to`, polled every second.
+    fn press(snooze: &mut SnoozeStateMachine, from: i64, to: i64, window_open: bool) {
+        for second in from..to {
+            snooze.update(at(second), window_open, true, &SETTINGS);
+        }
+        snooze.update(at(to), window_open, false, &SETTINGS);
+    }
+
+    #[test]
+    fn rings_while_the_alarm_window_is_open() {
+        let mut snooze = SnoozeStateMachine::new();
+        assert!(!snooze.update(at(0), false, false, &SETTINGS));
+        assert!(snooze.update(at(1), true, false, &SETTINGS));
+        assert!(!snooze.update(at(2), false, false, &SETTINGS));
+        assert_eq!(snooze.state(), SnoozeState::Idle);
+    }
+
+    #[test]
+    fn short_press_snoozes_then_rings_again() {
+        let mut snooze = SnoozeStateMachine::new();
+        snooze.update(at(0), true, false, &SETTINGS);
+        press(&mut snooze, 1, 2, true);
+        assert_eq!(
+            snooze.state(),
+            SnoozeState::Snoozed {
+                until: at(2) + Duration::minutes(5)
+            }
+        );
+        assert!(!snooze.update(at(299), false, false, &SETTINGS));
+        assert!(snooze.update(at(302), false, false, &SETTINGS));
+        assert_eq!(
+            snooze.state(),
+            SnoozeState::Ringing {
+                until: Some(at(302) + Duration::minutes(1))
+            }
+        );
+        assert!(!snooze.update(at(362), false, false, &SETTINGS));
+    }
+
+    #[test]
+    fn snooze_count_is_limited() {
+        let mut snooze = SnoozeStateMachine::new();
+        snooze.update(at(0), true, false, &SETTINGS);
+        press(&mut snooze, 1, 2, true);
+        snooze.update(at(302), true, false, &SETTINGS);
+        press(&mut snooze, 303, 304, true);
+        snooze.update(at(604), true, false, &SETTINGS);
+        press(&mut snooze, 605, 606, true);
+        assert!(matches!(snooze.state(), SnoozeState::Ringing { .. }));
+    }
+
+    #[test]
+    fn long_press_dismisses_without_snoozing_on_release() {
+        let mut snooze = SnoozeStateMachine::new();
+        snooze.update(at(0), true, false, &SETTINGS);
+        press(&mut snooze, 1, 4, true);
+        assert_eq!(snooze.state(), SnoozeState::Dismissed);
+        assert!(!snooze.update(at(5), true, false, &SETTINGS));
+        assert!(!snooze.update(at(6), false, false, &SETTINGS));
+        assert_eq!(snooze.state(), SnoozeState::Idle);
+    }
+
+    #[test]
+    fn remote_commands_act_as_presses() {
+        let mut snooze = SnoozeStateMachine::new();
+        snooze.update(at(0), true, false, &SETTINGS);
+        snooze.request(SnoozeCommand::Snooze);
+        snooze.update(at(1), true, false, &SETTINGS);
+        assert!(matches!(snooze.state(), SnoozeState::Snoozed { .. }));
+        snooze.request(SnoozeCommand::Dismiss);
+        snooze.update(at(2), true, false, &SETTINGS);
+        assert_eq!(snooze.state(), SnoozeState::Dismissed);
+    }
+}
@@ -0,0 +1,209 @@
+use std::net::Ipv4Addr;
+
+use crate::dto::static_ip_settings::StaticIpSettings;
+
+// what `esp_netif` accepts
+const MAX_DNS_SERVERS: usize = 2;
+const MAX_HOSTNAME_LENGTH: usize = 30;
+// /31 and /32 leave no room for a gateway
+const MAX_PREFIX_LENGTH: u32 = 30;
+const DEFAULT_NETMASK: Ipv4Addr = Ipv4Addr::new(255, 255, 255, 0);
+
+/// Reads the address settings of the setup page: no address means DHCP,
+/// the netmask defaults to /24 and the DNS server to the gateway.
+pub fn parse_static_ip(
+    address: &str,
+    gateway: &str,
+    netmask: &str,
+    dns_servers: &str,
+) -> Result<Option<StaticIpSettings>, String> {
+    if address.trim().is_empty() {
+        return Ok(None);
+    }
+    let address = parse_address("IP address", address)?;
+    let gateway = parse_address("gateway", gateway)?;
+    let netmask = match netmask.trim().is_empty() {
+        true => DEFAULT_NETMASK,
+        false => parse_address("netmask", netmask)?,
+    };
+    let mut dns_servers = dns_servers
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|server| !server.is_empty())
+        .map(|server| parse_address("DNS server", server))
+        .collect::<Result<Vec<_>, _>>()?;
+    if dns_servers.is_empty() {
+        dns_servers.push(gateway);
+    }
+    let settings = StaticIpSettings {
+        address,
+        gateway,
+        netmask,
+        dns_servers,
+    };
+    validate_static_ip(&settings)?;
+    Ok(Some(settings))
+}
+
+fn parse_address(name: &str, value: &str) -> Result<Ipv4Addr, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("the {} must be an IPv4 address like 192.168.1.50", name))
+}
+
+/// Checks that the address and the gateway are hosts of the same subnet
+/// and that the DNS servers can be queried.
+pub fn validate_static_ip(settings: &StaticIpSettings) -> Result<(), String> {
+    let prefix = netmask_prefix(settings.netmask)
+        .filter(|prefix| (1..=MAX_PREFIX_LENGTH).contains(prefix))
+        .ok_or("the netmask must be contiguous, between /1 and /30, like 255.255.255.0")?;
+    if !is_host_address(settings.address, prefix) {
+        return Err(format!(
+            "{} is not a host address of a /{} subnet",
+            settings.address, prefix
+        ));
+    }
+    if !is_host_address(settings.gateway, prefix) || settings.gateway == settings.address {
+        return Err(format!(
+            "the gateway {} is not another host address",
+            settings.gateway
+        ));
+    }
+    let mask = u32::from(settings.netmask);
+    if u32::from(settings.address) & mask != u32::from(settings.gateway) & mask {
+        return Err(format!(
+            "the gateway {} is not in the subnet of {}/{}",
+            settings.gateway, settings.address, prefix
+        ));
+    }
+    if settings.dns_servers.is_empty() || settings.dns_servers.len() > MAX_DNS_SERVERS {
+        return Err(format!("1 to {} DNS servers are required", MAX_DNS_SERVERS));
+    }
+    if let Some(server) = settings
+        .dns_servers
+        .iter()
+        .find(|server| !is_unicast(**server))
+    {
+        return Err(format!(
+            "the DNS server {} is not a unicast address",
+            server
+        ));
+    }
+    Ok(())
+}
+
+/// Length of the prefix of a contiguous netmask, e.g. 24 for 255.255.255.0.
+pub fn netmask_prefix(netmask: Ipv4Addr) -> Option<u32> {
+    let mask = u32::from(netmask);
+    let prefix = mask.leading_ones();
+    (mask.checked_shl(prefix).unwrap_or(0) == 0).then_some(prefix)
+}
+
+fn is_unicast(address: Ipv4Addr) -> bool {
+    !(address.is_unspecified()
+        || address.is_loopback()
+        || address.is_multicast()
+        || address.is_broadcast())
+}
+
+/// A unicast address that is neither the network nor the broadcast address
+/// of its subnet.
+fn is_host_address(address: Ipv4Addr, prefix: u32) -> bool {
+    let host_mask = u32::MAX >> prefix;
+    let host = u32::from(address) & host_mask;
+    is_unicast(address) && host != 0 && host != host_mask
+}
+
+/// The DHCP hostname: `DEVICE_NAME` in lowercase with dashes, followed by
+/// the end of the MAC address, e.g. `alarm-clock-3a7f`.
+pub fn dhcp_hostname(device_name: &str, mac_address: &str) -> String {
+    let digits: String = mac_address.chars().filter(|c| *c != ':').collect();
+    let suffix = digits[digits.len().saturating_sub(4)..].to_lowercase();
+    let name: String = device_name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let name: Vec<&str> = name.split('-').filter(|part| !part.is_empty()).collect();
+    let mut name = name.join("-");
+    name.truncate(MAX_HOSTNAME_LENGTH - suffix.len() - 1);
+    let name = name.trim_end_matches('-');
+    match name.is_empty() {
+        true => format!("esp32-{}", suffix),
+        false => format!("{}-{}", name, suffix),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_get_their_defaults() {
+        assert_eq!(parse_static_ip(" ", "", "", ""), Ok(None));
+        let settings = parse_static_ip("192.168.1.50", "192.168.1.1", "", "")
+            .unwrap()
+            .unwrap();
+        assert_eq!(settings.netmask, Ipv4Addr::new(255, 255, 255, 0));
+        assert_eq!(settings.dns_servers, [Ipv4Addr::new(192, 168, 1, 1)]);
+
+        let settings = parse_static_ip("10.0.3.7", "10.0.0.1", "255.255.0.0", "1.1.1.1, 9.9.9.9")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            settings.dns_servers,
+            [Ipv4Addr::new(1, 1, 1, 1), Ipv4Addr::new(9, 9, 9, 9)]
+        );
+    }
+
+    #[test]
+    fn inconsistent_addressing_is_rejected() {
+        for (address, gateway, netmask, dns_servers) in [
+            ("192.168.1.300", "192.168.1.1", "", ""),
+            ("192.168.1.50", "", "", ""),
+            ("192.168.1.50", "192.168.1.1", "255.0.255.0", ""),
+            ("192.168.1.50", "192.168.1.1", "255.255.255.254", ""),
+            ("192.168.1.0", "192.168.1.1", "", ""),
+            ("192.168.1.255", "192.168.1.1", "", ""),
+            ("192.168.1.50", "192.168.1.50", "", ""),
+            ("192.168.1.50", "192.168.2.1", "", ""),
+            ("192.168.1.50", "192.168.1.1", "", "1.1.1.1 8.8.8.8 9.9.9.9"),
+            ("192.168.1.50", "192.168.1.1", "", "224.0.0.251"),
+            ("192.168.1.50", "192.168.1.1", "", "dns.example"),
+        ] {
+            assert!(
+                parse_static_ip(address, gateway, netmask, dns_servers).is_err(),
+                "{} {} {} {}",
+                address,
+                gateway,
+                netmask,
+                dns_servers
+            );
+        }
+    }
+
+    #[test]
+    fn netmask_prefix_of_contiguous_masks_only() {
+        assert_eq!(netmask_prefix(Ipv4Addr::new(255, 255, 255, 0)), Some(24));
+        assert_eq!(netmask_prefix(Ipv4Addr::new(255, 255, 252, 0)), Some(22));
+        assert_eq!(netmask_prefix(Ipv4Addr::new(255, 255, 255, 255)), Some(32));
+        assert_eq!(netmask_prefix(Ipv4Addr::new(0, 0, 0, 0)), Some(0));
+        assert_eq!(netmask_prefix(Ipv4Addr::new(255, 0, 255, 0)), None);
+    }
+
+    #[test]
+    fn hostname_is_a_valid_dns_label() {
+        assert_eq!(
+            dhcp_hostname("Alarm Clock", "02:00:00:00:3A:7F"),
+            "alarm-clock-3a7f"
+        );
+        assert_eq!(
+            dhcp_hostname("--Élisys  clock!", "3a7f"),
+            "lisys-clock-3a7f"
+        );
+        assert_eq!(dhcp_hostname("!!!", "3a7f"), "esp32-3a7f");
+        let hostname = dhcp_hostname("A very long name for a small alarm clock", "3a7f");
+        assert!(hostname.len() <= MAX_HOSTNAME_LENGTH);
+        assert_eq!(hostname, "a-very-long-name-for-a-sm-3a7f");
+    }
+}
@@ -0,0 +1,486 @@
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, LocalResult, NaiveDate, NaiveDateTime, Offset,
+    TimeZone, Utc,
+};
+use log::error;
+
+use crate::ConfigurationResponse;
+
+/// POSIX TZ strings of the IANA zones the device knows by name, the same
+/// rules the tz database exports for embedded systems. Any other zone can be
+/// configured with its POSIX TZ string directly.
+const IANA_TIMEZONES: &[(&str, &str)] = &[
+    ("UTC", "UTC0"),
+    ("Etc/UTC", "UTC0"),
+    ("Europe/Amsterdam", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Athens", "EET-2EEST,M3.5.0/3,M10.5.0/4"),
+    ("Europe/Berlin", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Brussels", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Bucharest", "EET-2EEST,M3.5.0/3,M10.5.0/4"),
+    ("Europe/Budapest", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Copenhagen", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Dublin", "GMT0IST,M3.5.0/1,M10.5.0"),
+    ("Europe/Helsinki", "EET-2EEST,M3.5.0/3,M10.5.0/4"),
+    ("Europe/Istanbul", "<+03>-3"),
+    ("Europe/Kyiv", "EET-2EEST,M3.5.0/3,M10.5.0/4"),
+    ("Europe/Lisbon", "WET0WEST,M3.5.0/1,M10.5.0"),
+    ("Europe/London", "GMT0BST,M3.5.0/1,M10.5.0"),
+    ("Europe/Madrid", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Moscow", "MSK-3"),
+    ("Europe/Oslo", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Paris", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Prague", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Rome", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Stockholm", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Vienna", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Warsaw", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Zurich", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("America/Anchorage", "AKST9AKDT,M3.2.0,M11.1.0"),
+    ("America/Argentina/Buenos_Aires", "<-03>3"),
+    ("America/Bogota", "<-05>5"),
+    ("America/Chicago", "CST6CDT,M3.2.0,M11.1.0"),
+    ("America/Denver", "MST7MDT,M3.2.0,M11.1.0"),
+    ("America/Lima", "<-05>5"),
+    ("America/Los_Angeles", "PST8PDT,M3.2.0,M11.1.0"),
+    ("America/Mexico_City", "CST6"),
+    ("America/New_York", "EST5EDT,M3.2.0,M11.1.0"),
+    ("America/Phoenix", "MST7"),
+    ("America/Santiago", "<-04>4<-03>,M9.1.6/24,M4.1.6/24"),
+    ("America/Sao_Paulo", "<-03>3"),
+    ("America/Toronto", "EST5EDT,M3.2.0,M11.1.0"),
+    ("America/Vancouver", "PST8PDT,M3.2.0,M11.1.0"),
+    ("Asia/Bangkok", "<+07>-7"),
+    ("Asia/Dubai", "<+04>-4"),
+    ("Asia/Hong_Kong", "HKT-8"),
+    ("Asia/Jakarta", "WIB-7"),
+    ("Asia/Jerusalem", "IST-2IDT,M3.4.4/26,M10.5.0"),
+    ("Asia/Karachi", "PKT-5"),
+    ("Asia/Kolkata", "IST-5:30"),
+    ("Asia/Manila", "PST-8"),
+    ("Asia/Seoul", "KST-9"),
+    ("Asia/Shanghai", "CST-8"),
+    ("Asia/Singapore", "<+08>-8"),
+    ("Asia/Tehran", "<+0330>-3:30"),
+    ("Asia/Tokyo", "JST-9"),
+    ("Australia/Adelaide", "ACST-9:30ACDT,M10.1.0,M4.1.0/3"),
+    ("Australia/Brisbane", "AEST-10"),
+    ("Australia/Melbourne", "AEST-10AEDT,M10.1.0,M4.1.0/3"),
+    ("Australia/Perth", "AWST-8"),
+    ("Australia/Sydney", "AEST-10AEDT,M10.1.0,M4.1.0/3"),
+    ("Africa/Cairo", "EET-2EEST,M4.5.5/0,M10.5.4/24"),
+    ("Africa/Johannesburg", "SAST-2"),
+    ("Africa/Lagos", "WAT-1"),
+    ("Africa/Nairobi", "EAT-3"),
+    ("Atlantic/Reykjavik", "GMT0"),
+    ("Pacific/Auckland", "NZST-12NZDT,M9.5.0,M4.1.0/3"),
+    ("Pacific/Honolulu", "HST10"),
+];
+
+// POSIX default when a DST name is given without rules
+const DEFAULT_DST_RULES: &str = "M3.2.0,M11.1.0";
+const DEFAULT_TRANSITION_SECONDS: i32 = 2 * 60 * 60;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TransitionDate {
+    /// `Jn`: day 1..=365, February 29 is never counted
+    Julian(u32),
+    /// `n`: day 0..=365, February 29 is counted
+    ZeroBased(u32),
+    /// `Mm.w.d`: day `d` (0 = Sunday) of week `w` (5 = last) of month `m`
+    MonthWeekDay(u32, u32, u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Transition {
+    date: TransitionDate,
+    /// local time of the transition, may be negative or beyond 24 hours
+    seconds: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct DaylightSaving {
+    offset: FixedOffset,
+    start: Transition,
+    end: Transition,
+}
+
+/// Time zone described by a POSIX TZ string, e.g. `CET-1CEST,M3.5.0,M10.5.0/3`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PosixTimeZone {
+    standard_offset: FixedOffset,
+    daylight_saving: Option<DaylightSaving>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UserTimeZone {
+    Fixed(FixedOffset),
+    Posix(PosixTimeZone),
+}
+
+impl UserTimeZone {
+    pub fn offset_at(&self, instant: &DateTime<Utc>) -> FixedOffset {
+        match self {
+            UserTimeZone::Fixed(offset) => *offset,
+            UserTimeZone::Posix(timezone) => timezone.offset_at(&instant.naive_utc()),
+        }
+    }
+
+    pub fn local_time(&self, instant: &DateTime<Utc>) -> DateTime<FixedOffset> {
+        instant.with_timezone(&self.offset_at(instant))
+    }
+
+    /// Maps a wall-clock time to the instants showing it: none inside the
+    /// hour skipped when DST starts, two inside the hour repeated when it ends.
+    pub fn resolve_local(&self, local: &NaiveDateTime) -> LocalResult<DateTime<FixedOffset>> {
+        let offsets = match self {
+            UserTimeZone::Fixed(offset) => return offset.from_local_datetime(local),
+            UserTimeZone::Posix(timezone) => timezone.offsets(),
+        };
+        let mut candidates: Vec<DateTime<FixedOffset>> = offsets
+            .iter()
+            .filter_map(|offset| {
+                let instant = *local - Duration::seconds(offset.local_minus_utc() as i64);
+                let instant = Utc.from_utc_datetime(&instant);
+                match self.offset_at(&instant) == *offset {
+                    true => Some(instant.with_timezone(offset)),
+                    false => None,
+                }
+            })
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        match candidates.as_slice() {
+            [] => LocalResult::None,
+            [single] => LocalResult::Single(*single),
+            [earliest, .., latest] => LocalResult::Ambiguous(*earliest, *latest),
+        }
+    }
+}
+
+impl PosixTimeZone {
+    fn offsets(&self) -> Vec<FixedOffset> {
+        let mut offsets = vec![self.standard_offset];
+        if let Some(daylight_saving) = self.daylight_saving {
+            offsets.push(daylight_saving.offset);
+        }
+        offsets
+    }
+
+    fn offset_at(&self, instant: &NaiveDateTime) -> FixedOffset {
+        match self.daylight_saving {
+            Some(daylight_saving) if self.is_daylight_saving(&daylight_saving, instant) => {
+                daylight_saving.offset
+            }
+            _ => self.standard_offset,
+        }
+    }
+
+    fn is_daylight_saving(
+        &self,
+        daylight_saving: &DaylightSaving,
+        instant: &NaiveDateTime,
+    ) -> bool {
+        let standard_offset = Duration::seconds(self.standard_offset.local_minus_utc() as i64);
+        let daylight_saving_offset =
+            Duration::seconds(daylight_saving.offset.local_minus_utc() as i64);
+        let year = (*instant + standard_offset).year();
+        // the start is expressed in standard time, the end in daylight saving time
+        let start = transition_local_time(&daylight_saving.start, year) - standard_offset;
+        let end = transition_local_time(&daylight_saving.end, year) - daylight_saving_offset;
+        if start < end {
+            start <= *instant && *instant < end
+        } else {
+            // southern hemisphere: daylight saving time spans the new year
+            !(end <= *instant && *instant < start)
+        }
+    }
+}
+
+fn transition_local_time(transition: &Transition, year: i32) -> NaiveDateTime {
+    let date = match transition.date {
+        TransitionDate::Julian(day) => {
+            let is_leap = NaiveDate::from_ymd_opt(year, 2, 29).is_some();
+            let day = if is_leap && day >= 60 { day + 1 } else { day };
+            NaiveDate::from_yo_opt(year, day).unwrap()
+        }
+        TransitionDate::ZeroBased(day) => NaiveDate::from_yo_opt(year, day + 1)
+            .unwrap_or_else(|| NaiveDate::from_ymd_opt(year, 12, 31).unwrap()),
+        TransitionDate::MonthWeekDay(month, week, weekday) => {
+            let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+            let first_weekday = first.weekday().num_days_from_sunday();
+            let mut day = 1 + (weekday + 7 - first_weekday) % 7 + (week - 1) * 7;
+            while NaiveDate::from_ymd_opt(year, month, day).is_none() {
+                day -= 7;
+            }
+            NaiveDate::from_ymd_opt(year, month, day).unwrap()
+        }
+    };
+    date.and_hms_opt(0, 0, 0).unwrap() + Duration::seconds(transition.seconds as i64)
+}
+
+/// Accepts an IANA zone name known by the device or a POSIX TZ string.
+pub fn parse_timezone(name: &str) -> Option<UserTimeZone> {
+    let posix = IANA_TIMEZONES
+        .iter()
+        .find(|(iana_name, _)| *iana_name == name)
+        .map_or(name, |(_, posix)| *posix);
+    parse_posix_timezone(posix).map(UserTimeZone::Posix)
+}
+
+/// Uses `timezone` when the server sends one, `timezoneSeconds` otherwise,
+/// and UTC when neither is valid.
+pub fn get_user_timezone(configuration: &ConfigurationResponse) -> UserTimeZone {
+    let fixed = UserTimeZone::Fixed(
+        FixedOffset::east_opt(configuration.timezone_seconds).unwrap_or_else(|| {
+            error!(
+                "invalid timezone of {} seconds, using UTC",
+                configuration.timezone_seconds
+            );
+            Utc.fix()
+        }),
+    );
+    match &configuration.timezone {
+        None => fixed,
+        Some(name) => parse_timezone(name).unwrap_or_else(|| {
+            error!(
+                "unknown timezone {}, using {} seconds",
+                name, configuration.timezone_seconds
+            );
+            fixed
+        }),
+    }
+}
+
+fn parse_posix_timezone(value: &str) -> Option<PosixTimeZone> {
+    let mut parser = PosixParser { rest: value };
+    parser.name()?;
+    // POSIX offsets are west of Greenwich: CET-1 is UTC+1, and must stay
+    // within a day
+    let standard_offset = FixedOffset::east_opt(-parser.time()?)?;
+    if parser.rest.is_empty() {
+        return Some(PosixTimeZone {
+            standard_offset,
+            daylight_saving: None,
+        });
+    }
+    parser.name()?;
+    let offset = match parser.rest.starts_with(',') || parser.rest.is_empty() {
+        true => FixedOffset::east_opt(standard_offset.local_minus_utc() + 60 * 60)?,
+        false => FixedOffset::east_opt(-parser.time()?)?,
+    };
+    let rules = match parser.rest.strip_prefix(',') {
+        Some(rules) => rules,
+        None if parser.rest.is_empty() => DEFAULT_DST_RULES,
+        None => return None,
+    };
+    let (start, end) = rules.split_once(',')?;
+    Some(PosixTimeZone {
+        standard_offset,
+        daylight_saving: Some(DaylightSaving {
+            offset,
+            start: parse_transition(start)?,
+            end: parse_transition(end)?,
+        }),
+    })
+}
+
+fn parse_transition(value: &str) -> Option<Transition> {
+    let (date, time) = match value.split_once('/') {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+    let date = if let Some(day) = date.strip_prefix('J') {
+        TransitionDate::Julian(day.parse().ok().filter(|day| (1..=365).contains(day))?)
+    } else if let Some(month_week_day) = date.strip_prefix('M') {
+        let mut fields = month_week_day
+            .split('.')
+            .map(|field| field.parse::<u32>().ok());
+        let month = fields.next()??;
+        let week = fields.next()??;
+        let weekday = fields.next()??;
+        if fields.next().is_some()
+            || !(1..=12).contains(&month)
+            || !(1..=5).contains(&week)
+            || weekday > 6
+        {
+            return None;
+        }
+        TransitionDate::MonthWeekDay(month, week, weekday)
+    } else {
+        TransitionDate::ZeroBased(date.parse().ok().filter(|day| *day <= 365)?)
+    };
+    let seconds = match time {
+        None => DEFAULT_TRANSITION_SECONDS,
+        Some(time) => {
+            let mut parser = PosixParser { rest: time };
+            let seconds = parser.time()?;
+            if !parser.rest.is_empty() {
+                return None;
+            }
+            seconds
+        }
+    };
+    Some(Transition { date, seconds })
+}
+
+struct PosixParser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> PosixParser<'a> {
+    /// `CET`, or `<+03>` for names that are not alphabetic
+    fn name(&mut self) -> Option<&'a str> {
+        if let Some(quoted) = self.rest.strip_prefix('<') {
+            let (name, rest) = quoted.split_once('>')?;
+            self.rest = rest;
+            return Some(name);
+        }
+        let length = self
+            .rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(self.rest.len());
+        if length < 3 {
+            return None;
+        }
+        let (name, rest) = self.rest.split_at(length);
+        self.rest = rest;
+        Some(name)
+    }
+
+    /// `[+-]hh[:mm[:ss]]` in seconds
+    fn time(&mut self) -> Option<i32> {
+        let length = self
+            .rest
+            .find(|c: char| !(c.is_ascii_digit() || c == ':' || c == '+' || c == '-'))
+            .unwrap_or(self.rest.len());
+        let (time, rest) = self.rest.split_at(length);
+        let (sign, time) = match time.strip_prefix('-') {
+            Some(time) => (-1, time),
+            None => (1, time.strip_prefix('+').unwrap_or(time)),
+        };
+        let mut seconds = 0;
+        let mut fields = 0;
+        for (index, field) in time.split(':').enumerate() {
+            let value: i32 = field.parse().ok()?;
+            if index > 2 || (index > 0 && value > 59) || value > 167 {
+                return None;
+            }
+            seconds += value * [3600, 60, 1][index];
+            fields += 1;
+        }
+        if fields == 0 {
+            return None;
+        }
+        self.rest = rest;
+        Some(sign * seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(date_time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(date_time)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn local(date_time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date_time, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn offset_hours(timezone: &UserTimeZone, date_time: &str) -> f32 {
+        timezone.offset_at(&utc(date_time)).local_minus_utc() as f32 / 3600.0
+    }
+
+    #[test]
+    fn posix_strings_follow_daylight_saving_rules() {
+        let berlin = parse_timezone("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        assert_eq!(offset_hours(&berlin, "2024-01-15T12:00:00Z"), 1.0);
+        assert_eq!(offset_hours(&berlin, "2024-07-15T12:00:00Z"), 2.0);
+        // DST starts at 01:00 UTC on the last Sunday of March
+        assert_eq!(offset_hours(&berlin, "2024-03-31T00:59:00Z"), 1.0);
+        assert_eq!(offset_hours(&berlin, "2024-03-31T01:00:00Z"), 2.0);
+
+        let sydney = parse_timezone("Australia/Sydney").unwrap();
+        assert_eq!(offset_hours(&sydney, "2024-01-15T12:00:00Z"), 11.0);
+        assert_eq!(offset_hours(&sydney, "2024-07-15T12:00:00Z"), 10.0);
+
+        let new_york = parse_timezone("EST5EDT").unwrap();
+        assert_eq!(offset_hours(&new_york, "2024-01-15T12:00:00Z"), -5.0);
+        assert_eq!(offset_hours(&new_york, "2024-07-15T12:00:00Z"), -4.0);
+
+        let kolkata = parse_timezone("IST-5:30").unwrap();
+        assert_eq!(offset_hours(&kolkata, "2024-07-15T12:00:00Z"), 5.5);
+        let istanbul = parse_timezone("<+03>-3").unwrap();
+        assert_eq!(offset_hours(&istanbul, "2024-07-15T12:00:00Z"), 3.0);
+    }
+
+    #[test]
+    fn iana_names_map_to_their_posix_rules() {
+        assert_eq!(
+            parse_timezone("Europe/Berlin"),
+            parse_timezone("CET-1CEST,M3.5.0,M10.5.0/3")
+        );
+        assert!(parse_timezone("Mars/Olympus_Mons").is_none());
+    }
+
+    #[test]
+    fn malformed_strings_and_offsets_of_a_day_or_more_are_rejected() {
+        for value in [
+            "",
+            "X-1",
+            "CET",
+            "CET-1CEST,M13.5.0,M10.5.0",
+            "CET-1CEST,M3.5.0",
+            "XXX-30",
+            "XXX-24",
+            "XXX-23CEST-25,M3.5.0,M10.5.0",
+        ] {
+            assert!(parse_timezone(value).is_none(), "{}", value);
+        }
+        assert!(parse_timezone("XXX-23:59").is_some());
+    }
+
+    #[test]
+    fn skipped_hour_has_no_instant_and_repeated_hour_has_two() {
+        let berlin = parse_timezone("Europe/Berlin").unwrap();
+        assert_eq!(
+            berlin.resolve_local(&local("2024-03-31 02:30")),
+            LocalResult::None
+        );
+        match berlin.resolve_local(&local("2024-10-27 02:30")) {
+            LocalResult::Ambiguous(earliest, latest) => {
+                assert_eq!(earliest.with_timezone(&Utc), utc("2024-10-27T00:30:00Z"));
+                assert_eq!(latest.with_timezone(&Utc), utc("2024-10-27T01:30:00Z"));
+            }
+            other => panic!("expected two instants, got {:?}", other),
+        }
+        match berlin.resolve_local(&local("2024-07-15 07:00")) {
+            LocalResult::Single(instant) => {
+                assert_eq!(instant.with_timezone(&Utc), utc("2024-07-15T05:00:00Z"))
+            }
+            other => panic!("expected one instant, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_configured_timezones_fall_back() {
+        let mut configuration = crate::helper::configuration_helper::get_default_configuration(
+            anyhow::Error::msg("test"),
+        );
+        configuration.timezone_seconds = 2 * 60 * 60;
+        configuration.timezone = Some("XXX-30".to_owned());
+        assert_eq!(
+            get_user_timezone(&configuration),
+            UserTimeZone::Fixed(FixedOffset::east_opt(2 * 60 * 60).unwrap())
+        );
+        configuration.timezone = None;
+        configuration.timezone_seconds = 30 * 60 * 60;
+        assert_eq!(
+            get_user_timezone(&configuration),
+            UserTimeZone::Fixed(Utc.fix())
+        );
+    }
+}
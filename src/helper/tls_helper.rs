@@ -0,0 +1,236 @@
+use sha2::{Digest, Sha256};
+
+use crate::config::config::{REQUIRE_HTTPS, SERVER_CA_CERTIFICATE, SERVER_CERTIFICATE_PINS};
+
+const SHA256_LENGTH: usize = 32;
+// DER tags of the X.509 fields read here
+const DER_SEQUENCE: u8 = 0x30;
+const DER_EXPLICIT_VERSION: u8 = 0xa0;
+// serial number, signature algorithm, issuer, validity and subject
+const FIELDS_BEFORE_PUBLIC_KEY: usize = 5;
+
+pub type CertificatePin = [u8; SHA256_LENGTH];
+
+/// How the `https://` servers are authenticated.
+#[derive(Clone, Debug, Default)]
+pub struct TlsSettings {
+    /// PEM of the CA the server certificate must chain to, `None` for the
+    /// CA bundle of ESP-IDF.
+    pub ca_certificate: Option<String>,
+    /// SHA-256 of the accepted server certificates or public keys; when
+    /// there are some they replace the CA check.
+    pub pins: Vec<CertificatePin>,
+    pub require_https: bool,
+}
+
+/// The settings of `config.rs`, an error for an invalid pin.
+pub fn configured_tls_settings() -> Result<TlsSettings, String> {
+    let pins = SERVER_CERTIFICATE_PINS
+        .iter()
+        .map(|pin| parse_pin(pin))
+        .collect::<Result<Vec<CertificatePin>, String>>()?;
+    Ok(TlsSettings {
+        ca_certificate: SERVER_CA_CERTIFICATE.map(str::to_owned),
+        pins,
+        require_https: REQUIRE_HTTPS,
+    })
+}
+
+/// 64 hexadecimal digits, as printed by `sha256sum`, or separated by colons
+/// as printed by `openssl x509 -fingerprint -sha256`.
+pub fn parse_pin(value: &str) -> Result<CertificatePin, String> {
+    let digits: String = value.trim().chars().filter(|c| *c != ':').collect();
+    from_hex(&digits)
+        .and_then(|bytes| CertificatePin::try_from(bytes).ok())
+        .ok_or_else(|| format!("invalid SHA-256 pin: {}", value))
+}
+
+/// Refuses the `http://` URLs when HTTPS is required.
+pub fn check_url_scheme(url: &str, settings: &TlsSettings) -> Result<(), String> {
+    match settings.require_https && !url.starts_with("https://") {
+        true => Err(format!("refusing plain HTTP to {}, HTTPS is required", url)),
+        false => Ok(()),
+    }
+}
+
+/// Accepts the server certificate (DER) when its SHA-256 or the one of its
+/// public key is among the pins, telling both hashes otherwise so that the
+/// right one can be pinned.
+pub fn check_pinned_certificate(certificate: &[u8], pins: &[CertificatePin]) -> Result<(), String> {
+    let certificate_hash: CertificatePin = Sha256::digest(certificate).into();
+    let public_key_hash: Option<CertificatePin> =
+        subject_public_key_info(certificate).map(|public_key| Sha256::digest(public_key).into());
+    if pins
+        .iter()
+        .any(|pin| *pin == certificate_hash || Some(*pin) == public_key_hash)
+    {
+        return Ok(());
+    }
+    Err(format!(
+        "the server certificate matches none of the pinned hashes (certificate {}, public key {})",
+        to_hex(&certificate_hash),
+        public_key_hash.map_or("unreadable".to_owned(), |hash| to_hex(&hash))
+    ))
+}
+
+/// The DER `SubjectPublicKeyInfo` of an X.509 certificate, which follows
+/// the optional version and 5 other fields of the `TBSCertificate`.
+pub fn subject_public_key_info(certificate: &[u8]) -> Option<&[u8]> {
+    let (tag, certificate, _) = der_element(certificate)?;
+    if tag != DER_SEQUENCE {
+        return None;
+    }
+    let (tag, mut fields, _) = der_element(certificate)?;
+    if tag != DER_SEQUENCE {
+        return None;
+    }
+    if fields.first() == Some(&DER_EXPLICIT_VERSION) {
+        fields = der_element(fields)?.2;
+    }
+    for _ in 0..FIELDS_BEFORE_PUBLIC_KEY {
+        fields = der_element(fields)?.2;
+    }
+    let (tag, _, rest) = der_element(fields)?;
+    if tag != DER_SEQUENCE {
+        return None;
+    }
+    Some(&fields[..fields.len() - rest.len()])
+}
+
+/// Tag, content and what follows the DER element at the start of `input`.
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *input.first()?;
+    let first_length = *input.get(1)? as usize;
+    let (length, header_length) = match first_length < 0x80 {
+        true => (first_length, 2),
+        false => {
+            // long form: the number of length bytes, then the length
+            let count = first_length & 0x7f;
+            if count == 0 || count > 4 {
+                return None;
+            }
+            let length = input
+                .get(2..2 + count)?
+                .iter()
+                .fold(0usize, |length, byte| (length << 8) | *byte as usize);
+            (length, 2 + count)
+        }
+    };
+    let end = header_length.checked_add(length)?;
+    Some((tag, input.get(header_length..end)?, &input[end..]))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// `None` for an odd number of digits or another character.
+pub fn from_hex(digits: &str) -> Option<Vec<u8>> {
+    if digits.len() % 2 != 0 || !digits.is_ascii() {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&digits[index..index + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut element = vec![tag];
+        match content.len() {
+            length if length < 0x80 => element.push(length as u8),
+            length => element.extend_from_slice(&[0x81, length as u8]),
+        }
+        element.extend_from_slice(content);
+        element
+    }
+
+    /// Public key info long enough for a long form length.
+    fn public_key_info() -> Vec<u8> {
+        let algorithm = der(DER_SEQUENCE, &der(0x06, &[0x2b, 0x65, 0x70]));
+        let key = der(0x03, &[0x42; 140]);
+        der(DER_SEQUENCE, &[algorithm, key].concat())
+    }
+
+    /// Skeleton X.509 certificate: only the structure is read.
+    fn certificate(with_version: bool) -> Vec<u8> {
+        let mut fields = Vec::new();
+        if with_version {
+            fields.extend(der(DER_EXPLICIT_VERSION, &der(0x02, &[2])));
+        }
+        fields.extend(der(0x02, &[0x01, 0x23]));
+        for _ in 0..FIELDS_BEFORE_PUBLIC_KEY - 1 {
+            fields.extend(der(DER_SEQUENCE, &[]));
+        }
+        fields.extend(public_key_info());
+        fields.extend(der(0xa3, &[]));
+        let tbs_certificate = der(DER_SEQUENCE, &fields);
+        let signature = der(0x03, &[0x00, 0x01]);
+        der(
+            DER_SEQUENCE,
+            &[tbs_certificate, der(DER_SEQUENCE, &[]), signature].concat(),
+        )
+    }
+
+    fn sha256(bytes: &[u8]) -> CertificatePin {
+        Sha256::digest(bytes).into()
+    }
+
+    #[test]
+    fn public_key_info_is_found_with_or_without_version() {
+        let expected = public_key_info();
+        for with_version in [true, false] {
+            assert_eq!(
+                subject_public_key_info(&certificate(with_version)),
+                Some(expected.as_slice())
+            );
+        }
+        assert_eq!(subject_public_key_info(&certificate(true)[..40]), None);
+        assert_eq!(subject_public_key_info(&[0x02, 0x01, 0x00]), None);
+    }
+
+    #[test]
+    fn certificate_or_public_key_pins_match() {
+        let certificate = certificate(true);
+        let certificate_pin = sha256(&certificate);
+        let public_key_pin = sha256(&public_key_info());
+        let other_pin = sha256(b"another server");
+
+        assert!(check_pinned_certificate(&certificate, &[other_pin, certificate_pin]).is_ok());
+        assert!(check_pinned_certificate(&certificate, &[public_key_pin]).is_ok());
+        let error = check_pinned_certificate(&certificate, &[other_pin]).unwrap_err();
+        assert!(error.contains(&to_hex(&certificate_pin)));
+        assert!(error.contains(&to_hex(&public_key_pin)));
+        assert!(check_pinned_certificate(&certificate, &[]).is_err());
+    }
+
+    #[test]
+    fn pins_are_parsed_with_or_without_colons() {
+        let pin = sha256(b"server");
+        let hex = to_hex(&pin);
+        assert_eq!(parse_pin(&hex), Ok(pin));
+        let colons = pin
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<String>>()
+            .join(":");
+        assert_eq!(parse_pin(&format!(" {} ", colons)), Ok(pin));
+        assert!(parse_pin(&hex[2..]).is_err());
+        assert!(parse_pin(&hex.replace('a', "g")).is_err());
+    }
+
+    #[test]
+    fn plain_http_is_refused_when_https_is_required() {
+        let settings = TlsSettings {
+            require_https: true,
+            ..TlsSettings::default()
+        };
+        assert!(check_url_scheme("http://10.0.0.9/config", &settings).is_err());
+        assert!(check_url_scheme("https://10.0.0.9/config", &settings).is_ok());
+        assert!(check_url_scheme("http://10.0.0.9/config", &TlsSettings::default()).is_ok());
+    }
+}
@@ -0,0 +1,106 @@
+use std::cmp::Reverse;
+
+use crate::dto::{wifi_credentials::WifiCredentials, wifi_settings::WifiSettings};
+use crate::platform::wifi::ScannedNetwork;
+
+/// The saved networks in the order they are tried. The networks found by
+/// the scan come first, the strongest signal first. The others, e.g. hidden
+/// SSIDs, follow. Ties go to the last connected network, then to the
+/// highest priority; equal networks keep the order in which they were saved.
+pub fn rank_networks<'a>(
+    settings: &'a WifiSettings,
+    scanned: &[ScannedNetwork],
+) -> Vec<&'a WifiCredentials> {
+    let mut ranked: Vec<(usize, &WifiCredentials)> = settings.networks.iter().enumerate().collect();
+    ranked.sort_by_key(|(index, credentials)| {
+        // the strongest access point of the SSID
+        let rssi = scanned
+            .iter()
+            .filter(|network| network.ssid == credentials.ssid)
+            .map(|network| network.rssi)
+            .max();
+        let is_last_connected = settings.last_connected.as_deref() == Some(&credentials.ssid);
+        Reverse((
+            rssi.is_some(),
+            rssi,
+            is_last_connected,
+            credentials.priority,
+            Reverse(*index),
+        ))
+    });
+    ranked
+        .into_iter()
+        .map(|(_, credentials)| credentials)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(networks: &[(&str, i32)], last_connected: Option<&str>) -> WifiSettings {
+        WifiSettings {
+            networks: networks
+                .iter()
+                .map(|(ssid, priority)| WifiCredentials {
+                    ssid: ssid.to_string(),
+                    password: String::new(),
+                    priority: *priority,
+                    static_ip: None,
+                })
+                .collect(),
+            last_connected: last_connected.map(str::to_owned),
+            ..WifiSettings::default()
+        }
+    }
+
+    fn scan(networks: &[(&str, i32)]) -> Vec<ScannedNetwork> {
+        networks
+            .iter()
+            .map(|(ssid, rssi)| ScannedNetwork {
+                ssid: ssid.to_string(),
+                rssi: *rssi,
+            })
+            .collect()
+    }
+
+    fn ranked(settings: &WifiSettings, scanned: &[ScannedNetwork]) -> Vec<String> {
+        rank_networks(settings, scanned)
+            .into_iter()
+            .map(|credentials| credentials.ssid.clone())
+            .collect()
+    }
+
+    #[test]
+    fn strongest_network_in_range_comes_first() {
+        let settings = settings(
+            &[("office", 10), ("backup", 0), ("home", 5)],
+            Some("office"),
+        );
+        let scanned = scan(&[("office", -80), ("backup", -50), ("neighbour", -30)]);
+        assert_eq!(ranked(&settings, &scanned), ["backup", "office", "home"]);
+    }
+
+    #[test]
+    fn strongest_access_point_of_an_ssid_counts() {
+        let settings = settings(&[("office", 0), ("backup", 0)], None);
+        let scanned = scan(&[("office", -85), ("backup", -60), ("office", -55)]);
+        assert_eq!(ranked(&settings, &scanned), ["office", "backup"]);
+    }
+
+    #[test]
+    fn ties_go_to_the_last_connected_then_to_the_priority() {
+        let settings = settings(&[("a", 0), ("b", 5), ("c", 1), ("d", 1)], Some("c"));
+        let scanned = scan(&[("a", -60), ("b", -60), ("c", -60), ("d", -60)]);
+        assert_eq!(ranked(&settings, &scanned), ["c", "b", "d", "a"]);
+    }
+
+    #[test]
+    fn networks_out_of_range_follow() {
+        let settings = settings(&[("hidden", 0), ("home", 9), ("office", 0)], Some("home"));
+        let scanned = scan(&[("office", -90)]);
+        assert_eq!(ranked(&settings, &scanned), ["office", "home", "hidden"]);
+        // a failed scan keeps the saved networks
+        assert_eq!(ranked(&settings, &[]), ["home", "hidden", "office"]);
+    }
+}
@@ -1,14 +1,25 @@
+// some settings are only read on the ESP32
+#[cfg_attr(not(target_os = "espidf"), allow(dead_code))]
 mod config;
 mod dto;
 mod helper;
+mod platform;
 mod service;
+#[cfg(not(target_os = "espidf"))]
+mod simulator;
 
 use dto::config_response::Configuration as ConfigurationResponse;
-use service::orchestrator_service::orchestrate;
 
+#[cfg(target_os = "espidf")]
 fn main() {
     esp_idf_svc::sys::link_patches();
     esp_idf_svc::log::EspLogger::initialize_default();
 
-    orchestrate();
+    let mut device = platform::esp::board::take_device();
+    service::orchestrator_service::orchestrate(&mut device, None);
+}
+
+#[cfg(not(target_os = "espidf"))]
+fn main() {
+    simulator::runner::simulate();
 }
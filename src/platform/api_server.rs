@@ -0,0 +1,60 @@
+use serde::Serialize;
+
+use crate::dto::api_error_response::ApiErrorResponse;
+
+/// A request received by the local REST API or by the setup portal.
+pub struct ApiRequest {
+    pub method: String,
+    /// without the query string
+    pub path: String,
+    pub body: String,
+}
+
+pub struct ApiResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl ApiResponse {
+    pub fn json<T: Serialize>(status: u16, body: &T) -> ApiResponse {
+        ApiResponse {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_string(body).unwrap(),
+        }
+    }
+
+    pub fn html(status: u16, body: String) -> ApiResponse {
+        ApiResponse {
+            status,
+            content_type: "text/html; charset=utf-8",
+            body,
+        }
+    }
+
+    pub fn empty(status: u16) -> ApiResponse {
+        ApiResponse {
+            status,
+            content_type: "application/json",
+            body: String::new(),
+        }
+    }
+
+    pub fn error(status: u16, message: &str) -> ApiResponse {
+        ApiResponse::json(status, &ApiErrorResponse::new(message.to_owned()))
+    }
+}
+
+/// Embedded HTTP server of the local REST API or of the setup portal.
+/// Requests are received in the background and answered from the loop that
+/// owns the state.
+pub trait ApiServer {
+    fn start(&mut self) -> Result<(), anyhow::Error>;
+
+    /// Closes the port, which another server may then use.
+    fn stop(&mut self);
+
+    /// Answers with `handler` the requests received since the last call.
+    fn serve(&mut self, handler: &mut dyn FnMut(&ApiRequest) -> ApiResponse);
+}
@@ -0,0 +1,19 @@
+use chrono::{DateTime, Utc};
+
+/// Source of the current time and of blocking delays.
+///
+/// On the ESP32 this is the system clock synchronized through SNTP; the
+/// simulator provides a virtual clock that can be fast-forwarded.
+pub trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+
+    fn delay_ms(&mut self, ms: u32);
+
+    fn synchronize(&mut self, one_shot: bool) -> Result<(), String>;
+
+    /// When `synchronize` last completed, `None` before.
+    fn synchronized_at(&self) -> Option<DateTime<Utc>>;
+
+    /// Milliseconds since boot, unaffected by the synchronization.
+    fn uptime_ms(&self) -> u64;
+}
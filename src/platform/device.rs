@@ -0,0 +1,32 @@
+use super::{
+    api_server::ApiServer,
+    clock::Clock,
+    gpio::ButtonProvider,
+    http::{HttpClient, ServerUrl},
+    mdns::Mdns,
+    mqtt::MqttClient,
+    storage::Storage,
+    system::System,
+    tone::ToneOutput,
+    wifi::Wifi,
+};
+
+/// Everything the orchestrator needs from the hardware, so that the same
+/// alarm logic runs on the ESP32 and in the host simulator.
+pub struct Device {
+    pub clock: Box<dyn Clock>,
+    pub wifi: Box<dyn Wifi>,
+    pub http: Box<dyn HttpClient>,
+    /// where `http` sends the requests for the Elisys server
+    pub server_url: ServerUrl,
+    pub mdns: Box<dyn Mdns>,
+    pub mqtt: Box<dyn MqttClient>,
+    pub buzzer1: Box<dyn ToneOutput>,
+    pub buzzer2: Box<dyn ToneOutput>,
+    pub buttons: Box<dyn ButtonProvider>,
+    pub storage: Box<dyn Storage>,
+    pub api: Box<dyn ApiServer>,
+    /// serves the setup page while the device is an access point
+    pub portal: Box<dyn ApiServer>,
+    pub system: Box<dyn System>,
+}
@@ -0,0 +1,80 @@
+use super::{
+    esp_api_server::EspApiServer, esp_clock::EspClock, esp_gpio::EspButtons, esp_http::EspHttp,
+    esp_mdns::EspMdnsResponder, esp_mqtt::EspMqtt, esp_storage::EspStorage, esp_system::EspSystem,
+    esp_tone::EspToneOutput, esp_wifi::EspWifiNetwork,
+};
+use crate::config::config::{API_SERVER_PORT, MQTT_BROKER_URL};
+use crate::helper::provisioning_helper::load_wifi_settings;
+use crate::helper::tls_helper::configured_tls_settings;
+use crate::platform::{
+    device::Device,
+    http::{RebasedHttpClient, ServerUrl},
+    storage::{MemoryStorage, Storage},
+};
+use esp_idf_svc::{
+    eventloop::EspSystemEventLoop, hal::peripherals::Peripherals, nvs::EspDefaultNvsPartition,
+    wifi::EspWifi,
+};
+use log::error;
+use std::{cell::RefCell, rc::Rc};
+
+// phones check their captive portal over plain HTTP; API_SERVER_PORT differs
+const PORTAL_PORT: u16 = 80;
+
+pub fn take_device() -> Device {
+    let peripherals = Peripherals::take().unwrap();
+
+    let sys_loop = EspSystemEventLoop::take().unwrap();
+    let nvs = EspDefaultNvsPartition::take().unwrap();
+
+    let mut storage: Box<dyn Storage> = match EspStorage::new(nvs.clone()) {
+        Ok(storage) => Box::new(storage),
+        Err(e) => {
+            error!(
+                "NVS not available, settings will not survive a reboot: {:?}",
+                e
+            );
+            Box::new(MemoryStorage::new())
+        }
+    };
+
+    // until the server is looked for with mDNS
+    let server_url: ServerUrl = Rc::new(RefCell::new(load_wifi_settings(&mut *storage).server_url));
+
+    let wifi_driver = EspWifi::new(peripherals.modem, sys_loop, Some(nvs)).unwrap();
+
+    Device {
+        clock: Box::new(EspClock::new()),
+        wifi: Box::new(EspWifiNetwork::new(wifi_driver)),
+        http: Box::new(RebasedHttpClient::new(
+            Box::new(
+                EspHttp::new(configured_tls_settings().expect("invalid TLS settings")).unwrap(),
+            ),
+            server_url.clone(),
+        )),
+        server_url,
+        mdns: Box::new(EspMdnsResponder::new()),
+        mqtt: Box::new(EspMqtt::new(MQTT_BROKER_URL)),
+        buzzer1: Box::new(
+            EspToneOutput::new(
+                peripherals.ledc.timer0,
+                peripherals.ledc.channel0,
+                peripherals.pins.gpio5,
+            )
+            .unwrap(),
+        ),
+        buzzer2: Box::new(
+            EspToneOutput::new(
+                peripherals.ledc.timer1,
+                peripherals.ledc.channel1,
+                peripherals.pins.gpio15,
+            )
+            .unwrap(),
+        ),
+        buttons: Box::new(EspButtons),
+        storage,
+        api: Box::new(EspApiServer::new(API_SERVER_PORT, "/api/*")),
+        portal: Box::new(EspApiServer::new(PORTAL_PORT, "/*")),
+        system: Box::new(EspSystem),
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender, SyncSender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use crate::config::config::{API_MAX_REQUEST_SIZE, API_RESPONSE_TIMEOUT_MILLISECONDS};
+use crate::helper::response_body_helper::{read_response_body, ResponseBodyError};
+use crate::platform::api_server::{ApiRequest, ApiResponse, ApiServer};
+use embedded_svc::{
+    http::{Headers, Method},
+    io::{Read, Write},
+};
+use esp_idf_svc::http::server::{Configuration, EspHttpServer};
+use log::info;
+
+type PendingRequest = (ApiRequest, SyncSender<ApiResponse>);
+
+/// The ESP-IDF server runs its handlers in its own task: each request is
+/// handed over to the orchestrator, and the handler waits for the answer.
+pub struct EspApiServer {
+    port: u16,
+    uri: &'static str,
+    server: Option<EspHttpServer>,
+    sender: Arc<Mutex<Sender<PendingRequest>>>,
+    receiver: Receiver<PendingRequest>,
+}
+
+impl EspApiServer {
+    /// `uri` may end with a `*` wildcard.
+    pub fn new(port: u16, uri: &'static str) -> EspApiServer {
+        let (sender, receiver) = mpsc::channel();
+        EspApiServer {
+            port,
+            uri,
+            server: None,
+            sender: Arc::new(Mutex::new(sender)),
+            receiver,
+        }
+    }
+}
+
+impl ApiServer for EspApiServer {
+    fn start(&mut self) -> Result<(), anyhow::Error> {
+        self.server = None;
+        let mut server = EspHttpServer::new(&Configuration {
+            http_port: self.port,
+            uri_match_wildcard: true,
+            ..Default::default()
+        })?;
+        for (method, name) in [
+            (Method::Get, "GET"),
+            (Method::Post, "POST"),
+            (Method::Delete, "DELETE"),
+        ] {
+            let sender = self.sender.clone();
+            server.fn_handler(self.uri, method, move |mut request| {
+                let path = request.uri().split('?').next().unwrap_or("").to_owned();
+                let content_length = request.content_len().map(|length| length as usize);
+                let body = read_response_body(
+                    |buf| request.read(buf).map_err(|e| format!("{:?}", e)),
+                    content_length,
+                    API_MAX_REQUEST_SIZE,
+                );
+                let response = match body {
+                    Ok(body) => {
+                        let (reply, response) = mpsc::sync_channel(1);
+                        let api_request = ApiRequest {
+                            method: name.to_owned(),
+                            path,
+                            body,
+                        };
+                        sender.lock().unwrap().send((api_request, reply))?;
+                        response
+                            .recv_timeout(Duration::from_millis(
+                                API_RESPONSE_TIMEOUT_MILLISECONDS as u64,
+                            ))
+                            .unwrap_or_else(|_| ApiResponse::error(503, "the alarm clock is busy"))
+                    }
+                    Err(e @ ResponseBodyError::TooLarge { .. }) => {
+                        ApiResponse::error(413, &e.to_string())
+                    }
+                    Err(e) => ApiResponse::error(400, &e.to_string()),
+                };
+                request
+                    .into_response(
+                        response.status,
+                        None,
+                        &[("Content-Type", response.content_type)],
+                    )?
+                    .write_all(response.body.as_bytes())?;
+                Ok(())
+            })?;
+        }
+        info!("HTTP server listening on {} port {}", self.uri, self.port);
+        self.server = Some(server);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if self.server.take().is_some() {
+            info!("HTTP server on port {} stopped", self.port);
+        }
+    }
+
+    fn serve(&mut self, handler: &mut dyn FnMut(&ApiRequest) -> ApiResponse) {
+        while let Ok((request, reply)) = self.receiver.try_recv() {
+            // the handler may have given up waiting
+            let _ = reply.send(handler(&request));
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::platform::clock::Clock;
+use chrono::{DateTime, Utc};
+use esp_idf_svc::sntp;
+use esp_idf_svc::{hal::delay::FreeRtos, sntp::SyncStatus};
+use log::info;
+use log::warn;
+use std::time::Instant;
+
+pub struct EspClock {
+    boot: Instant,
+    synchronized_at: Option<DateTime<Utc>>,
+}
+
+impl Default for EspClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EspClock {
+    pub fn new() -> EspClock {
+        EspClock {
+            boot: Instant::now(),
+            synchronized_at: None,
+        }
+    }
+}
+
+impl Clock for EspClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        FreeRtos::delay_ms(ms);
+    }
+
+    fn synchronize(&mut self, one_shot: bool) -> Result<(), String> {
+        if synchronize_clock(one_shot)? {
+            self.synchronized_at = Some(Utc::now());
+        }
+        Ok(())
+    }
+
+    fn synchronized_at(&self) -> Option<DateTime<Utc>> {
+        self.synchronized_at
+    }
+
+    fn uptime_ms(&self) -> u64 {
+        self.boot.elapsed().as_millis() as u64
+    }
+}
+
+/// Returns whether the synchronization completed, a one-shot attempt can
+/// give up before.
+pub fn synchronize_clock(one_shot: bool) -> Result<bool, String> {
+    let sntp = sntp::EspSntp::new_default();
+    if sntp.is_err() {
+        return Err("Sync error".into());
+    }
+    let sntp = sntp.unwrap();
+    info!("SNTP initialized, waiting for status!");
+    let mut attempts = 0;
+    while sntp.get_sync_status() != SyncStatus::Completed {
+        FreeRtos::delay_ms(100);
+        warn!("waiting for clock synchronization...");
+        attempts += 1;
+        if one_shot && attempts > 3000 {
+            return Ok(false);
+        }
+        if attempts > 300000 {
+            return Err("clock sync: to many attempts".into());
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,44 @@
+use crate::platform::gpio::{Button, ButtonProvider, OutputPin};
+use esp_idf_svc::hal::gpio::{AnyIOPin, Input, Output, Pin, PinDriver, Pull};
+use log::error;
+
+impl<T: Pin> OutputPin for PinDriver<'_, T, Output> {
+    fn set_high(&mut self) {
+        PinDriver::set_high(self).ok();
+    }
+
+    fn set_low(&mut self) {
+        PinDriver::set_low(self).ok();
+    }
+}
+
+/// Buttons are wired between the GPIO and ground, with the internal pull-up.
+impl<T: Pin> Button for PinDriver<'_, T, Input> {
+    fn is_pressed(&mut self) -> bool {
+        self.is_low()
+    }
+}
+
+pub struct EspButtons;
+
+impl ButtonProvider for EspButtons {
+    fn button(&mut self, gpio: i32) -> Option<Box<dyn Button>> {
+        // GPIO5 and GPIO15 are already driven by the buzzers
+        if gpio == 5 || gpio == 15 {
+            error!("GPIO{} is reserved for the buzzers", gpio);
+            return None;
+        }
+        let pin = unsafe { AnyIOPin::new(gpio) };
+        let mut button = match PinDriver::input(pin) {
+            Err(e) => {
+                error!("unable to use GPIO{} as input: {:?}", gpio, e);
+                return None;
+            }
+            Ok(button) => button,
+        };
+        if button.set_pull(Pull::Up).is_err() {
+            error!("unable to enable the pull-up on GPIO{}", gpio);
+        }
+        Some(Box::new(button))
+    }
+}
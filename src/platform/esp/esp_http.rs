@@ -0,0 +1,155 @@
+use super::esp_tls::{attach_tls_verification, install_tls_verification, take_tls_rejection};
+use crate::config::config::HTTP_MAX_RESPONSE_SIZE;
+use crate::helper::response_body_helper::read_response_body;
+use crate::helper::tls_helper::{check_url_scheme, TlsSettings};
+use crate::platform::http::{HttpClient, HttpResponse, HttpTransportError};
+use anyhow::Error as StandardError;
+use embedded_svc::{
+    http::client::Client,
+    io::{Read, Write},
+};
+use esp_idf_svc::{
+    http::client::{Configuration, EspHttpConnection},
+    io::EspIOError,
+};
+use esp_idf_sys::{ESP_ERR_HTTP_EAGAIN, ESP_ERR_TIMEOUT};
+use log::{error, info};
+
+// the ESP-IDF client cannot list the response headers, only these are kept
+const RESPONSE_HEADERS: &[&str] = &["ETag"];
+
+pub struct EspHttp {
+    tls: TlsSettings,
+}
+
+impl EspHttp {
+    pub fn new(tls: TlsSettings) -> Result<EspHttp, StandardError> {
+        install_tls_verification(&tls)?;
+        Ok(EspHttp { tls })
+    }
+}
+
+impl HttpClient for EspHttp {
+    fn post(
+        &mut self,
+        url: &str,
+        headers: &[(&str, &str)],
+        payload: &[u8],
+    ) -> Result<HttpResponse, StandardError> {
+        if let Err(message) = check_url_scheme(url, &self.tls) {
+            error!("{}", message);
+            return Err(HttpTransportError::Tls(message).into());
+        }
+        let configuration = Configuration {
+            crt_bundle_attach: Some(attach_tls_verification),
+            ..Default::default()
+        };
+        let client = Client::wrap(EspHttpConnection::new(&configuration)?);
+        // a rejection left by an earlier connection
+        take_tls_rejection();
+        let result = post_request(payload, client, url, headers);
+        match (result, take_tls_rejection()) {
+            (Err(_), Some(rejection)) => {
+                let message = format!("{}: {}", url, rejection);
+                error!("{}", message);
+                Err(HttpTransportError::Tls(message).into())
+            }
+            (result, _) => result,
+        }
+    }
+}
+
+fn post_request(
+    payload: &[u8],
+    mut client: Client<EspHttpConnection>,
+    url: &str,
+    extra_headers: &[(&str, &str)],
+) -> Result<HttpResponse, StandardError> {
+    let content_length_header = format!("{}", payload.len());
+    let mut headers = vec![
+        ("content-type", "application/json"),
+        ("content-length", &*content_length_header),
+    ];
+    headers.extend_from_slice(extra_headers);
+
+    let request = client.post(url, &headers);
+
+    let mut request = match request {
+        Err(e) => {
+            let message = format!("connection error: {:?}", e);
+            return Err(transport_error(message, is_timeout(&e)));
+        }
+        Ok(request) => request,
+    };
+
+    if request.write_all(payload).is_err() {
+        let message = format!("connection error while trying to write all");
+        return Err(transport_error(message, false));
+    }
+    if request.flush().is_err() {
+        let message = format!("connection error while trying to flush");
+        return Err(transport_error(message, false));
+    }
+    info!("-> POST {}", url);
+    let mut response = match request.submit() {
+        Err(e) => {
+            let message = format!("connection error while trying to read response: {:?}", e);
+            return Err(transport_error(message, is_timeout(&e)));
+        }
+        Ok(response) => response,
+    };
+
+    let status = response.status();
+    info!("<- {}", status);
+    let response_headers = RESPONSE_HEADERS
+        .iter()
+        .filter_map(|name| {
+            response
+                .header(name)
+                .map(|value| (name.to_string(), value.to_owned()))
+        })
+        .collect();
+    // esp_http_client decodes chunked bodies, which have no Content-Length
+    let is_chunked = response
+        .header("Transfer-Encoding")
+        .is_some_and(|value| value.eq_ignore_ascii_case("chunked"));
+    let content_length = match is_chunked {
+        true => None,
+        false => response
+            .header("Content-Length")
+            .and_then(|value| value.parse().ok()),
+    };
+    let body = read_response_body(
+        |buf| response.read(buf).map_err(|e| format!("{:?}", e)),
+        content_length,
+        HTTP_MAX_RESPONSE_SIZE,
+    );
+
+    match body {
+        Err(e) => {
+            error!("connection error while trying to read response: {}", e);
+            Err(e.into())
+        }
+        Ok(body) => {
+            info!("received message: {}", body);
+            Ok(HttpResponse {
+                status,
+                headers: response_headers,
+                body,
+            })
+        }
+    }
+}
+
+fn transport_error(message: String, timed_out: bool) -> StandardError {
+    error!("{}", message);
+    match timed_out {
+        true => HttpTransportError::Timeout(message).into(),
+        false => HttpTransportError::Connection(message).into(),
+    }
+}
+
+fn is_timeout(e: &EspIOError) -> bool {
+    let code = e.0.code();
+    code == ESP_ERR_TIMEOUT as i32 || code == ESP_ERR_HTTP_EAGAIN as i32
+}
@@ -0,0 +1,87 @@
+use std::{net::IpAddr, time::Duration};
+
+use crate::platform::mdns::{Mdns, MdnsService};
+use esp_idf_svc::mdns::{EspMdns, Interface, Protocol, QueryResult};
+
+// at most that many servers are reported by a browse
+const MAX_RESULTS: usize = 8;
+
+/// The ESP-IDF mDNS component, started on first use.
+pub struct EspMdnsResponder {
+    mdns: Option<EspMdns>,
+}
+
+impl EspMdnsResponder {
+    pub fn new() -> EspMdnsResponder {
+        EspMdnsResponder { mdns: None }
+    }
+
+    fn mdns(&mut self) -> Result<&mut EspMdns, anyhow::Error> {
+        if self.mdns.is_none() {
+            self.mdns = Some(EspMdns::take()?);
+        }
+        Ok(self.mdns.as_mut().unwrap())
+    }
+}
+
+impl Mdns for EspMdnsResponder {
+    fn advertise(
+        &mut self,
+        hostname: &str,
+        instance: &str,
+        service: &str,
+        protocol: &str,
+        port: u16,
+        txt: &[(&str, &str)],
+    ) -> Result<(), anyhow::Error> {
+        let mdns = self.mdns()?;
+        mdns.set_hostname(hostname)?;
+        mdns.set_instance_name(instance)?;
+        mdns.add_service(Some(instance), service, protocol, port, txt)?;
+        Ok(())
+    }
+
+    fn browse(
+        &mut self,
+        service: &str,
+        protocol: &str,
+        timeout_ms: u32,
+    ) -> Result<Vec<MdnsService>, anyhow::Error> {
+        let mut results: Vec<QueryResult> = (0..MAX_RESULTS)
+            .map(|_| QueryResult {
+                instance_name: None,
+                hostname: None,
+                port: 0,
+                txt: Vec::new(),
+                addr: Vec::new(),
+                interface: Interface::STA,
+                ip_protocol: Protocol::V4,
+            })
+            .collect();
+        let found = self.mdns()?.query_ptr(
+            service,
+            protocol,
+            Duration::from_millis(timeout_ms as u64),
+            MAX_RESULTS,
+            &mut results,
+        )?;
+        Ok(results
+            .into_iter()
+            .take(found)
+            .map(|result| MdnsService {
+                instance: result.instance_name.unwrap_or_default(),
+                hostname: result.hostname.unwrap_or_default(),
+                addresses: result
+                    .addr
+                    .iter()
+                    .filter_map(|address| match address {
+                        IpAddr::V4(address) => Some(*address),
+                        IpAddr::V6(_) => None,
+                    })
+                    .collect(),
+                port: result.port,
+                txt: result.txt,
+            })
+            .collect())
+    }
+}
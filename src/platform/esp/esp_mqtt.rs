@@ -0,0 +1,111 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::{
+    config::config::HTTP_MAX_RESPONSE_SIZE,
+    platform::mqtt::{MqttClient, MqttLastWill, MqttMessage},
+};
+use embedded_svc::mqtt::client::{Event, QoS};
+use esp_idf_svc::mqtt::client::{EspMqttClient, LwtConfiguration, MqttClientConfiguration};
+use log::{error, info, warn};
+
+/// The ESP-IDF client runs in its own task: its events are handed over to
+/// the orchestrator through `connected` and `received`.
+pub struct EspMqtt {
+    url: String,
+    client: Option<EspMqttClient<'static>>,
+    connected: Arc<AtomicBool>,
+    received: Arc<Mutex<VecDeque<MqttMessage>>>,
+}
+
+impl EspMqtt {
+    pub fn new(url: &str) -> EspMqtt {
+        EspMqtt {
+            url: url.to_owned(),
+            client: None,
+            connected: Arc::new(AtomicBool::new(false)),
+            received: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+}
+
+impl MqttClient for EspMqtt {
+    fn connect(&mut self, client_id: &str, last_will: &MqttLastWill) -> Result<(), anyhow::Error> {
+        if self.client.is_some() {
+            return Ok(());
+        }
+        let configuration = MqttClientConfiguration {
+            client_id: Some(client_id),
+            // a configuration must arrive in one piece, the client hands
+            // larger messages over in fragments
+            buffer_size: HTTP_MAX_RESPONSE_SIZE,
+            out_buffer_size: HTTP_MAX_RESPONSE_SIZE,
+            lwt: Some(LwtConfiguration {
+                topic: &last_will.topic,
+                payload: last_will.payload.as_bytes(),
+                qos: QoS::AtLeastOnce,
+                retain: true,
+            }),
+            ..Default::default()
+        };
+        let connected = self.connected.clone();
+        let received = self.received.clone();
+        let client = EspMqttClient::new(&self.url, &configuration, move |event| match event {
+            Ok(Event::Connected(_)) => {
+                info!("connected to the MQTT broker");
+                connected.store(true, Ordering::SeqCst);
+            }
+            Ok(Event::Disconnected) => {
+                warn!("disconnected from the MQTT broker");
+                connected.store(false, Ordering::SeqCst);
+            }
+            Ok(Event::Received(message)) => match message.topic() {
+                Some(topic) => received.lock().unwrap().push_back(MqttMessage {
+                    topic: topic.to_owned(),
+                    payload: String::from_utf8_lossy(message.data()).into_owned(),
+                }),
+                None => warn!(
+                    "ignoring a fragmented MQTT message, larger than {} bytes",
+                    HTTP_MAX_RESPONSE_SIZE
+                ),
+            },
+            Err(e) => error!("MQTT error: {:?}", e),
+            _ => {}
+        })?;
+        self.client = Some(client);
+        Ok(())
+    }
+
+    fn is_connected(&mut self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    fn subscribe(&mut self, topic: &str) -> Result<(), anyhow::Error> {
+        match &mut self.client {
+            Some(client) => {
+                client.subscribe(topic, QoS::AtLeastOnce)?;
+                Ok(())
+            }
+            None => Err(anyhow::Error::msg("MQTT client not started")),
+        }
+    }
+
+    fn publish(&mut self, topic: &str, payload: &str, retain: bool) -> Result<(), anyhow::Error> {
+        match &mut self.client {
+            Some(client) => {
+                client.publish(topic, QoS::AtLeastOnce, retain, payload.as_bytes())?;
+                Ok(())
+            }
+            None => Err(anyhow::Error::msg("MQTT client not started")),
+        }
+    }
+
+    fn receive(&mut self) -> Option<MqttMessage> {
+        self.received.lock().unwrap().pop_front()
+    }
+}
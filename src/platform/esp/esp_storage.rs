@@ -0,0 +1,37 @@
+use crate::platform::storage::Storage;
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+
+const NAMESPACE: &str = "elisys";
+
+/// Values are stored as blobs: NVS strings are limited to 4000 bytes, not
+/// enough for a long cron list.
+pub struct EspStorage {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl EspStorage {
+    pub fn new(partition: EspDefaultNvsPartition) -> Result<EspStorage, anyhow::Error> {
+        Ok(EspStorage {
+            nvs: EspNvs::new(partition, NAMESPACE, true)?,
+        })
+    }
+}
+
+impl Storage for EspStorage {
+    fn get(&mut self, key: &str) -> Result<Option<String>, anyhow::Error> {
+        let length = match self.nvs.blob_len(key)? {
+            None => return Ok(None),
+            Some(length) => length,
+        };
+        let mut buffer = vec![0u8; length];
+        match self.nvs.get_blob(key, &mut buffer)? {
+            None => Ok(None),
+            Some(value) => Ok(Some(String::from_utf8(value.to_vec())?)),
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), anyhow::Error> {
+        self.nvs.set_blob(key, value.as_bytes())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,12 @@
+use crate::platform::system::System;
+use esp_idf_svc::hal::reset;
+use log::warn;
+
+pub struct EspSystem;
+
+impl System for EspSystem {
+    fn restart(&mut self) {
+        warn!("restarting...");
+        reset::restart();
+    }
+}
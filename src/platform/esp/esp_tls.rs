@@ -0,0 +1,161 @@
+use std::{
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    ptr, slice,
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::anyhow;
+use esp_idf_sys::{
+    esp_crt_bundle_attach, esp_err_t, mbedtls_ssl_conf_authmode, mbedtls_ssl_conf_ca_chain,
+    mbedtls_ssl_conf_verify, mbedtls_ssl_config, mbedtls_x509_crt, mbedtls_x509_crt_init,
+    mbedtls_x509_crt_parse, mbedtls_x509_crt_verify_info, ESP_OK, MBEDTLS_SSL_VERIFY_REQUIRED,
+    MBEDTLS_X509_BADCERT_NOT_TRUSTED,
+};
+
+use crate::helper::tls_helper::{check_pinned_certificate, CertificatePin, TlsSettings};
+
+/// What the TLS connections check. It is set once at boot, the mbedTLS
+/// callbacks have no context to carry it.
+struct Verification {
+    ca_chain: Option<CaChain>,
+    pins: Vec<CertificatePin>,
+}
+
+/// The parsed `SERVER_CA_CERTIFICATE`, never freed.
+struct CaChain(*mut mbedtls_x509_crt);
+
+// only read by mbedTLS once parsed
+unsafe impl Send for CaChain {}
+unsafe impl Sync for CaChain {}
+
+static VERIFICATION: OnceLock<Verification> = OnceLock::new();
+// why the last server certificate was rejected, for the HTTP client
+static REJECTION: Mutex<Option<String>> = Mutex::new(None);
+
+/// Parses the CA certificate and keeps the pins for the next connections.
+pub fn install_tls_verification(settings: &TlsSettings) -> Result<(), anyhow::Error> {
+    let ca_chain = match &settings.ca_certificate {
+        None => None,
+        Some(pem) => {
+            // mbedTLS tells PEM from DER by the terminating NUL
+            let pem = CString::new(pem.as_str())?;
+            let bytes = pem.as_bytes_with_nul();
+            let chain = Box::into_raw(Box::new(unsafe { std::mem::zeroed::<mbedtls_x509_crt>() }));
+            let result = unsafe {
+                mbedtls_x509_crt_init(chain);
+                mbedtls_x509_crt_parse(chain, bytes.as_ptr(), bytes.len())
+            };
+            if result != 0 {
+                return Err(anyhow!(
+                    "invalid SERVER_CA_CERTIFICATE: mbedTLS error -0x{:04x}",
+                    -result
+                ));
+            }
+            Some(CaChain(chain))
+        }
+    };
+    VERIFICATION
+        .set(Verification {
+            ca_chain,
+            pins: settings.pins.clone(),
+        })
+        .map_err(|_| anyhow!("TLS verification already installed"))
+}
+
+/// Why the server certificate of the last connection was rejected, if it was.
+pub fn take_tls_rejection() -> Option<String> {
+    REJECTION.lock().ok()?.take()
+}
+
+/// Given as `crt_bundle_attach` to the HTTP client, which calls it with the
+/// mbedTLS configuration of each `https://` connection: pins, else the CA
+/// certificate, else the CA bundle.
+pub unsafe extern "C" fn attach_tls_verification(conf: *mut c_void) -> esp_err_t {
+    let ssl_conf = conf as *mut mbedtls_ssl_config;
+    let verification = match VERIFICATION.get() {
+        Some(verification) => verification,
+        None => return esp_crt_bundle_attach(conf),
+    };
+    if !verification.pins.is_empty() {
+        // the bundle sets the placeholder CA without which mbedTLS does not
+        // call the verify callback
+        let result = esp_crt_bundle_attach(conf);
+        if result != ESP_OK as esp_err_t {
+            return result;
+        }
+        mbedtls_ssl_conf_verify(ssl_conf, Some(verify_pinned_certificate), ptr::null_mut());
+    } else if let Some(ca_chain) = &verification.ca_chain {
+        mbedtls_ssl_conf_ca_chain(ssl_conf, ca_chain.0, ptr::null_mut());
+        mbedtls_ssl_conf_verify(
+            ssl_conf,
+            Some(record_untrusted_certificate),
+            ptr::null_mut(),
+        );
+    } else {
+        return esp_crt_bundle_attach(conf);
+    }
+    mbedtls_ssl_conf_authmode(ssl_conf, MBEDTLS_SSL_VERIFY_REQUIRED as c_int);
+    ESP_OK as esp_err_t
+}
+
+/// Called for each certificate of the chain, the server one last (depth 0):
+/// only the server certificate is checked, against the pins.
+unsafe extern "C" fn verify_pinned_certificate(
+    _context: *mut c_void,
+    certificate: *mut mbedtls_x509_crt,
+    depth: c_int,
+    flags: *mut u32,
+) -> c_int {
+    if depth > 0 {
+        *flags = 0;
+        return 0;
+    }
+    let der = slice::from_raw_parts((*certificate).raw.p, (*certificate).raw.len);
+    let pins = VERIFICATION
+        .get()
+        .map_or(&[][..], |verification| &verification.pins[..]);
+    match check_pinned_certificate(der, pins) {
+        Ok(()) => *flags = 0,
+        Err(message) => {
+            record_rejection(message);
+            *flags |= MBEDTLS_X509_BADCERT_NOT_TRUSTED;
+        }
+    }
+    0
+}
+
+/// Keeps the chain verification of mbedTLS, recording why it failed.
+unsafe extern "C" fn record_untrusted_certificate(
+    _context: *mut c_void,
+    _certificate: *mut mbedtls_x509_crt,
+    depth: c_int,
+    flags: *mut u32,
+) -> c_int {
+    if *flags != 0 {
+        let mut info = [0 as c_char; 256];
+        mbedtls_x509_crt_verify_info(
+            info.as_mut_ptr(),
+            info.len(),
+            b"\0".as_ptr() as *const c_char,
+            *flags,
+        );
+        let info = CStr::from_ptr(info.as_ptr()).to_string_lossy();
+        let reasons: Vec<&str> = info
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        record_rejection(format!(
+            "certificate at depth {} rejected with SERVER_CA_CERTIFICATE: {}",
+            depth,
+            reasons.join(", ")
+        ));
+    }
+    0
+}
+
+fn record_rejection(message: String) {
+    if let Ok(mut rejection) = REJECTION.lock() {
+        *rejection = Some(message);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::platform::{gpio::OutputPin, tone::ToneOutput};
+use esp_idf_svc::hal::{
+    gpio::OutputPin as HalOutputPin,
+    ledc::{config::TimerConfig, LedcChannel, LedcDriver, LedcTimer, LedcTimerDriver, Resolution},
+    peripheral::Peripheral,
+    units::Hertz,
+};
+use esp_idf_sys::EspError;
+use log::error;
+
+const INITIAL_FREQUENCY_HZ: u32 = 1000;
+
+/// Buzzer on a LEDC channel with its own timer: full duty for the on/off
+/// patterns, a 50% square wave for the tones.
+pub struct EspToneOutput {
+    timer: LedcTimerDriver<'static>,
+    channel: LedcDriver<'static>,
+    frequency_hz: u32,
+    volume_percent: u8,
+}
+
+impl EspToneOutput {
+    pub fn new<T: LedcTimer, C: LedcChannel>(
+        timer: impl Peripheral<P = T> + 'static,
+        channel: impl Peripheral<P = C> + 'static,
+        pin: impl Peripheral<P = impl HalOutputPin> + 'static,
+    ) -> Result<EspToneOutput, EspError> {
+        // 10 bits keep the lowest notes reachable with the 80 MHz clock
+        let timer = LedcTimerDriver::new(
+            timer,
+            &TimerConfig::default()
+                .frequency(Hertz(INITIAL_FREQUENCY_HZ))
+                .resolution(Resolution::Bits10),
+        )?;
+        let mut channel = LedcDriver::new(channel, &timer, pin)?;
+        channel.set_duty(0)?;
+        Ok(EspToneOutput {
+            timer,
+            channel,
+            frequency_hz: INITIAL_FREQUENCY_HZ,
+            volume_percent: 100,
+        })
+    }
+
+    fn scaled_duty(&self, duty: u32) -> u32 {
+        duty * self.volume_percent as u32 / 100
+    }
+
+    fn set_duty(&mut self, duty: u32) {
+        if let Err(e) = self.channel.set_duty(duty) {
+            error!("unable to set the buzzer duty: {:?}", e);
+        }
+    }
+}
+
+impl OutputPin for EspToneOutput {
+    fn set_high(&mut self) {
+        self.set_duty(self.scaled_duty(self.channel.get_max_duty()));
+    }
+
+    fn set_low(&mut self) {
+        self.set_duty(0);
+    }
+}
+
+impl ToneOutput for EspToneOutput {
+    fn set_tone(&mut self, frequency_hz: u32) {
+        if frequency_hz == 0 {
+            self.set_duty(0);
+            return;
+        }
+        if frequency_hz != self.frequency_hz {
+            if let Err(e) = self.timer.set_frequency(Hertz(frequency_hz)) {
+                error!("unable to play {} Hz: {:?}", frequency_hz, e);
+                self.set_duty(0);
+                return;
+            }
+            self.frequency_hz = frequency_hz;
+        }
+        self.set_duty(self.scaled_duty(self.channel.get_max_duty() / 2));
+    }
+
+    fn set_volume(&mut self, percent: u8) {
+        self.volume_percent = percent.clamp(1, 100);
+    }
+}
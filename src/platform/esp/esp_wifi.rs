@@ -0,0 +1,203 @@
+use std::{
+    net::UdpSocket,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::config::config::{DEVICE_NAME, WIFI_CONNECT_TIMEOUT_MILLISECONDS};
+use crate::dto::{static_ip_settings::StaticIpSettings, wifi_credentials::WifiCredentials};
+use crate::helper::provisioning_helper::captive_dns_response;
+use crate::helper::static_ip_helper::{dhcp_hostname, netmask_prefix};
+use crate::platform::wifi::{ScannedNetwork, Wifi};
+use embedded_svc::wifi::{
+    AccessPointConfiguration, AuthMethod, ClientConfiguration, Configuration,
+};
+use esp_idf_svc::hal::delay::FreeRtos;
+use esp_idf_svc::ipv4::{self, ClientSettings, DHCPClientSettings, Mask, Subnet};
+use esp_idf_svc::netif::{EspNetif, NetifConfiguration};
+use esp_idf_svc::wifi::EspWifi;
+use esp_idf_svc::wifi::WifiDeviceId;
+use esp_idf_sys::{esp, wifi_ap_record_t, EspError, ESP_ERR_TIMEOUT};
+use log::{error, info, warn};
+
+const DNS_PORT: u16 = 53;
+// the DNS thread checks whether it should stop at this interval
+const DNS_READ_TIMEOUT_MILLISECONDS: u64 = 1000;
+
+pub struct EspWifiNetwork {
+    wifi_driver: EspWifi<'static>,
+    captive_dns_running: Arc<AtomicBool>,
+}
+
+impl EspWifiNetwork {
+    pub fn new(wifi_driver: EspWifi<'static>) -> EspWifiNetwork {
+        EspWifiNetwork {
+            wifi_driver,
+            captive_dns_running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Wifi for EspWifiNetwork {
+    fn is_connected(&mut self) -> bool {
+        self.wifi_driver.is_connected().unwrap_or(false)
+    }
+
+    fn scan(&mut self) -> Result<Vec<ScannedNetwork>, anyhow::Error> {
+        if !self.wifi_driver.is_started()? {
+            self.wifi_driver
+                .set_configuration(&Configuration::Client(ClientConfiguration::default()))?;
+            self.wifi_driver.start()?;
+        }
+        let scanned = self.wifi_driver.scan()?;
+        Ok(scanned
+            .into_iter()
+            .map(|access_point| ScannedNetwork {
+                ssid: access_point.ssid.to_string(),
+                rssi: access_point.signal_strength as i32,
+            })
+            .collect())
+    }
+
+    fn connect(&mut self, credentials: &WifiCredentials) -> Result<(), anyhow::Error> {
+        self.captive_dns_running.store(false, Ordering::Relaxed);
+        let hostname = dhcp_hostname(DEVICE_NAME, &get_mac_address(&mut self.wifi_driver));
+        if self.wifi_driver.is_started()? {
+            self.wifi_driver.stop()?;
+        }
+        let netif = EspNetif::new_with_conf(&NetifConfiguration {
+            ip_configuration: ip_configuration(credentials.static_ip.as_ref(), &hostname)?,
+            ..NetifConfiguration::wifi_default_client()
+        })?;
+        self.wifi_driver.swap_netif_sta(netif)?;
+        Ok(connect_to_wifi(&mut self.wifi_driver, credentials)?)
+    }
+
+    fn start_access_point(&mut self, ssid: &str, password: &str) -> Result<(), anyhow::Error> {
+        if self.wifi_driver.is_started()? {
+            self.wifi_driver.stop()?;
+        }
+        self.wifi_driver
+            .set_configuration(&Configuration::AccessPoint(AccessPointConfiguration {
+                ssid: ssid.into(),
+                password: password.into(),
+                auth_method: AuthMethod::WPA2Personal,
+                ..Default::default()
+            }))?;
+        self.wifi_driver.start()?;
+        let ip = self.wifi_driver.ap_netif().get_ip_info()?.ip;
+        info!("access point {} started, setup page at http://{}", ssid, ip);
+        start_captive_dns(ip.octets(), self.captive_dns_running.clone())
+    }
+
+    fn mac_address(&mut self) -> String {
+        get_mac_address(&mut self.wifi_driver)
+    }
+
+    fn rssi(&mut self) -> Option<i32> {
+        if !self.is_connected() {
+            return None;
+        }
+        let mut ap_info = wifi_ap_record_t::default();
+        esp!(unsafe { esp_idf_sys::esp_wifi_sta_get_ap_info(&mut ap_info) }).ok()?;
+        Some(ap_info.rssi as i32)
+    }
+}
+
+pub fn connect_to_wifi(
+    wifi_driver: &mut EspWifi<'_>,
+    credentials: &WifiCredentials,
+) -> Result<(), EspError> {
+    let auth_method = match credentials.password.is_empty() {
+        true => AuthMethod::None,
+        false => AuthMethod::WPA2Personal,
+    };
+    if wifi_driver.is_started()? {
+        wifi_driver.stop()?;
+    }
+    wifi_driver.set_configuration(&Configuration::Client(ClientConfiguration {
+        ssid: credentials.ssid.as_str().into(),
+        password: credentials.password.as_str().into(),
+        auth_method,
+        ..Default::default()
+    }))?;
+
+    wifi_driver.start()?;
+    wifi_driver.connect()?;
+    let mut waited_ms = 0;
+    while !wifi_driver.is_connected()? {
+        if waited_ms % 5000 == 0 {
+            warn!("Waiting for connection to {}", credentials.ssid);
+        }
+        FreeRtos::delay_ms(100);
+        waited_ms += 100;
+        if waited_ms > WIFI_CONNECT_TIMEOUT_MILLISECONDS {
+            return Err(EspError::from(ESP_ERR_TIMEOUT as i32).unwrap());
+        }
+    }
+    Ok(())
+}
+
+/// DHCP announcing `hostname`, or the fixed addressing of the network.
+fn ip_configuration(
+    static_ip: Option<&StaticIpSettings>,
+    hostname: &str,
+) -> Result<ipv4::Configuration, anyhow::Error> {
+    let client = match static_ip {
+        None => ipv4::ClientConfiguration::DHCP(DHCPClientSettings {
+            hostname: Some(
+                hostname
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("hostname too long: {}", hostname))?,
+            ),
+        }),
+        Some(static_ip) => ipv4::ClientConfiguration::Fixed(ClientSettings {
+            ip: static_ip.address,
+            subnet: Subnet {
+                gateway: static_ip.gateway,
+                mask: Mask(netmask_prefix(static_ip.netmask).unwrap_or(24) as u8),
+            },
+            dns: static_ip.dns_servers.first().copied(),
+            secondary_dns: static_ip.dns_servers.get(1).copied(),
+        }),
+    };
+    Ok(ipv4::Configuration::Client(client))
+}
+
+/// Answers the DNS queries of the access point clients with the address
+/// of the device, until `running` is cleared.
+fn start_captive_dns(ip: [u8; 4], running: Arc<AtomicBool>) -> Result<(), anyhow::Error> {
+    if running.swap(true, Ordering::Relaxed) {
+        return Ok(());
+    }
+    let socket = UdpSocket::bind(("0.0.0.0", DNS_PORT))?;
+    socket.set_read_timeout(Some(Duration::from_millis(DNS_READ_TIMEOUT_MILLISECONDS)))?;
+    thread::Builder::new().stack_size(4096).spawn(move || {
+        let mut buffer = [0u8; 512];
+        while running.load(Ordering::Relaxed) {
+            let (length, client) = match socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                // read timeout
+                Err(_) => continue,
+            };
+            if let Some(response) = captive_dns_response(&buffer[..length], ip) {
+                if let Err(e) = socket.send_to(&response, client) {
+                    error!("unable to answer a DNS query: {:?}", e);
+                }
+            }
+        }
+        info!("captive DNS stopped");
+    })?;
+    Ok(())
+}
+
+pub fn get_mac_address(wifi: &mut EspWifi<'static>) -> String {
+    let mav = wifi.driver().get_mac(WifiDeviceId::Sta).unwrap();
+    let mac_address_obj = macaddr::MacAddr6::new(mav[0], mav[1], mav[2], mav[3], mav[4], mav[5]);
+    let mac_address_value = mac_address_obj.to_string();
+    mac_address_value
+}
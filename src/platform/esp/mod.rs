@@ -0,0 +1,12 @@
+pub mod board;
+pub mod esp_api_server;
+pub mod esp_clock;
+pub mod esp_gpio;
+pub mod esp_http;
+pub mod esp_mdns;
+pub mod esp_mqtt;
+pub mod esp_storage;
+pub mod esp_system;
+pub mod esp_tls;
+pub mod esp_tone;
+pub mod esp_wifi;
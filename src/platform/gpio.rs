@@ -0,0 +1,16 @@
+/// Digital output driving a buzzer or a LED.
+pub trait OutputPin {
+    fn set_high(&mut self);
+
+    fn set_low(&mut self);
+}
+
+/// Push button read by polling, e.g. the snooze button.
+pub trait Button {
+    fn is_pressed(&mut self) -> bool;
+}
+
+/// Hands out buttons on GPIOs chosen at runtime by the configuration.
+pub trait ButtonProvider {
+    fn button(&mut self, gpio: i32) -> Option<Box<dyn Button>>;
+}
@@ -0,0 +1,85 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use crate::helper::provisioning_helper::rebase_server_url;
+
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Transport used by `client_service` to talk with the Elisys server.
+pub trait HttpClient {
+    /// Sends a JSON payload to `url`, with `headers` in addition to the
+    /// content type and length.
+    fn post(
+        &mut self,
+        url: &str,
+        headers: &[(&str, &str)],
+        payload: &[u8],
+    ) -> Result<HttpResponse, anyhow::Error>;
+}
+
+/// Base URL of the Elisys server, e.g. `http://192.168.1.20:8080`: the one
+/// discovered with mDNS or entered in the setup page, `None` for
+/// `DEFAULT_SERVER_URL`.
+pub type ServerUrl = Rc<RefCell<Option<String>>>;
+
+/// Sends the requests meant for `DEFAULT_SERVER_URL` to the current server.
+pub struct RebasedHttpClient {
+    inner: Box<dyn HttpClient>,
+    server_url: ServerUrl,
+}
+
+impl RebasedHttpClient {
+    pub fn new(inner: Box<dyn HttpClient>, server_url: ServerUrl) -> RebasedHttpClient {
+        RebasedHttpClient { inner, server_url }
+    }
+}
+
+impl HttpClient for RebasedHttpClient {
+    fn post(
+        &mut self,
+        url: &str,
+        headers: &[(&str, &str)],
+        payload: &[u8],
+    ) -> Result<HttpResponse, anyhow::Error> {
+        let url = match self.server_url.borrow().as_deref() {
+            Some(server_url) => rebase_server_url(url, server_url),
+            None => url.to_owned(),
+        };
+        self.inner.post(&url, headers, payload)
+    }
+}
+
+/// Failures of the transport itself, before an HTTP status was received.
+#[derive(Debug)]
+pub enum HttpTransportError {
+    Connection(String),
+    // only told apart by the ESP-IDF client
+    #[cfg_attr(not(target_os = "espidf"), allow(dead_code))]
+    Timeout(String),
+    /// The server certificate was rejected, or plain HTTP refused.
+    Tls(String),
+}
+
+impl fmt::Display for HttpTransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpTransportError::Connection(message) => write!(f, "{}", message),
+            HttpTransportError::Timeout(message) => write!(f, "timeout: {}", message),
+            HttpTransportError::Tls(message) => write!(f, "TLS error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for HttpTransportError {}
@@ -0,0 +1,34 @@
+use std::net::Ipv4Addr;
+
+/// A service instance found by `Mdns::browse`.
+#[derive(Clone, Debug)]
+pub struct MdnsService {
+    pub instance: String,
+    pub hostname: String,
+    pub addresses: Vec<Ipv4Addr>,
+    pub port: u16,
+    pub txt: Vec<(String, String)>,
+}
+
+/// Multicast DNS responder and DNS-SD browser of the local network.
+pub trait Mdns {
+    /// Answers for `<hostname>.local` and announces a service of the device,
+    /// e.g. `_elisys-clock` over `_tcp`.
+    fn advertise(
+        &mut self,
+        hostname: &str,
+        instance: &str,
+        service: &str,
+        protocol: &str,
+        port: u16,
+        txt: &[(&str, &str)],
+    ) -> Result<(), anyhow::Error>;
+
+    /// The instances of a service answering within the timeout.
+    fn browse(
+        &mut self,
+        service: &str,
+        protocol: &str,
+        timeout_ms: u32,
+    ) -> Result<Vec<MdnsService>, anyhow::Error>;
+}
@@ -0,0 +1,13 @@
+pub mod api_server;
+pub mod clock;
+pub mod device;
+#[cfg(target_os = "espidf")]
+pub mod esp;
+pub mod gpio;
+pub mod http;
+pub mod mdns;
+pub mod mqtt;
+pub mod storage;
+pub mod system;
+pub mod tone;
+pub mod wifi;
@@ -0,0 +1,29 @@
+/// A message received on a subscribed topic.
+pub struct MqttMessage {
+    pub topic: String,
+    pub payload: String,
+}
+
+/// Published (retained) by the broker when the device disappears without
+/// disconnecting, so that the server can tell it is offline.
+pub struct MqttLastWill {
+    pub topic: String,
+    pub payload: String,
+}
+
+/// Connection to the MQTT broker, the alternative to `HttpClient` when the
+/// transport is `Transport::Mqtt`.
+pub trait MqttClient {
+    /// Starts the connection; does nothing if it was already started, the
+    /// client reconnects by itself.
+    fn connect(&mut self, client_id: &str, last_will: &MqttLastWill) -> Result<(), anyhow::Error>;
+
+    fn is_connected(&mut self) -> bool;
+
+    fn subscribe(&mut self, topic: &str) -> Result<(), anyhow::Error>;
+
+    fn publish(&mut self, topic: &str, payload: &str, retain: bool) -> Result<(), anyhow::Error>;
+
+    /// The next message received since the last call, if any.
+    fn receive(&mut self) -> Option<MqttMessage>;
+}
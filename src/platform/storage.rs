@@ -0,0 +1,34 @@
+use std::collections::HashMap;
+
+/// Small key/value store that survives reboots (NVS on the ESP32).
+/// Keys must not be longer than 15 characters.
+pub trait Storage {
+    fn get(&mut self, key: &str) -> Result<Option<String>, anyhow::Error>;
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), anyhow::Error>;
+}
+
+/// Volatile storage, used by the simulator and when NVS is not available.
+#[derive(Default)]
+pub struct MemoryStorage {
+    values: HashMap<String, String>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage {
+            values: HashMap::new(),
+        }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&mut self, key: &str) -> Result<Option<String>, anyhow::Error> {
+        Ok(self.values.get(key).cloned())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), anyhow::Error> {
+        self.values.insert(key.to_owned(), value.to_owned());
+        Ok(())
+    }
+}
@@ -0,0 +1,6 @@
+/// Operations on the chip itself.
+pub trait System {
+    /// Restarts the device, e.g. to apply new WiFi credentials; the
+    /// simulator carries on instead.
+    fn restart(&mut self);
+}
@@ -0,0 +1,11 @@
+use super::gpio::OutputPin;
+
+/// Buzzer output that can also play a tone, for passive buzzers. The on/off
+/// patterns keep using `set_high` and `set_low`.
+pub trait ToneOutput: OutputPin {
+    /// Plays a square wave at `frequency_hz` until the next call, 0 is silence.
+    fn set_tone(&mut self, frequency_hz: u32);
+
+    /// Scales the duty cycle of the next `set_high` and `set_tone`, 1 to 100.
+    fn set_volume(&mut self, percent: u8);
+}
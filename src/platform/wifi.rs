@@ -0,0 +1,30 @@
+use crate::dto::wifi_credentials::WifiCredentials;
+
+/// An access point found by `Wifi::scan`.
+#[derive(Clone, Debug)]
+pub struct ScannedNetwork {
+    pub ssid: String,
+    /// dBm
+    pub rssi: i32,
+}
+
+/// Station interface used to reach the Elisys server, and access point of
+/// the setup portal.
+pub trait Wifi {
+    fn is_connected(&mut self) -> bool;
+
+    /// The access points in range; the station is started if needed.
+    fn scan(&mut self) -> Result<Vec<ScannedNetwork>, anyhow::Error>;
+
+    /// Joins the network, giving up after `WIFI_CONNECT_TIMEOUT_MILLISECONDS`.
+    fn connect(&mut self, credentials: &WifiCredentials) -> Result<(), anyhow::Error>;
+
+    /// Leaves the network and opens a WPA2 access point; every DNS name
+    /// resolves to the device meanwhile.
+    fn start_access_point(&mut self, ssid: &str, password: &str) -> Result<(), anyhow::Error>;
+
+    fn mac_address(&mut self) -> String;
+
+    /// Signal strength of the access point in dBm, `None` when disconnected.
+    fn rssi(&mut self) -> Option<i32>;
+}
@@ -0,0 +1,374 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use cron::Schedule;
+use serde_json::json;
+
+use crate::dto::{
+    alarm_response::AlarmResponse,
+    config_cron_list_response::CronListResponse,
+    local_alarms::LocalAlarms,
+    status_response::{AlarmState, StatusResponse},
+    test_buzz_request::TestBuzzRequest,
+};
+use crate::helper::{
+    alarm_helper::ScheduledAlarm,
+    local_alarm_helper::{alarm_key, is_alarm_switched_on},
+    snooze_helper::SnoozeState,
+};
+use crate::platform::api_server::{ApiRequest, ApiResponse};
+use crate::ConfigurationResponse;
+
+// a test buzz blocks the orchestrator loop
+const MAX_TEST_BUZZ_SECONDS: u32 = 30;
+
+/// What the local API can read, gathered by the orchestrator for each
+/// request.
+pub struct ApiContext<'a> {
+    pub mac_address: &'a str,
+    pub now: DateTime<FixedOffset>,
+    pub configuration: &'a ConfigurationResponse,
+    pub local_alarms: &'a LocalAlarms,
+    pub alarm: &'a Option<ScheduledAlarm>,
+    pub snooze_state: SnoozeState,
+    pub clock_synchronized_at: Option<DateTime<Utc>>,
+    pub wifi_rssi: Option<i32>,
+    pub uptime_ms: u64,
+}
+
+/// What a request asks the orchestrator to do, once it is answered.
+#[derive(Debug)]
+pub enum ApiAction {
+    AddAlarm(CronListResponse),
+    /// the key of a local alarm
+    RemoveAlarm(String),
+    TestBuzz(TestBuzzRequest),
+    Dismiss,
+}
+
+/// Answers a request of the local API; independent of the HTTP server, the
+/// changes are returned as an action instead of being applied.
+pub fn route(request: &ApiRequest, context: &ApiContext) -> (ApiResponse, Option<ApiAction>) {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["api", "status"]) => (get_status(context), None),
+        ("GET", ["api", "alarms"]) => (ApiResponse::json(200, &list_alarms(context)), None),
+        ("POST", ["api", "alarms"]) => add_alarm(&request.body, context),
+        ("DELETE", ["api", "alarms", key]) => remove_alarm(key, context),
+        ("POST", ["api", "buzz"]) => test_buzz(&request.body, context),
+        ("POST", ["api", "dismiss"]) => dismiss(context),
+        (_, ["api", "status"])
+        | (_, ["api", "alarms"])
+        | (_, ["api", "alarms", _])
+        | (_, ["api", "buzz"])
+        | (_, ["api", "dismiss"]) => (ApiResponse::error(405, "method not allowed"), None),
+        _ => (ApiResponse::error(404, "not found"), None),
+    }
+}
+
+fn get_status(context: &ApiContext) -> ApiResponse {
+    let alarm_state = match context.snooze_state {
+        SnoozeState::Idle => AlarmState::Idle,
+        SnoozeState::Ringing { .. } => AlarmState::Ringing,
+        SnoozeState::Snoozed { .. } => AlarmState::Snoozed,
+        SnoozeState::Dismissed => AlarmState::Dismissed,
+    };
+    ApiResponse::json(
+        200,
+        &StatusResponse {
+            mac_address: context.mac_address.to_owned(),
+            now: context.now,
+            next_alarm: context.alarm.as_ref().map(|alarm| alarm.date_time),
+            next_alarm_description: context
+                .alarm
+                .as_ref()
+                .map(|alarm| alarm.description.clone()),
+            alarm_state,
+            clock_synchronized_at: context.clock_synchronized_at,
+            wifi_rssi: context.wifi_rssi,
+            uptime_seconds: context.uptime_ms / 1000,
+            configuration_version: context.configuration.version.clone(),
+            transport: context.configuration.transport,
+        },
+    )
+}
+
+/// The alarms of the server, then the ones added on the device.
+fn list_alarms(context: &ApiContext) -> Vec<AlarmResponse> {
+    let server_alarms = context.configuration.cron_list.len();
+    context
+        .configuration
+        .cron_list
+        .iter()
+        .chain(&context.local_alarms.added)
+        .enumerate()
+        .map(|(index, cron)| AlarmResponse {
+            key: alarm_key(index, cron),
+            local: index >= server_alarms,
+            switched_on: is_alarm_switched_on(index, cron, context.local_alarms),
+            alarm: cron.clone(),
+        })
+        .collect()
+}
+
+fn add_alarm(body: &str, context: &ApiContext) -> (ApiResponse, Option<ApiAction>) {
+    let mut alarm = match serde_json::from_str::<CronListResponse>(body) {
+        Ok(alarm) => alarm,
+        Err(e) => {
+            return (
+                ApiResponse::error(400, &format!("invalid alarm: {}", e)),
+                None,
+            )
+        }
+    };
+    if alarm.at.is_none() && alarm.cron.is_empty() {
+        return (
+            ApiResponse::error(400, "either cron or at is required"),
+            None,
+        );
+    }
+    // an invalid cron would stop the calculation of the next alarm
+    if !alarm.cron.is_empty() && Schedule::from_str(&alarm.cron).is_err() {
+        return (ApiResponse::error(400, "invalid cron"), None);
+    }
+    // a local alarm always has an id, its key
+    let key = match alarm.id {
+        Some(_) => alarm_key(0, &alarm),
+        None => format!("local-{}", context.now.timestamp()),
+    };
+    alarm.id = Some(key.clone());
+    if list_alarms(context).iter().any(|other| other.key == key) {
+        return (
+            ApiResponse::error(409, &format!("alarm {} already exists", key)),
+            None,
+        );
+    }
+    let response = AlarmResponse {
+        key,
+        local: true,
+        switched_on: alarm.enabled,
+        alarm: alarm.clone(),
+    };
+    (
+        ApiResponse::json(201, &response),
+        Some(ApiAction::AddAlarm(alarm)),
+    )
+}
+
+fn remove_alarm(key: &str, context: &ApiContext) -> (ApiResponse, Option<ApiAction>) {
+    match list_alarms(context).iter().find(|alarm| alarm.key == key) {
+        None => (ApiResponse::error(404, &format!("no alarm {}", key)), None),
+        Some(alarm) if !alarm.local => (
+            ApiResponse::error(409, "alarm of the server, switch it off instead"),
+            None,
+        ),
+        Some(_) => (
+            ApiResponse::empty(204),
+            Some(ApiAction::RemoveAlarm(key.to_owned())),
+        ),
+    }
+}
+
+fn test_buzz(body: &str, context: &ApiContext) -> (ApiResponse, Option<ApiAction>) {
+    let request = if body.trim().is_empty() {
+        TestBuzzRequest::default()
+    } else {
+        match serde_json::from_str::<TestBuzzRequest>(body) {
+            Ok(request) => request,
+            Err(e) => {
+                return (
+                    ApiResponse::error(400, &format!("invalid request: {}", e)),
+                    None,
+                )
+            }
+        }
+    };
+    if request.seconds == 0 || request.seconds > MAX_TEST_BUZZ_SECONDS {
+        return (
+            ApiResponse::error(
+                400,
+                &format!("seconds must be between 1 and {}", MAX_TEST_BUZZ_SECONDS),
+            ),
+            None,
+        );
+    }
+    if matches!(context.snooze_state, SnoozeState::Ringing { .. }) {
+        return (ApiResponse::error(409, "an alarm is ringing"), None);
+    }
+    (
+        ApiResponse::json(202, &request),
+        Some(ApiAction::TestBuzz(request)),
+    )
+}
+
+fn dismiss(context: &ApiContext) -> (ApiResponse, Option<ApiAction>) {
+    match context.snooze_state {
+        SnoozeState::Ringing { .. } | SnoozeState::Snoozed { .. } => (
+            ApiResponse::json(202, &json!({ "alarmState": AlarmState::Dismissed })),
+            Some(ApiAction::Dismiss),
+        ),
+        _ => (ApiResponse::error(409, "no alarm is ringing"), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helper::configuration_helper::get_default_configuration;
+
+    struct Fixture {
+        configuration: ConfigurationResponse,
+        local_alarms: LocalAlarms,
+        alarm: Option<ScheduledAlarm>,
+    }
+
+    /// One alarm of the server, `alarm0`, and one added on the device, `nap`.
+    fn fixture() -> Fixture {
+        let mut configuration = get_default_configuration(anyhow::Error::msg("test"));
+        configuration.cron_list = vec![CronListResponse::new(
+            "0 30 7 * * * *".to_owned(),
+            "wake up".to_owned(),
+        )];
+        let mut nap = CronListResponse::new("0 30 14 * * * *".to_owned(), "nap".to_owned());
+        nap.id = Some("nap".to_owned());
+        Fixture {
+            configuration,
+            local_alarms: LocalAlarms {
+                added: vec![nap],
+                ..LocalAlarms::default()
+            },
+            alarm: None,
+        }
+    }
+
+    fn call(
+        fixture: &Fixture,
+        snooze_state: SnoozeState,
+        method: &str,
+        path: &str,
+        body: &str,
+    ) -> (ApiResponse, Option<ApiAction>) {
+        let context = ApiContext {
+            mac_address: "02:00:00:00:00:01",
+            now: DateTime::parse_from_rfc3339("2024-03-04T06:00:00+01:00").unwrap(),
+            configuration: &fixture.configuration,
+            local_alarms: &fixture.local_alarms,
+            alarm: &fixture.alarm,
+            snooze_state,
+            clock_synchronized_at: None,
+            wifi_rssi: Some(-60),
+            uptime_ms: 42_000,
+        };
+        let request = ApiRequest {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            body: body.to_owned(),
+        };
+        route(&request, &context)
+    }
+
+    fn get(fixture: &Fixture, path: &str) -> ApiResponse {
+        call(fixture, SnoozeState::Idle, "GET", path, "").0
+    }
+
+    #[test]
+    fn status_and_alarms_are_listed() {
+        let fixture = fixture();
+        let status = get(&fixture, "/api/status");
+        assert_eq!(status.status, 200);
+        assert!(status.body.contains(r#""alarmState":"idle""#));
+        assert!(status.body.contains(r#""uptimeSeconds":42"#));
+
+        let alarms: serde_json::Value =
+            serde_json::from_str(&get(&fixture, "/api/alarms/").body).unwrap();
+        assert_eq!(alarms[0]["key"], "alarm0");
+        assert_eq!(alarms[0]["local"], false);
+        assert_eq!(alarms[1]["key"], "nap");
+        assert_eq!(alarms[1]["local"], true);
+    }
+
+    #[test]
+    fn unknown_paths_and_methods_are_refused() {
+        let fixture = fixture();
+        assert_eq!(get(&fixture, "/api/unknown").status, 404);
+        assert_eq!(get(&fixture, "/").status, 404);
+        assert_eq!(get(&fixture, "/api/buzz").status, 405);
+        let (response, action) = call(&fixture, SnoozeState::Idle, "PUT", "/api/alarms/nap", "");
+        assert_eq!(response.status, 405);
+        assert!(action.is_none());
+    }
+
+    #[test]
+    fn alarms_are_added_when_valid_and_new() {
+        let fixture = fixture();
+        let post = |body| call(&fixture, SnoozeState::Idle, "POST", "/api/alarms", body);
+
+        let (response, action) =
+            post(r#"{"id":"gym","cron":"0 0 18 * * Mon *","description":"gym"}"#);
+        assert_eq!(response.status, 201);
+        assert!(
+            matches!(action, Some(ApiAction::AddAlarm(alarm)) if alarm.id.as_deref() == Some("gym"))
+        );
+
+        // without an id the alarm gets one
+        let (_, action) = post(r#"{"cron":"0 0 18 * * Mon *","description":"gym"}"#);
+        assert!(matches!(action, Some(ApiAction::AddAlarm(alarm))
+            if alarm.id.as_deref() == Some("local-1709528400")));
+
+        for (body, status) in [
+            (
+                r#"{"id":"nap","cron":"0 0 18 * * * *","description":"again"}"#,
+                409,
+            ),
+            (r#"{"cron":"every evening","description":"gym"}"#, 400),
+            (r#"{"cron":"","description":"gym"}"#, 400),
+            ("not json", 400),
+        ] {
+            let (response, action) = post(body);
+            assert_eq!(response.status, status, "{}", body);
+            assert!(action.is_none());
+        }
+    }
+
+    #[test]
+    fn only_local_alarms_are_removed() {
+        let fixture = fixture();
+        let delete = |path| call(&fixture, SnoozeState::Idle, "DELETE", path, "");
+        let (response, action) = delete("/api/alarms/nap");
+        assert_eq!(response.status, 204);
+        assert!(matches!(action, Some(ApiAction::RemoveAlarm(key)) if key == "nap"));
+        assert_eq!(delete("/api/alarms/alarm0").0.status, 409);
+        assert_eq!(delete("/api/alarms/unknown").0.status, 404);
+    }
+
+    #[test]
+    fn test_buzz_and_dismiss_depend_on_the_alarm_state() {
+        let fixture = fixture();
+        let ringing = SnoozeState::Ringing { until: None };
+
+        let (response, action) = call(&fixture, SnoozeState::Idle, "POST", "/api/buzz", "");
+        assert_eq!(response.status, 202);
+        assert!(matches!(action, Some(ApiAction::TestBuzz(_))));
+        let too_long = format!(r#"{{"seconds":{}}}"#, MAX_TEST_BUZZ_SECONDS + 1);
+        assert_eq!(
+            call(&fixture, SnoozeState::Idle, "POST", "/api/buzz", &too_long)
+                .0
+                .status,
+            400
+        );
+        assert_eq!(
+            call(&fixture, ringing, "POST", "/api/buzz", "").0.status,
+            409
+        );
+
+        let (response, action) = call(&fixture, ringing, "POST", "/api/dismiss", "");
+        assert_eq!(response.status, 202);
+        assert!(matches!(action, Some(ApiAction::Dismiss)));
+        assert_eq!(
+            call(&fixture, SnoozeState::Idle, "POST", "/api/dismiss", "")
+                .0
+                .status,
+            409
+        );
+    }
+}
@@ -1,19 +1,113 @@
+use crate::dto::device_credentials::DeviceCredentials;
+use crate::helper::configuration_helper::validate_configuration;
+use crate::helper::device_auth_helper::RequestSigner;
+use crate::helper::response_body_helper::ResponseBodyError;
+use crate::helper::signature_helper::{check_unsigned_answer, verified_configuration};
+use crate::platform::http::{HttpClient, HttpResponse, HttpTransportError};
 use crate::ConfigurationResponse;
 use crate::{
-    config::config::{DEVICE_DESCRIPTION, DEVICE_NAME, DEVICE_TYPE, REGISTER_DEVICE_URL},
+    config::config::{
+        ALARM_CONSUMED_URL, ALARM_EVENT_URL, DEVICE_DESCRIPTION, DEVICE_NAME, DEVICE_TYPE,
+        DIAGNOSTIC_URL, REGISTER_DEVICE_URL,
+    },
     dto::{
-        config_request::ConfigRequest, register_device::RegisterDeviceDTO,
-        request_i_am_alive::RequestIAmAlive,
+        alarm_consumed_request::AlarmConsumedRequest, alarm_event_request::AlarmEventRequest,
+        config_request::ConfigRequest, config_unchanged_response::ConfigUnchangedResponse,
+        diagnostic_request::DiagnosticRequest, outbound_message::OutboundMessage,
+        register_device::RegisterDeviceDTO, request_i_am_alive::RequestIAmAlive,
     },
 };
-use anyhow::Error as StandardError;
-use embedded_svc::{http::client::Client as HttpClient, io::Write, utils::io};
-use esp_idf_svc::http::client::EspHttpConnection;
-use log::{error, info};
+use log::{error, info, warn};
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ClientError {
+    Connection(String),
+    Timeout(String),
+    Tls(String),
+    HttpStatus { status: u16, body: String },
+    Decode(String),
+    Unverified(String),
+    Oversize { max_size: usize },
+}
+
+impl ClientError {
+    /// Worth retrying shortly: the server or the network may recover, or
+    /// the server asked to try again later (408, 429).
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ClientError::Timeout(_) => true,
+            ClientError::HttpStatus { status, .. } => {
+                *status >= 500 || *status == 408 || *status == 429
+            }
+            _ => false,
+        }
+    }
+
+    /// The server refused the request and will refuse it again.
+    pub fn is_rejected(&self) -> bool {
+        matches!(self, ClientError::HttpStatus { status, .. }
+            if (400..500).contains(status) && *status != 408 && *status != 429)
+    }
+
+    /// The server refused the credentials of the device, or wants some.
+    pub fn is_unauthorized(&self) -> bool {
+        matches!(self, ClientError::HttpStatus { status: 401, .. })
+    }
+
+    /// The server does not know this device (anymore).
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, ClientError::HttpStatus { status: 404, .. })
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Connection(message) => write!(f, "connection error: {}", message),
+            ClientError::Timeout(message) => write!(f, "timeout: {}", message),
+            ClientError::Tls(message) => write!(f, "TLS error: {}", message),
+            ClientError::HttpStatus { status, body } => {
+                write!(f, "unexpected HTTP status {}: {}", status, body)
+            }
+            ClientError::Decode(message) => write!(f, "invalid response: {}", message),
+            ClientError::Unverified(message) => {
+                write!(f, "unverified configuration: {}", message)
+            }
+            ClientError::Oversize { max_size } => {
+                write!(f, "response larger than {} bytes", max_size)
+            }
+        }
+    }
+}
 
-pub fn register_device(mac_address: &str) -> anyhow::Result<(), anyhow::Error> {
-    let client = HttpClient::wrap(EspHttpConnection::new(&Default::default())?);
+impl std::error::Error for ClientError {}
 
+impl From<anyhow::Error> for ClientError {
+    fn from(e: anyhow::Error) -> Self {
+        if let Some(e) = e.downcast_ref::<HttpTransportError>() {
+            return match e {
+                HttpTransportError::Connection(message) => ClientError::Connection(message.clone()),
+                HttpTransportError::Timeout(message) => ClientError::Timeout(message.clone()),
+                HttpTransportError::Tls(message) => ClientError::Tls(message.clone()),
+            };
+        }
+        match e.downcast_ref::<ResponseBodyError>() {
+            Some(ResponseBodyError::TooLarge { max_size, .. }) => ClientError::Oversize {
+                max_size: *max_size,
+            },
+            Some(ResponseBodyError::InvalidUtf8) => ClientError::Decode(e.to_string()),
+            _ => ClientError::Connection(e.to_string()),
+        }
+    }
+}
+
+/// Registers the device, with the credentials the server issued for the
+/// next requests, if any.
+pub fn register_device(
+    http: &mut dyn HttpClient,
+    mac_address: &str,
+) -> Result<Option<DeviceCredentials>, ClientError> {
     let payload = serde_json::to_string(&RegisterDeviceDTO::new(
         mac_address.to_owned(),
         DEVICE_TYPE.into(),
@@ -24,131 +118,396 @@ pub fn register_device(mac_address: &str) -> anyhow::Result<(), anyhow::Error> {
     let payload = payload.as_bytes();
 
     info!("trying to send data...");
-    let result = post_request(payload, client, REGISTER_DEVICE_URL);
-    info!("data sent? {}", !result.is_err());
-    return match result {
-        Err(e) => Err(e.into()),
-        Ok(_) => Ok(()),
-    };
+    let result = post_json(http, REGISTER_DEVICE_URL, None, &[], payload);
+    info!("data sent? {}", result.is_ok());
+    let response = result?;
+    if response.body.trim().is_empty() {
+        return Ok(None);
+    }
+    match serde_json::from_str::<DeviceCredentials>(&response.body) {
+        Ok(credentials) if credentials.token.is_some() || credentials.secret.is_some() => {
+            Ok(Some(credentials))
+        }
+        Ok(_) => Ok(None),
+        Err(e) => {
+            warn!("unreadable registration response, no credentials: {}", e);
+            Ok(None)
+        }
+    }
 }
 
-pub fn send_i_am_alive(mac_address: &str, url: &str) -> anyhow::Result<(), anyhow::Error> {
-    let client = HttpClient::wrap(EspHttpConnection::new(&Default::default())?);
-    let payload = serde_json::to_string(&RequestIAmAlive::new(mac_address.to_owned())).unwrap();
+pub fn send_i_am_alive(
+    http: &mut dyn HttpClient,
+    signer: &RequestSigner,
+    url: &str,
+    request: &RequestIAmAlive,
+) -> Result<(), ClientError> {
+    let payload = serde_json::to_string(request).unwrap();
     let payload = payload.as_bytes();
 
     info!("trying to send is alive ack...");
-    let result = post_request(payload, client, url);
-    info!("ack sent? {}", !result.is_err());
-    return match result {
-        Err(e) => Err(e.into()),
-        Ok(_) => Ok(()),
-    };
+    let result = post_json(http, url, Some(signer), &[], payload);
+    info!("ack sent? {}", result.is_ok());
+    result.map(|_| ())
 }
 
+pub fn send_alarm_consumed(
+    http: &mut dyn HttpClient,
+    signer: &RequestSigner,
+    request: &AlarmConsumedRequest,
+) -> Result<(), ClientError> {
+    let payload = serde_json::to_string(request).unwrap();
+
+    info!("trying to report a one-shot alarm...");
+    post_json(
+        http,
+        ALARM_CONSUMED_URL,
+        Some(signer),
+        &[],
+        payload.as_bytes(),
+    )
+    .map(|_| ())
+}
+
+pub fn send_alarm_event(
+    http: &mut dyn HttpClient,
+    signer: &RequestSigner,
+    event: &AlarmEventRequest,
+) -> Result<(), ClientError> {
+    let payload = serde_json::to_string(event).unwrap();
+
+    info!("trying to send alarm event...");
+    post_json(http, ALARM_EVENT_URL, Some(signer), &[], payload.as_bytes()).map(|_| ())
+}
+
+pub fn send_diagnostic(
+    http: &mut dyn HttpClient,
+    signer: &RequestSigner,
+    request: &DiagnosticRequest,
+) -> Result<(), ClientError> {
+    let payload = serde_json::to_string(request).unwrap();
+
+    info!("trying to send diagnostic...");
+    post_json(http, DIAGNOSTIC_URL, Some(signer), &[], payload.as_bytes()).map(|_| ())
+}
+
+pub fn send_outbound_message(
+    http: &mut dyn HttpClient,
+    signer: &RequestSigner,
+    message: &OutboundMessage,
+) -> Result<(), ClientError> {
+    match message {
+        OutboundMessage::Heartbeat { endpoint, request } => {
+            send_i_am_alive(http, signer, endpoint, request)
+        }
+        OutboundMessage::AlarmConsumed(request) => send_alarm_consumed(http, signer, request),
+        OutboundMessage::AlarmEvent(request) => send_alarm_event(http, signer, request),
+        OutboundMessage::Diagnostic(request) => send_diagnostic(http, signer, request),
+    }
+}
+
+pub enum ConfigurationUpdate {
+    Unchanged,
+    Changed(Box<ConfigurationResponse>),
+}
+
+/// Downloads the configuration; with the `current_configuration` version
+/// the server can answer `304 Not Modified` (or `{"unchanged": true}`)
+/// instead. With a `public_key` the configuration is always downloaded in
+/// full, since those answers are not signed.
 pub fn get_configuration(
+    http: &mut dyn HttpClient,
+    signer: &RequestSigner,
     configuration_uri: &str,
     mac_address: &str,
-) -> anyhow::Result<ConfigurationResponse, anyhow::Error> {
-    let client = HttpClient::wrap(EspHttpConnection::new(&Default::default())?);
-    let payload = serde_json::to_string(&ConfigRequest::new(mac_address.to_owned())).unwrap();
+    current_configuration: Option<&ConfigurationResponse>,
+    public_key: Option<&str>,
+) -> Result<ConfigurationUpdate, ClientError> {
+    let current_version = current_configuration
+        .and_then(|configuration| configuration.version.as_deref())
+        .filter(|_| public_key.is_none());
+    let payload = serde_json::to_string(&ConfigRequest::new(
+        mac_address.to_owned(),
+        current_version.map(|version| version.to_owned()),
+    ))
+    .unwrap();
     let payload = payload.as_bytes();
+    let headers: Vec<(&str, &str)> = match current_version {
+        Some(version) => vec![("If-None-Match", version)],
+        None => vec![],
+    };
 
     info!("[config downloader]: trying to get remote configuration...");
-    let result = post_request(payload, client, configuration_uri);
+    let result = post_json(http, configuration_uri, Some(signer), &headers, payload);
     info!(
         "[config downloader]: configuration retrieved with success? {}",
-        !result.is_err()
+        result.is_ok()
     );
 
     match result {
-        Ok(body_string) => {
-            let configuration: Result<ConfigurationResponse, serde_json::Error> =
-                serde_json::from_str(&body_string);
-            info!("{:?}", configuration);
-
-            if configuration.is_err() {
-                let err = configuration.err().unwrap();
-                error!(
-            "[config downloader]: error while trying to parse the configuration response: {}",
-            &err
-        );
-                return Err(err.into());
+        Ok(response) => {
+            if response.status == 304
+                || serde_json::from_str::<ConfigUnchangedResponse>(&response.body)
+                    .is_ok_and(|body| body.unchanged)
+            {
+                if let Err(message) =
+                    check_unsigned_answer("the configuration unchanged answer", public_key)
+                {
+                    error!("[config downloader]: {}", message);
+                    return Err(ClientError::Unverified(message));
+                }
+                info!("[config downloader]: configuration unchanged");
+                return Ok(ConfigurationUpdate::Unchanged);
             }
 
-            let configuration = configuration.unwrap();
+            let issued_after =
+                current_configuration.and_then(|configuration| configuration.issued_at);
+            let body =
+                match verified_configuration(&response.body, public_key, mac_address, issued_after)
+                {
+                    Ok(body) => body,
+                    Err(message) => {
+                        error!(
+                            "[config downloader]: rejecting the configuration: {}",
+                            message
+                        );
+                        return Err(ClientError::Unverified(message));
+                    }
+                };
+            let mut configuration: ConfigurationResponse = match serde_json::from_str(&body) {
+                Ok(configuration) => configuration,
+                Err(err) => {
+                    error!(
+                        "[config downloader]: error while trying to parse the configuration response: {}",
+                        &err
+                    );
+                    return Err(ClientError::Decode(err.to_string()));
+                }
+            };
+            if let Err(message) = validate_configuration(&configuration) {
+                warn!(
+                    "[config downloader]: rejecting the configuration: {}",
+                    message
+                );
+                return Err(ClientError::Decode(message));
+            }
+            // the header is not signed
+            if let Some(etag) = response.header("ETag").filter(|_| public_key.is_none()) {
+                configuration.version = Some(etag.to_owned());
+            }
             info!(
                 "[config downloader]: Remote configuration loaded successfully: {:?}",
                 configuration
             );
-            return Ok(configuration);
+            Ok(ConfigurationUpdate::Changed(Box::new(configuration)))
         }
         Err(e) => {
             error!("[config downloader]: Error decoding response body: {}", e);
-            return Err(e.into());
+            Err(e)
         }
     }
 }
 
-fn post_request(
-    payload: &[u8],
-    mut client: HttpClient<EspHttpConnection>,
+/// Posts the payload, authenticated by `signer` when given, and turns
+/// anything but a 2xx or a 304 into an error.
+fn post_json(
+    http: &mut dyn HttpClient,
     url: &str,
-) -> Result<String, StandardError> {
-    let content_length_header = format!("{}", payload.len());
-    let headers = [
-        ("content-type", "application/json"),
-        ("content-length", &*content_length_header),
-    ];
-
-    let request = client.post(url, &headers);
-
-    if request.is_err() {
-        let message = format!("connection error: {:?}", request.err());
-        error!("{}", message);
-        return Err(StandardError::msg(message));
-    }
-    let mut request = request.unwrap();
-
-    if request.write_all(payload).is_err() {
-        let message = format!("connection error while trying to write all");
-        error!("{}", message);
-        return Err(StandardError::msg(message));
-    }
-    if request.flush().is_err() {
-        let message = format!("connection error while trying to flush");
-        error!("{}", message);
-        return Err(StandardError::msg(message));
-    }
-    info!("-> POST {}", url);
-    let response = request.submit();
-    if response.is_err() {
-        let message = format!("connection error while trying to read response");
-        error!("{}", message);
-        return Err(StandardError::msg(message));
-    }
-    let mut response = response.unwrap();
-
-    let status = response.status();
-    info!("<- {}", status);
-    let mut buf = [0u8; 4086];
-    let bytes_read = io::try_read_full(&mut response, &mut buf).map_err(|e| e.0);
-
-    if bytes_read.is_err() {
-        let message = format!(
-            "connection error while trying to read response: {:?}",
-            bytes_read.err()
-        );
-        error!("{}", message);
-        return Err(StandardError::msg(message));
-    } else {
-        let bytes_read = bytes_read.unwrap();
-        return match std::str::from_utf8(&buf[0..bytes_read]) {
-            Err(e) => Err(StandardError::msg(format!("{:?}", e))),
-            Ok(str) => {
-                info!("received message: {}", str);
-                Ok(str.to_owned())
+    signer: Option<&RequestSigner>,
+    headers: &[(&str, &str)],
+    payload: &[u8],
+) -> Result<HttpResponse, ClientError> {
+    let authentication = signer.map_or(Vec::new(), |signer| signer.headers(url, payload));
+    let mut headers = headers.to_vec();
+    headers.extend(
+        authentication
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str())),
+    );
+    let response = http.post(url, &headers, payload)?;
+    if (200..300).contains(&response.status) || response.status == 304 {
+        return Ok(response);
+    }
+    error!("<- {} from {}: {}", response.status, url, response.body);
+    Err(ClientError::HttpStatus {
+        status: response.status,
+        body: response.body,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::config::config::{DEFAULT_CONFIGURATION_URI, DEFAULT_SERVER_URL};
+
+    const CONFIGURATION: &str = r#"{"iamAliveEndpoint":"http://localhost/alive","iamAliveIntervalSeconds":30,"cronList":[{"cron":"0 30 7 * * * *","description":"wake up"}],"timezoneSeconds":3600,"alarmIntervalMinutes":1}"#;
+    const MAC_ADDRESS: &str = "02:00:00:00:00:01";
+    // RFC 8032, test vector 1
+    const PUBLIC_KEY: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+
+    struct Request {
+        headers: Vec<(String, String)>,
+        body: String,
+    }
+
+    /// Answers the first request with `response`, keeping the requests.
+    struct FakeHttp {
+        response: Option<HttpResponse>,
+        requests: Vec<Request>,
+    }
+
+    impl FakeHttp {
+        fn answering(status: u16, headers: &[(&str, &str)], body: &str) -> FakeHttp {
+            FakeHttp {
+                response: Some(HttpResponse {
+                    status,
+                    headers: headers
+                        .iter()
+                        .map(|(name, value)| (name.to_string(), value.to_string()))
+                        .collect(),
+                    body: body.to_owned(),
+                }),
+                requests: Vec::new(),
+            }
+        }
+    }
+
+    impl HttpClient for FakeHttp {
+        fn post(
+            &mut self,
+            _url: &str,
+            headers: &[(&str, &str)],
+            payload: &[u8],
+        ) -> Result<HttpResponse, anyhow::Error> {
+            self.requests.push(Request {
+                headers: headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                body: String::from_utf8(payload.to_vec()).unwrap(),
+            });
+            self.response
+                .take()
+                .ok_or_else(|| HttpTransportError::Connection("no response".to_owned()).into())
+        }
+    }
+
+    fn get_with_key(
+        http: &mut FakeHttp,
+        version: Option<&str>,
+        public_key: Option<&str>,
+    ) -> Result<ConfigurationUpdate, ClientError> {
+        let signer = RequestSigner::new(None, DEFAULT_SERVER_URL, Utc::now());
+        let mut current_configuration: ConfigurationResponse =
+            serde_json::from_str(CONFIGURATION).unwrap();
+        current_configuration.version = version.map(str::to_owned);
+        get_configuration(
+            http,
+            &signer,
+            DEFAULT_CONFIGURATION_URI,
+            MAC_ADDRESS,
+            Some(&current_configuration),
+            public_key,
+        )
+    }
+
+    fn get(http: &mut FakeHttp, version: Option<&str>) -> Result<ConfigurationUpdate, ClientError> {
+        get_with_key(http, version, None)
+    }
+
+    #[test]
+    fn current_version_is_sent_as_header_and_in_the_body() {
+        let mut http = FakeHttp::answering(304, &[], "");
+        get(&mut http, Some("\"v1\"")).unwrap();
+        let request = &http.requests[0];
+        assert!(request
+            .headers
+            .contains(&("If-None-Match".to_owned(), "\"v1\"".to_owned())));
+        assert!(request.body.contains(r#""version":"\"v1\"""#));
+    }
+
+    #[test]
+    fn not_modified_or_unchanged_body_keeps_the_configuration() {
+        let mut http = FakeHttp::answering(304, &[], "");
+        assert!(matches!(
+            get(&mut http, Some("v1")),
+            Ok(ConfigurationUpdate::Unchanged)
+        ));
+        let mut http = FakeHttp::answering(200, &[], r#"{"unchanged":true}"#);
+        assert!(matches!(
+            get(&mut http, Some("v1")),
+            Ok(ConfigurationUpdate::Unchanged)
+        ));
+    }
+
+    #[test]
+    fn etag_becomes_the_version_of_a_new_configuration() {
+        let mut http = FakeHttp::answering(200, &[("ETag", "\"v2\"")], CONFIGURATION);
+        match get(&mut http, None) {
+            Ok(ConfigurationUpdate::Changed(configuration)) => {
+                assert_eq!(configuration.version.as_deref(), Some("\"v2\""));
             }
-        };
+            _ => panic!("expected a new configuration"),
+        }
+        assert!(!http.requests[0]
+            .headers
+            .iter()
+            .any(|(name, _)| name == "If-None-Match"));
+    }
+
+    #[test]
+    fn unsigned_answers_are_refused_with_a_public_key() {
+        let public_key = Some(PUBLIC_KEY);
+        let mut http = FakeHttp::answering(304, &[], "");
+        assert!(matches!(
+            get_with_key(&mut http, Some("v1"), public_key),
+            Err(ClientError::Unverified(_))
+        ));
+        assert!(!http.requests[0]
+            .headers
+            .iter()
+            .any(|(name, _)| name == "If-None-Match"));
+        assert!(!http.requests[0].body.contains("v1"));
+        let mut http = FakeHttp::answering(200, &[], r#"{"unchanged":true}"#);
+        assert!(matches!(
+            get_with_key(&mut http, Some("v1"), public_key),
+            Err(ClientError::Unverified(_))
+        ));
+        let mut http = FakeHttp::answering(200, &[("ETag", "\"v2\"")], CONFIGURATION);
+        assert!(matches!(
+            get_with_key(&mut http, None, public_key),
+            Err(ClientError::Unverified(_))
+        ));
+    }
+
+    fn status_error(status: u16) -> ClientError {
+        let mut http = FakeHttp::answering(status, &[], "refused");
+        post_json(&mut http, DEFAULT_CONFIGURATION_URI, None, &[], b"{}")
+            .err()
+            .unwrap()
+    }
+
+    #[test]
+    fn error_statuses_are_classified() {
+        for status in [500, 503, 408, 429] {
+            let error = status_error(status);
+            assert!(error.is_transient(), "{}", status);
+            assert!(!error.is_rejected(), "{}", status);
+        }
+        for status in [400, 403, 404, 410] {
+            let error = status_error(status);
+            assert!(!error.is_transient(), "{}", status);
+            assert!(error.is_rejected(), "{}", status);
+        }
+        assert!(status_error(404).is_not_found());
+        assert!(status_error(401).is_unauthorized());
+        assert!(matches!(
+            status_error(500),
+            ClientError::HttpStatus { status: 500, body } if body == "refused"
+        ));
+        assert!(ClientError::Timeout("slow".to_owned()).is_transient());
+        assert!(!ClientError::Connection("refused".to_owned()).is_transient());
     }
 }
@@ -0,0 +1,219 @@
+use log::{error, info, warn};
+
+use crate::config::config::{
+    API_SERVER_PORT, DEFAULT_SERVER_URL, DEVICE_MDNS_SERVICE, DEVICE_NAME, DEVICE_TYPE,
+    ENABLE_API_SERVER, ENABLE_SERVER_DISCOVERY, SERVER_DISCOVERY_TIMEOUT_MILLISECONDS,
+    SERVER_MDNS_SERVICE,
+};
+use crate::helper::discovery_helper::{
+    load_discovered_server_url, save_discovered_server_url, select_server_url,
+};
+use crate::helper::provisioning_helper::load_wifi_settings;
+use crate::helper::static_ip_helper::dhcp_hostname;
+use crate::platform::device::Device;
+
+const MDNS_PROTOCOL: &str = "_tcp";
+
+/// Announces the device as `<hostname>.local` with its local API port, so
+/// that tools can find the alarm clocks of the network.
+pub fn advertise_device(device: &mut Device, mac_address: &str) {
+    let hostname = dhcp_hostname(DEVICE_NAME, mac_address);
+    let api = if ENABLE_API_SERVER { "1" } else { "0" };
+    let txt = [("mac", mac_address), ("type", DEVICE_TYPE), ("api", api)];
+    match device.mdns.advertise(
+        &hostname,
+        DEVICE_NAME,
+        DEVICE_MDNS_SERVICE,
+        MDNS_PROTOCOL,
+        API_SERVER_PORT,
+        &txt,
+    ) {
+        Ok(()) => info!("advertised as {}.local", hostname),
+        Err(e) => error!("unable to advertise the device with mDNS: {:?}", e),
+    }
+}
+
+/// Points the requests to the server entered in the setup page; without
+/// one, to the server announced with mDNS, else to the last one found, else
+/// to `DEFAULT_SERVER_URL`. An announcement, which anyone on the network can
+/// make, never overrides a server entered by hand. Returns whether the
+/// server changed.
+pub fn locate_server(device: &mut Device) -> bool {
+    let configured = load_wifi_settings(&mut *device.storage).server_url;
+    let server_url = match configured {
+        Some(configured) => Some(configured),
+        None if ENABLE_SERVER_DISCOVERY => discover_server(device),
+        None => None,
+    };
+    let changed = *device.server_url.borrow() != server_url;
+    if changed {
+        info!(
+            "Elisys server: {}",
+            server_url.as_deref().unwrap_or(DEFAULT_SERVER_URL)
+        );
+    }
+    *device.server_url.borrow_mut() = server_url;
+    changed
+}
+
+/// The announced server, cached for when nothing answers, else the cached
+/// one.
+fn discover_server(device: &mut Device) -> Option<String> {
+    let cached = load_discovered_server_url(&mut *device.storage);
+    let services = device
+        .mdns
+        .browse(
+            SERVER_MDNS_SERVICE,
+            MDNS_PROTOCOL,
+            SERVER_DISCOVERY_TIMEOUT_MILLISECONDS,
+        )
+        .unwrap_or_else(|e| {
+            error!("unable to browse for the Elisys server: {:?}", e);
+            Vec::new()
+        });
+    match select_server_url(&services, cached.as_deref()) {
+        Some(server_url) => {
+            if cached.as_deref() != Some(server_url.as_str()) {
+                save_discovered_server_url(&mut *device.storage, &server_url);
+            }
+            Some(server_url)
+        }
+        None => {
+            warn!(
+                "no {}.{} server announced, using {}",
+                SERVER_MDNS_SERVICE,
+                MDNS_PROTOCOL,
+                cached.as_deref().unwrap_or(DEFAULT_SERVER_URL)
+            );
+            cached
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        net::Ipv4Addr,
+        rc::Rc,
+    };
+
+    use chrono::{DateTime, FixedOffset, Utc};
+
+    use super::*;
+    use crate::dto::wifi_settings::WifiSettings;
+    use crate::helper::provisioning_helper::save_wifi_settings;
+    use crate::helper::timezone_helper::UserTimeZone;
+    use crate::platform::{mdns::MdnsService, storage::MemoryStorage};
+    use crate::simulator::{
+        api_stub::ApiStub,
+        http_stub::HttpStub,
+        mdns_stub::MdnsStub,
+        mqtt_stub::MqttStub,
+        simulated_clock::SimulatedClock,
+        simulated_gpio::{SimulatedButtons, SimulatedBuzzer},
+        simulated_system::SimulatedSystem,
+        simulated_wifi::SimulatedWifi,
+    };
+
+    const MAC_ADDRESS: &str = "02:00:00:00:00:01";
+    const ANNOUNCED: &str = "http://10.0.0.2:8080";
+    const CACHED: &str = "http://10.0.0.7:8080";
+    const CONFIGURED: &str = "https://elisys.example";
+
+    /// A device on whose network `servers` are announced, with the server
+    /// entered in the setup page and the one cached by a previous discovery.
+    fn simulated_device(
+        servers: &[[u8; 4]],
+        configured: Option<&str>,
+        cached: Option<&str>,
+    ) -> Device {
+        let now = Rc::new(Cell::new(
+            DateTime::parse_from_rfc3339("2024-03-04T06:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc),
+        ));
+        let timezone = UserTimeZone::Fixed(FixedOffset::east_opt(0).unwrap());
+        let rings = Rc::new(Cell::new(0));
+        let mut storage = MemoryStorage::new();
+        let settings = WifiSettings {
+            server_url: configured.map(str::to_owned),
+            ..WifiSettings::default()
+        };
+        save_wifi_settings(&mut storage, &settings).unwrap();
+        if let Some(cached) = cached {
+            save_discovered_server_url(&mut storage, cached);
+        }
+        let servers = servers
+            .iter()
+            .enumerate()
+            .map(|(index, address)| MdnsService {
+                instance: format!("server-{}", index),
+                hostname: String::new(),
+                addresses: vec![Ipv4Addr::from(*address)],
+                port: 8080,
+                txt: Vec::new(),
+            })
+            .collect();
+        Device {
+            clock: Box::new(SimulatedClock::new(now.clone(), 0)),
+            wifi: Box::new(SimulatedWifi::new(MAC_ADDRESS.to_owned(), Vec::new())),
+            http: Box::new(HttpStub::new()),
+            server_url: Rc::new(RefCell::new(None)),
+            mdns: Box::new(MdnsStub::new(servers)),
+            mqtt: Box::new(MqttStub::new()),
+            buzzer1: Box::new(SimulatedBuzzer::new(
+                "buzzer1".to_owned(),
+                now.clone(),
+                timezone,
+                rings.clone(),
+            )),
+            buzzer2: Box::new(SimulatedBuzzer::new(
+                "buzzer2".to_owned(),
+                now.clone(),
+                timezone,
+                rings,
+            )),
+            buttons: Box::new(SimulatedButtons::new(now.clone(), Vec::new())),
+            storage: Box::new(storage),
+            api: Box::new(ApiStub::new("api", now.clone(), Vec::new())),
+            portal: Box::new(ApiStub::new("portal", now, Vec::new())),
+            system: Box::new(SimulatedSystem),
+        }
+    }
+
+    fn located(device: &mut Device) -> Option<String> {
+        locate_server(device);
+        device.server_url.borrow().clone()
+    }
+
+    #[test]
+    fn configured_server_wins_over_the_announced_one() {
+        let mut device = simulated_device(&[[10, 0, 0, 2]], Some(CONFIGURED), Some(CACHED));
+        assert_eq!(located(&mut device).as_deref(), Some(CONFIGURED));
+        assert_eq!(
+            load_discovered_server_url(&mut *device.storage).as_deref(),
+            Some(CACHED)
+        );
+    }
+
+    #[test]
+    fn announced_server_is_used_and_cached_without_a_configured_one() {
+        let mut device = simulated_device(&[[10, 0, 0, 2]], None, Some(CACHED));
+        assert!(locate_server(&mut device));
+        assert_eq!(device.server_url.borrow().as_deref(), Some(ANNOUNCED));
+        assert_eq!(
+            load_discovered_server_url(&mut *device.storage).as_deref(),
+            Some(ANNOUNCED)
+        );
+        assert!(!locate_server(&mut device));
+    }
+
+    #[test]
+    fn cached_server_then_the_default_one_are_used_when_nothing_is_announced() {
+        let mut device = simulated_device(&[], None, Some(CACHED));
+        assert_eq!(located(&mut device).as_deref(), Some(CACHED));
+        let mut device = simulated_device(&[], None, None);
+        assert_eq!(located(&mut device), None);
+    }
+}
@@ -0,0 +1,407 @@
+use chrono::{DateTime, FixedOffset};
+use log::info;
+
+use crate::config::config::{DEVICE_NAME, DEVICE_TYPE, HOME_ASSISTANT_DISCOVERY_PREFIX};
+use crate::dto::home_assistant_discovery::{HomeAssistantDevice, HomeAssistantDiscovery};
+use crate::platform::mqtt::MqttClient;
+use crate::service::client_service::ClientError;
+use crate::service::mqtt_service::mqtt_topic;
+
+const MANUFACTURER: &str = "Elisys";
+
+/// What the Home Assistant entities show, published again when it changes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HomeAssistantState {
+    pub next_alarm: Option<DateTime<FixedOffset>>,
+    pub ringing: bool,
+    pub alarms_enabled: bool,
+    /// key and switch of each alarm of the configuration
+    pub alarms: Vec<(String, bool)>,
+}
+
+/// Publishes, retained, the discovery payloads of the device entities and
+/// of the given alarms (key and description), and removes the entities of
+/// the alarms that are no longer in the configuration.
+pub fn publish_discovery(
+    mqtt: &mut dyn MqttClient,
+    mac_address: &str,
+    alarms: &[(String, String)],
+    removed_alarms: &[String],
+) -> Result<(), ClientError> {
+    let next_alarm = HomeAssistantDiscovery {
+        state_topic: Some(mqtt_topic(mac_address, "next-alarm")),
+        device_class: Some("timestamp".to_owned()),
+        ..discovery(mac_address, "next_alarm", "Next alarm")
+    };
+    publish_config(mqtt, mac_address, "sensor", &next_alarm)?;
+    let ringing = HomeAssistantDiscovery {
+        state_topic: Some(mqtt_topic(mac_address, "ringing")),
+        device_class: Some("sound".to_owned()),
+        ..discovery(mac_address, "ringing", "Ringing")
+    };
+    publish_config(mqtt, mac_address, "binary_sensor", &ringing)?;
+    let snooze = HomeAssistantDiscovery {
+        command_topic: Some(mqtt_topic(mac_address, "command/snooze")),
+        icon: Some("mdi:alarm-snooze".to_owned()),
+        ..discovery(mac_address, "snooze", "Snooze")
+    };
+    publish_config(mqtt, mac_address, "button", &snooze)?;
+    let dismiss = HomeAssistantDiscovery {
+        command_topic: Some(mqtt_topic(mac_address, "command/dismiss")),
+        icon: Some("mdi:alarm-off".to_owned()),
+        ..discovery(mac_address, "dismiss", "Dismiss")
+    };
+    publish_config(mqtt, mac_address, "button", &dismiss)?;
+    let alarms_switch = HomeAssistantDiscovery {
+        state_topic: Some(mqtt_topic(mac_address, "alarms")),
+        command_topic: Some(mqtt_topic(mac_address, "command/alarms")),
+        icon: Some("mdi:alarm".to_owned()),
+        ..discovery(mac_address, "alarms", "Alarms")
+    };
+    publish_config(mqtt, mac_address, "switch", &alarms_switch)?;
+    for (key, description) in alarms {
+        let alarm_switch = HomeAssistantDiscovery {
+            state_topic: Some(mqtt_topic(mac_address, &format!("alarm/{}", key))),
+            command_topic: Some(mqtt_topic(mac_address, &format!("command/alarm/{}", key))),
+            icon: Some("mdi:alarm".to_owned()),
+            ..discovery(mac_address, &alarm_object_id(key), description)
+        };
+        publish_config(mqtt, mac_address, "switch", &alarm_switch)?;
+    }
+    for key in removed_alarms {
+        // an empty retained payload deletes the entity
+        mqtt.publish(
+            &discovery_topic(mac_address, "switch", &alarm_object_id(key)),
+            "",
+            true,
+        )?;
+    }
+    info!(
+        "Home Assistant discovery published, {} alarms",
+        alarms.len()
+    );
+    Ok(())
+}
+
+/// Publishes, retained, the state topics that changed since `previous`.
+pub fn publish_state(
+    mqtt: &mut dyn MqttClient,
+    mac_address: &str,
+    previous: Option<&HomeAssistantState>,
+    state: &HomeAssistantState,
+) -> Result<(), ClientError> {
+    if previous.map_or(true, |previous| previous.next_alarm != state.next_alarm) {
+        let next_alarm = state
+            .next_alarm
+            .map_or("None".to_owned(), |next_alarm| next_alarm.to_rfc3339());
+        mqtt.publish(&mqtt_topic(mac_address, "next-alarm"), &next_alarm, true)?;
+    }
+    if previous.map_or(true, |previous| previous.ringing != state.ringing) {
+        mqtt.publish(
+            &mqtt_topic(mac_address, "ringing"),
+            on_off(state.ringing),
+            true,
+        )?;
+    }
+    if previous.map_or(true, |previous| {
+        previous.alarms_enabled != state.alarms_enabled
+    }) {
+        mqtt.publish(
+            &mqtt_topic(mac_address, "alarms"),
+            on_off(state.alarms_enabled),
+            true,
+        )?;
+    }
+    for alarm in &state.alarms {
+        if previous.is_some_and(|previous| previous.alarms.contains(alarm)) {
+            continue;
+        }
+        let (key, switched_on) = alarm;
+        mqtt.publish(
+            &mqtt_topic(mac_address, &format!("alarm/{}", key)),
+            on_off(*switched_on),
+            true,
+        )?;
+    }
+    Ok(())
+}
+
+fn publish_config(
+    mqtt: &mut dyn MqttClient,
+    mac_address: &str,
+    component: &str,
+    discovery: &HomeAssistantDiscovery,
+) -> Result<(), ClientError> {
+    let payload = serde_json::to_string(discovery).unwrap();
+    mqtt.publish(
+        &discovery_topic(mac_address, component, &discovery.object_id),
+        &payload,
+        true,
+    )?;
+    Ok(())
+}
+
+/// An entity of the device, without state nor command topic.
+fn discovery(mac_address: &str, object_id: &str, name: &str) -> HomeAssistantDiscovery {
+    HomeAssistantDiscovery {
+        name: name.to_owned(),
+        unique_id: unique_id(mac_address, object_id),
+        object_id: object_id.to_owned(),
+        state_topic: None,
+        command_topic: None,
+        device_class: None,
+        icon: None,
+        availability_topic: mqtt_topic(mac_address, "status"),
+        device: device(mac_address),
+    }
+}
+
+/// `<HOME_ASSISTANT_DISCOVERY_PREFIX>/<component>/<node id>/<object id>/config`
+fn discovery_topic(mac_address: &str, component: &str, object_id: &str) -> String {
+    format!(
+        "{}/{}/{}/{}/config",
+        HOME_ASSISTANT_DISCOVERY_PREFIX,
+        component,
+        node_id(mac_address),
+        object_id
+    )
+}
+
+/// The MAC address without separators, discovery ids do not allow colons.
+fn node_id(mac_address: &str) -> String {
+    mac_address.replace(':', "").to_lowercase()
+}
+
+fn unique_id(mac_address: &str, object_id: &str) -> String {
+    format!("{}_{}", node_id(mac_address), object_id)
+}
+
+fn alarm_object_id(key: &str) -> String {
+    format!("alarm_{}", key)
+}
+
+fn device(mac_address: &str) -> HomeAssistantDevice {
+    HomeAssistantDevice {
+        identifiers: vec![node_id(mac_address)],
+        connections: vec![("mac".to_owned(), mac_address.to_lowercase())],
+        name: DEVICE_NAME.to_owned(),
+        model: DEVICE_TYPE.to_owned(),
+        manufacturer: MANUFACTURER.to_owned(),
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "ON"
+    } else {
+        "OFF"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::platform::mqtt::{MqttLastWill, MqttMessage};
+
+    const MAC_ADDRESS: &str = "02:00:00:00:00:0A";
+
+    /// Keeps what is published, retained or not.
+    #[derive(Default)]
+    struct FakeMqtt {
+        published: Vec<(String, String, bool)>,
+    }
+
+    impl MqttClient for FakeMqtt {
+        fn connect(
+            &mut self,
+            _client_id: &str,
+            _last_will: &MqttLastWill,
+        ) -> Result<(), anyhow::Error> {
+            Ok(())
+        }
+
+        fn is_connected(&mut self) -> bool {
+            true
+        }
+
+        fn subscribe(&mut self, _topic: &str) -> Result<(), anyhow::Error> {
+            Ok(())
+        }
+
+        fn publish(
+            &mut self,
+            topic: &str,
+            payload: &str,
+            retain: bool,
+        ) -> Result<(), anyhow::Error> {
+            self.published
+                .push((topic.to_owned(), payload.to_owned(), retain));
+            Ok(())
+        }
+
+        fn receive(&mut self) -> Option<MqttMessage> {
+            None
+        }
+    }
+
+    impl FakeMqtt {
+        /// The payload published on the discovery topic, parsed.
+        fn discovery(&self, component: &str, object_id: &str) -> Value {
+            let topic = format!(
+                "{}/{}/02000000000a/{}/config",
+                HOME_ASSISTANT_DISCOVERY_PREFIX, component, object_id
+            );
+            let (_, payload, retain) = self
+                .published
+                .iter()
+                .find(|(published, _, _)| *published == topic)
+                .unwrap_or_else(|| panic!("nothing published on {}", topic));
+            assert!(retain);
+            serde_json::from_str(payload).unwrap()
+        }
+
+        fn topics(&self) -> Vec<String> {
+            self.published
+                .iter()
+                .map(|(topic, _, _)| topic.clone())
+                .collect()
+        }
+    }
+
+    fn state() -> HomeAssistantState {
+        HomeAssistantState {
+            next_alarm: Some(DateTime::parse_from_rfc3339("2024-03-04T07:30:00+01:00").unwrap()),
+            ringing: false,
+            alarms_enabled: true,
+            alarms: vec![("0-30-7".to_owned(), true)],
+        }
+    }
+
+    #[test]
+    fn discovery_payloads_describe_the_device_entities() {
+        let mut mqtt = FakeMqtt::default();
+        publish_discovery(&mut mqtt, MAC_ADDRESS, &[], &[]).unwrap();
+        assert_eq!(mqtt.published.len(), 5);
+
+        let device = json!({
+            "identifiers": ["02000000000a"],
+            "connections": [["mac", "02:00:00:00:00:0a"]],
+            "name": DEVICE_NAME,
+            "model": DEVICE_TYPE,
+            "manufacturer": MANUFACTURER,
+        });
+        assert_eq!(
+            mqtt.discovery("button", "snooze"),
+            json!({
+                "name": "Snooze",
+                "unique_id": "02000000000a_snooze",
+                "command_topic": mqtt_topic(MAC_ADDRESS, "command/snooze"),
+                "icon": "mdi:alarm-snooze",
+                "availability_topic": mqtt_topic(MAC_ADDRESS, "status"),
+                "device": device,
+            })
+        );
+        let next_alarm = mqtt.discovery("sensor", "next_alarm");
+        assert_eq!(
+            next_alarm["state_topic"],
+            mqtt_topic(MAC_ADDRESS, "next-alarm")
+        );
+        assert_eq!(next_alarm["device_class"], "timestamp");
+        assert!(next_alarm.get("command_topic").is_none());
+        assert_eq!(
+            mqtt.discovery("binary_sensor", "ringing")["state_topic"],
+            mqtt_topic(MAC_ADDRESS, "ringing")
+        );
+        assert_eq!(
+            mqtt.discovery("button", "dismiss")["command_topic"],
+            mqtt_topic(MAC_ADDRESS, "command/dismiss")
+        );
+        let alarms = mqtt.discovery("switch", "alarms");
+        assert_eq!(alarms["state_topic"], mqtt_topic(MAC_ADDRESS, "alarms"));
+        assert_eq!(
+            alarms["command_topic"],
+            mqtt_topic(MAC_ADDRESS, "command/alarms")
+        );
+    }
+
+    #[test]
+    fn alarm_switches_are_added_and_removed() {
+        let mut mqtt = FakeMqtt::default();
+        publish_discovery(
+            &mut mqtt,
+            MAC_ADDRESS,
+            &[("0-30-7".to_owned(), "wake up".to_owned())],
+            &["0-0-9".to_owned()],
+        )
+        .unwrap();
+
+        let alarm = mqtt.discovery("switch", "alarm_0-30-7");
+        assert_eq!(alarm["name"], "wake up");
+        assert_eq!(alarm["unique_id"], "02000000000a_alarm_0-30-7");
+        assert_eq!(
+            alarm["state_topic"],
+            mqtt_topic(MAC_ADDRESS, "alarm/0-30-7")
+        );
+        assert_eq!(
+            alarm["command_topic"],
+            mqtt_topic(MAC_ADDRESS, "command/alarm/0-30-7")
+        );
+        let (topic, payload, retain) = mqtt.published.last().unwrap();
+        assert_eq!(
+            topic,
+            &format!(
+                "{}/switch/02000000000a/alarm_0-0-9/config",
+                HOME_ASSISTANT_DISCOVERY_PREFIX
+            )
+        );
+        assert!(payload.is_empty());
+        assert!(retain);
+    }
+
+    #[test]
+    fn only_the_changed_states_are_published() {
+        let mut mqtt = FakeMqtt::default();
+        publish_state(&mut mqtt, MAC_ADDRESS, None, &state()).unwrap();
+        assert_eq!(
+            mqtt.published,
+            [
+                (
+                    mqtt_topic(MAC_ADDRESS, "next-alarm"),
+                    "2024-03-04T07:30:00+01:00".to_owned(),
+                    true
+                ),
+                (mqtt_topic(MAC_ADDRESS, "ringing"), "OFF".to_owned(), true),
+                (mqtt_topic(MAC_ADDRESS, "alarms"), "ON".to_owned(), true),
+                (
+                    mqtt_topic(MAC_ADDRESS, "alarm/0-30-7"),
+                    "ON".to_owned(),
+                    true
+                ),
+            ]
+        );
+
+        let mut mqtt = FakeMqtt::default();
+        let changed = HomeAssistantState {
+            next_alarm: None,
+            ringing: true,
+            alarms: vec![("0-30-7".to_owned(), false)],
+            ..state()
+        };
+        publish_state(&mut mqtt, MAC_ADDRESS, Some(&state()), &changed).unwrap();
+        assert_eq!(
+            mqtt.topics(),
+            [
+                mqtt_topic(MAC_ADDRESS, "next-alarm"),
+                mqtt_topic(MAC_ADDRESS, "ringing"),
+                mqtt_topic(MAC_ADDRESS, "alarm/0-30-7"),
+            ]
+        );
+        assert_eq!(mqtt.published[0].1, "None");
+
+        let mut mqtt = FakeMqtt::default();
+        publish_state(&mut mqtt, MAC_ADDRESS, Some(&state()), &state()).unwrap();
+        assert!(mqtt.published.is_empty());
+    }
+}
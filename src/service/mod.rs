@@ -1,5 +1,10 @@
+pub mod api_service;
 pub mod client_service;
 pub mod clock_service;
+pub mod discovery_service;
+pub mod home_assistant_service;
+pub mod mqtt_service;
 pub mod orchestrator_service;
 pub mod peripheral_service;
+pub mod provisioning_service;
 pub mod wifi_service;
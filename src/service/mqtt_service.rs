@@ -0,0 +1,387 @@
+use crate::config::config::{DEVICE_DESCRIPTION, DEVICE_NAME, DEVICE_TYPE, MQTT_TOPIC_PREFIX};
+use crate::dto::{outbound_message::OutboundMessage, register_device::RegisterDeviceDTO};
+use crate::helper::configuration_helper::validate_configuration;
+use crate::helper::signature_helper::verified_configuration;
+use crate::platform::mqtt::{MqttClient, MqttLastWill};
+use crate::service::client_service::ClientError;
+use crate::ConfigurationResponse;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+
+/// What is received from the broker.
+pub enum MqttInbound {
+    Configuration(ConfigurationResponse),
+    Command(MqttCommand),
+}
+
+/// Sent on `<MQTT_TOPIC_PREFIX>/<mac address>/command/...`, by the Home
+/// Assistant entities.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MqttCommand {
+    Snooze,
+    Dismiss,
+    EnableAlarms(bool),
+    /// the key of the alarm, see `alarm_key`
+    EnableAlarm(String, bool),
+}
+
+/// `<MQTT_TOPIC_PREFIX>/<mac address>/<name>`
+pub fn mqtt_topic(mac_address: &str, name: &str) -> String {
+    format!("{}/{}/{}", MQTT_TOPIC_PREFIX, mac_address, name)
+}
+
+/// Starts the connection, with a Last Will that marks the device offline.
+pub fn connect_to_broker(mqtt: &mut dyn MqttClient, mac_address: &str) -> Result<(), ClientError> {
+    let last_will = MqttLastWill {
+        topic: mqtt_topic(mac_address, "status"),
+        payload: OFFLINE.to_owned(),
+    };
+    mqtt.connect(mac_address, &last_will)
+        .map_err(ClientError::from)
+}
+
+/// Called after each (re)connection: subscribes to the configuration topic
+/// and publishes, retained, the device description and its online status.
+pub fn start_mqtt_session(mqtt: &mut dyn MqttClient, mac_address: &str) -> Result<(), ClientError> {
+    mqtt.subscribe(&mqtt_topic(mac_address, "config"))?;
+    mqtt.subscribe(&mqtt_topic(mac_address, "command/#"))?;
+    let device = serde_json::to_string(&RegisterDeviceDTO::new(
+        mac_address.to_owned(),
+        DEVICE_TYPE.into(),
+        DEVICE_NAME.into(),
+        DEVICE_DESCRIPTION.into(),
+    ))
+    .unwrap();
+    mqtt.publish(&mqtt_topic(mac_address, "device"), &device, true)?;
+    mqtt.publish(&mqtt_topic(mac_address, "status"), ONLINE, true)?;
+    info!("MQTT session started");
+    Ok(())
+}
+
+pub fn publish_outbound_message(
+    mqtt: &mut dyn MqttClient,
+    mac_address: &str,
+    message: &OutboundMessage,
+) -> Result<(), ClientError> {
+    if !mqtt.is_connected() {
+        return Err(ClientError::Connection(
+            "not connected to the MQTT broker".to_owned(),
+        ));
+    }
+    let (name, payload) = match message {
+        OutboundMessage::Heartbeat { request, .. } => ("heartbeat", serde_json::to_string(request)),
+        OutboundMessage::AlarmConsumed(request) => {
+            ("events/alarm-consumed", serde_json::to_string(request))
+        }
+        OutboundMessage::AlarmEvent(request) => ("events/alarm", serde_json::to_string(request)),
+        OutboundMessage::Diagnostic(request) => {
+            ("events/diagnostic", serde_json::to_string(request))
+        }
+    };
+    mqtt.publish(&mqtt_topic(mac_address, name), &payload.unwrap(), false)?;
+    Ok(())
+}
+
+/// The configurations and commands received since the previous call.
+/// The configurations must pass the signature check of HTTP, which also
+/// refuses those older than `issued_after`, when the one in use was issued.
+pub fn receive_mqtt_messages(
+    mqtt: &mut dyn MqttClient,
+    mac_address: &str,
+    public_key: Option<&str>,
+    mut issued_after: Option<DateTime<Utc>>,
+) -> Vec<MqttInbound> {
+    let config_topic = mqtt_topic(mac_address, "config");
+    let command_topic = mqtt_topic(mac_address, "command/");
+    let mut received = Vec::new();
+    while let Some(message) = mqtt.receive() {
+        if message.topic == config_topic {
+            let configuration =
+                verified_configuration(&message.payload, public_key, mac_address, issued_after)
+                    .map_err(|message| format!("rejecting the configuration: {}", message))
+                    .and_then(|body| {
+                        serde_json::from_str::<ConfigurationResponse>(&body)
+                            .map_err(|e| format!("invalid configuration received: {}", e))
+                    })
+                    .and_then(|configuration| {
+                        validate_configuration(&configuration)
+                            .map(|()| configuration)
+                            .map_err(|e| format!("rejecting the configuration: {}", e))
+                    });
+            match configuration {
+                Ok(data) => {
+                    info!("[config receiver]: configuration received");
+                    issued_after = data.issued_at.or(issued_after);
+                    received.push(MqttInbound::Configuration(data));
+                }
+                Err(message) => warn!("[config receiver]: {}", message),
+            }
+            continue;
+        }
+        let command = message
+            .topic
+            .strip_prefix(&command_topic)
+            .and_then(|name| parse_command(name, message.payload.trim()));
+        match command {
+            Some(command) => {
+                info!("MQTT command received: {:?}", command);
+                received.push(MqttInbound::Command(command));
+            }
+            None => warn!(
+                "ignoring MQTT message on {}: {}",
+                message.topic, message.payload
+            ),
+        }
+    }
+    received
+}
+
+fn parse_command(name: &str, payload: &str) -> Option<MqttCommand> {
+    let switched_on = match payload {
+        "ON" => Some(true),
+        "OFF" => Some(false),
+        _ => None,
+    };
+    match name {
+        "snooze" => Some(MqttCommand::Snooze),
+        "dismiss" => Some(MqttCommand::Dismiss),
+        "alarms" => switched_on.map(MqttCommand::EnableAlarms),
+        _ => {
+            let key = name.strip_prefix("alarm/")?;
+            switched_on.map(|on| MqttCommand::EnableAlarm(key.to_owned(), on))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use chrono::DateTime;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    use super::*;
+    use crate::dto::{
+        alarm_consumed_request::AlarmConsumedRequest, signed_configuration::SignedConfiguration,
+    };
+    use crate::helper::tls_helper::{from_hex, to_hex};
+    use crate::platform::mqtt::MqttMessage;
+
+    const MAC_ADDRESS: &str = "02:00:00:00:00:01";
+    const CONFIGURATION: &str = r#"{"iamAliveEndpoint":"http://localhost/alive","iamAliveIntervalSeconds":30,"cronList":[{"cron":"0 30 7 * * * *","description":"wake up"}],"timezoneSeconds":3600,"alarmIntervalMinutes":1}"#;
+    // RFC 8032, test vector 1
+    const SECRET_KEY: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+    const PUBLIC_KEY: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+
+    /// Delivers `inbox`, keeping the subscriptions and what is published.
+    #[derive(Default)]
+    struct FakeMqtt {
+        connected: bool,
+        inbox: VecDeque<MqttMessage>,
+        subscribed: Vec<String>,
+        published: Vec<(String, String, bool)>,
+    }
+
+    impl FakeMqtt {
+        fn receiving(messages: &[(&str, &str)]) -> FakeMqtt {
+            FakeMqtt {
+                connected: true,
+                inbox: messages
+                    .iter()
+                    .map(|(name, payload)| MqttMessage {
+                        topic: mqtt_topic(MAC_ADDRESS, name),
+                        payload: payload.to_string(),
+                    })
+                    .collect(),
+                ..FakeMqtt::default()
+            }
+        }
+    }
+
+    impl MqttClient for FakeMqtt {
+        fn connect(
+            &mut self,
+            _client_id: &str,
+            _last_will: &MqttLastWill,
+        ) -> Result<(), anyhow::Error> {
+            self.connected = true;
+            Ok(())
+        }
+
+        fn is_connected(&mut self) -> bool {
+            self.connected
+        }
+
+        fn subscribe(&mut self, topic: &str) -> Result<(), anyhow::Error> {
+            self.subscribed.push(topic.to_owned());
+            Ok(())
+        }
+
+        fn publish(
+            &mut self,
+            topic: &str,
+            payload: &str,
+            retain: bool,
+        ) -> Result<(), anyhow::Error> {
+            self.published
+                .push((topic.to_owned(), payload.to_owned(), retain));
+            Ok(())
+        }
+
+        fn receive(&mut self) -> Option<MqttMessage> {
+            self.inbox.pop_front()
+        }
+    }
+
+    fn sign(configuration: &str) -> String {
+        let secret_key = <[u8; 32]>::try_from(from_hex(SECRET_KEY).unwrap()).unwrap();
+        let signature = SigningKey::from_bytes(&secret_key).sign(configuration.as_bytes());
+        serde_json::to_string(&SignedConfiguration {
+            configuration: configuration.to_owned(),
+            signature: to_hex(&signature.to_bytes()),
+        })
+        .unwrap()
+    }
+
+    fn receive(mqtt: &mut FakeMqtt, public_key: Option<&str>) -> Vec<MqttInbound> {
+        receive_mqtt_messages(mqtt, MAC_ADDRESS, public_key, None)
+    }
+
+    fn commands(received: &[MqttInbound]) -> Vec<MqttCommand> {
+        received
+            .iter()
+            .filter_map(|inbound| match inbound {
+                MqttInbound::Command(command) => Some(command.clone()),
+                MqttInbound::Configuration(_) => None,
+            })
+            .collect()
+    }
+
+    fn configurations(received: &[MqttInbound]) -> Vec<&ConfigurationResponse> {
+        received
+            .iter()
+            .filter_map(|inbound| match inbound {
+                MqttInbound::Configuration(configuration) => Some(configuration),
+                MqttInbound::Command(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn topics_are_under_the_prefix_and_the_mac_address() {
+        assert_eq!(
+            mqtt_topic(MAC_ADDRESS, "events/alarm"),
+            format!("{}/02:00:00:00:00:01/events/alarm", MQTT_TOPIC_PREFIX)
+        );
+
+        let mut mqtt = FakeMqtt::default();
+        start_mqtt_session(&mut mqtt, MAC_ADDRESS).unwrap();
+        assert_eq!(
+            mqtt.subscribed,
+            [
+                mqtt_topic(MAC_ADDRESS, "config"),
+                mqtt_topic(MAC_ADDRESS, "command/#")
+            ]
+        );
+        let published: Vec<(&str, bool)> = mqtt
+            .published
+            .iter()
+            .map(|(topic, _, retain)| (topic.as_str(), *retain))
+            .collect();
+        assert_eq!(
+            published,
+            [
+                (mqtt_topic(MAC_ADDRESS, "device").as_str(), true),
+                (mqtt_topic(MAC_ADDRESS, "status").as_str(), true)
+            ]
+        );
+        assert_eq!(mqtt.published[1].1, ONLINE);
+    }
+
+    #[test]
+    fn outbound_messages_are_published_on_their_topic() {
+        let mut mqtt = FakeMqtt::default();
+        let message = OutboundMessage::AlarmConsumed(AlarmConsumedRequest::new(
+            MAC_ADDRESS.to_owned(),
+            DateTime::parse_from_rfc3339("2024-03-04T07:30:00+01:00").unwrap(),
+            "wake up".to_owned(),
+        ));
+        assert!(publish_outbound_message(&mut mqtt, MAC_ADDRESS, &message).is_err());
+
+        mqtt.connected = true;
+        publish_outbound_message(&mut mqtt, MAC_ADDRESS, &message).unwrap();
+        let (topic, payload, retain) = &mqtt.published[0];
+        assert_eq!(topic, &mqtt_topic(MAC_ADDRESS, "events/alarm-consumed"));
+        assert!(payload.contains(r#""description":"wake up""#));
+        assert!(!retain);
+    }
+
+    #[test]
+    fn pushed_configurations_are_parsed_and_checked() {
+        let invalid_crontab = CONFIGURATION.replace("0 30 7 * * * *", "every morning");
+        let mut mqtt = FakeMqtt::receiving(&[
+            ("config", "not json"),
+            ("config", &invalid_crontab),
+            ("config", CONFIGURATION),
+        ]);
+        let received = receive(&mut mqtt, None);
+        let configurations = configurations(&received);
+        assert_eq!(configurations.len(), 1);
+        assert_eq!(configurations[0].cron_list[0].description, "wake up");
+    }
+
+    #[test]
+    fn pushed_configurations_must_be_signed_with_a_public_key() {
+        let older = CONFIGURATION.replacen(
+            '{',
+            r#"{"macAddress":"02:00:00:00:00:01","issuedAt":"2024-03-04T06:00:00Z","#,
+            1,
+        );
+        let newer = CONFIGURATION.replacen(
+            '{',
+            r#"{"macAddress":"02:00:00:00:00:01","issuedAt":"2024-03-04T07:00:00Z","#,
+            1,
+        );
+        let mut mqtt = FakeMqtt::receiving(&[
+            ("config", CONFIGURATION),
+            ("config", &sign(&newer)),
+            ("config", &sign(&older)),
+        ]);
+        let received = receive(&mut mqtt, Some(PUBLIC_KEY));
+        let configurations = configurations(&received);
+        assert_eq!(configurations.len(), 1);
+        assert_eq!(
+            configurations[0].issued_at,
+            Some(
+                DateTime::parse_from_rfc3339("2024-03-04T07:00:00Z")
+                    .unwrap()
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn commands_are_parsed() {
+        let mut mqtt = FakeMqtt::receiving(&[
+            ("command/snooze", ""),
+            ("command/dismiss", "PRESS"),
+            ("command/alarms", "OFF"),
+            ("command/alarm/0-30-7", " ON\n"),
+            ("command/alarms", "maybe"),
+            ("command/reboot", ""),
+            ("heartbeat", "{}"),
+        ]);
+        assert_eq!(
+            commands(&receive(&mut mqtt, None)),
+            [
+                MqttCommand::Snooze,
+                MqttCommand::Dismiss,
+                MqttCommand::EnableAlarms(false),
+                MqttCommand::EnableAlarm("0-30-7".to_owned(), true)
+            ]
+        );
+    }
+}
@@ -1,170 +1,282 @@
 use crate::{
-    config::config::{CHECK_INTERVAL_CONFIGURATION_CRON, ENABLE_I_AM_ALIVE_ACK},
+    config::config::{
+        CHECK_INTERVAL_CONFIGURATION_CRON, ENABLE_API_SERVER, ENABLE_HOME_ASSISTANT_DISCOVERY,
+        ENABLE_I_AM_ALIVE_ACK,
+    },
+    dto::config_response::Transport,
     helper::{
-        date_helper::{
-            calculate_next_scheduled_time, from_str_to_date_time_after, is_time_to_buzz,
-        },
+        alarm_helper::{calculate_next_alarm, ScheduledAlarm},
+        date_helper::from_str_to_date_time_after,
+        escalation_helper::intensity_percent,
+        local_alarm_helper::{apply_local_alarms, load_local_alarms},
         orchestrator_helper::{
-            calculate_alarm_next_date_time, load_remote_configuration_or_default,
-            retrieve_config_if_necessary, send_i_am_alive_if_necessary,
-            sync_system_clock_if_necessary, try_register_device,
+            calculate_alarm_next_date_time, deliver_outbound_messages, get_boot_transport,
+            handle_mqtt_messages, load_remote_configuration_or_default,
+            publish_home_assistant_if_necessary, queue_alarm_event, rediscover_server_if_necessary,
+            report_consumed_one_shot_alarms, retrieve_config_if_necessary,
+            send_i_am_alive_if_necessary, serve_api_requests, snooze_outcome,
+            sync_mqtt_if_necessary, sync_system_clock_if_necessary, track_missed_alarm,
+            track_ringing_alarm, try_register_device, HomeAssistantPublisher, LoopState,
+            MqttSession, ServerDiscovery,
         },
+        outbound_queue_helper::OutboundQueue,
+        signature_helper::load_configuration_public_key,
+        snooze_helper::{SnoozeSettings, SnoozeStateMachine},
+        timezone_helper::get_user_timezone,
     },
+    platform::device::Device,
     service::{
         clock_service::synchronize_clock_insistently_and_connect_wifi_if_necessary,
-        peripheral_service::buzz,
+        discovery_service::{advertise_device, locate_server},
+        peripheral_service::{buzz, play_melody},
         wifi_service::{get_mac_address, reconnect_to_wifi_insistently_if_needed},
     },
 };
 use chrono::{DateTime, FixedOffset, TimeZone, Timelike, Utc};
-use esp_idf_svc::{
-    eventloop::EspSystemEventLoop,
-    hal::{delay::FreeRtos, gpio::PinDriver, peripherals::Peripherals},
-    nvs::EspDefaultNvsPartition,
-    wifi::EspWifi,
-};
+use log::error;
 use log::info;
 use log::warn;
 
-pub fn orchestrate() {
-    let peripherals = Peripherals::take().unwrap();
-
-    let sys_loop = EspSystemEventLoop::take().unwrap();
-    let nvs = EspDefaultNvsPartition::take().unwrap();
+/// Runs the alarm clock on the given device. The loop never ends on the
+/// ESP32; the simulator passes `until` to stop once the virtual clock
+/// reaches that instant.
+pub fn orchestrate(device: &mut Device, until: Option<DateTime<Utc>>) {
+    reconnect_to_wifi_insistently_if_needed(device, false);
 
-    let mut wifi_driver = EspWifi::new(peripherals.modem, sys_loop, Some(nvs)).unwrap();
+    let mac_address = get_mac_address(device);
 
-    reconnect_to_wifi_insistently_if_needed(&mut wifi_driver, false);
+    synchronize_clock_insistently_and_connect_wifi_if_necessary(device, false);
 
-    let mac_address = get_mac_address(&mut wifi_driver);
+    advertise_device(device, &mac_address);
 
-    synchronize_clock_insistently_and_connect_wifi_if_necessary(&mut wifi_driver, false);
+    let transport = get_boot_transport(device);
+    if transport == Transport::Http {
+        locate_server(device);
+        try_register_device(device, &mac_address);
+    }
+    let mut server_discovery = ServerDiscovery::new();
 
-    try_register_device(&mac_address);
+    device.buzzer1.set_low();
+    device.buzzer2.set_low();
 
-    let mut buzzer1 = PinDriver::output(peripherals.pins.gpio5).unwrap();
-    let mut buzzer2 = PinDriver::output(peripherals.pins.gpio15).unwrap();
-    buzzer1.set_low().ok();
-    buzzer2.set_low().ok();
+    if ENABLE_API_SERVER {
+        if let Err(e) = device.api.start() {
+            error!("unable to start the local API: {:?}", e);
+        }
+    }
 
-    let mut configuration = load_remote_configuration_or_default(&mac_address);
+    let mut outbound_queue = OutboundQueue::load(&mut *device.storage);
+    let public_key = load_configuration_public_key(&mut *device.storage);
+    let configuration = load_remote_configuration_or_default(
+        device,
+        &mac_address,
+        transport,
+        &mut outbound_queue,
+        public_key.as_deref(),
+    );
+    let mut home_assistant = HomeAssistantPublisher::new();
+    let local_alarms = load_local_alarms(&mut *device.storage);
 
-    let user_timezone_offset = FixedOffset::east_opt(configuration.timezone_seconds).unwrap();
+    let user_timezone = get_user_timezone(&configuration);
 
-    let mut alarm = calculate_next_scheduled_time(&configuration.cron_list, &user_timezone_offset);
+    let mut snooze_button = configuration
+        .snooze_gpio
+        .and_then(|gpio| device.buttons.button(gpio));
 
-    let mut is_calculated_alarm_next_date_time = false;
-    let mut is_last_config_sync = false;
+    let alarm = calculate_next_alarm(
+        &device.clock.now(),
+        &apply_local_alarms(&configuration, &local_alarms),
+        &user_timezone,
+    );
 
-    let ntp_sync_time: DateTime<FixedOffset> = Utc
-        .with_ymd_and_hms(2023, 1, 1, 0, 0, 0)
-        .unwrap()
-        .with_timezone(&user_timezone_offset);
+    let ntp_sync_time: DateTime<FixedOffset> =
+        user_timezone.local_time(&Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
 
-    let mut ntp_synchronized = false;
-    let now = Utc::now().with_timezone(&user_timezone_offset);
+    let now = user_timezone.local_time(&device.clock.now());
 
-    let mut cron_time = from_str_to_date_time_after(
-        &now,
-        CHECK_INTERVAL_CONFIGURATION_CRON,
-        &user_timezone_offset,
-    );
+    let cron_time =
+        from_str_to_date_time_after(&now, CHECK_INTERVAL_CONFIGURATION_CRON, now.offset())
+            .expect("CHECK_INTERVAL_CONFIGURATION_CRON must be a valid crontab");
 
-    let mut i_am_alive_sent = false;
     let i_am_alive_cron_time = (now.second() + configuration.i_am_alive_interval_seconds) % 60;
-    loop {
-        let now = Utc::now().with_timezone(&user_timezone_offset);
+    let mut state = LoopState {
+        mac_address,
+        public_key,
+        configuration,
+        user_timezone,
+        local_alarms,
+        alarm,
+        ringing_alarm: None,
+        is_calculated_alarm_next_date_time: false,
+        consumed_one_shots: Vec::new(),
+        snooze: SnoozeStateMachine::new(),
+        outbound_queue,
+        mqtt_session: MqttSession::new(),
+        ntp_sync_time,
+        ntp_synchronized: false,
+        cron_time,
+        is_last_config_sync: false,
+        i_am_alive_cron_time,
+        i_am_alive_sent: false,
+    };
+    while until.map_or(true, |until| device.clock.now() < until) {
+        let now = state.user_timezone.local_time(&device.clock.now());
+        let snooze_pressed = snooze_button
+            .as_mut()
+            .is_some_and(|button| button.is_pressed());
 
-        if is_time_to_buzz(alarm, now) {
-            buzz_buzz_buzz(
-                &mut buzzer1,
-                &mut buzzer2,
+        if state.configuration.transport == Transport::Mqtt {
+            handle_mqtt_messages(&mut state, device);
+        }
+        if ENABLE_API_SERVER {
+            serve_api_requests(&mut state, now, device);
+        }
+
+        report_consumed_one_shot_alarms(
+            &state.configuration,
+            now,
+            &mut state.consumed_one_shots,
+            &mut state.outbound_queue,
+            &state.mac_address,
+        );
+        track_missed_alarm(
+            &state.alarm,
+            now,
+            &state.ringing_alarm,
+            &mut state.is_calculated_alarm_next_date_time,
+            &mut state.outbound_queue,
+            &state.mac_address,
+        );
+        let alarm_window_open = track_ringing_alarm(
+            &state.alarm,
+            now,
+            &mut state.ringing_alarm,
+            &mut state.outbound_queue,
+            &state.mac_address,
+        );
+
+        let previous_snooze_state = state.snooze.state();
+        let must_buzz = state.snooze.update(
+            now.with_timezone(&Utc),
+            alarm_window_open,
+            snooze_pressed,
+            &SnoozeSettings::from_configuration(&state.configuration, state.ringing_alarm.as_ref()),
+        );
+        if let (Some(outcome), Some(ringing_alarm)) = (
+            snooze_outcome(previous_snooze_state, state.snooze.state()),
+            &state.ringing_alarm,
+        ) {
+            queue_alarm_event(
+                &mut state.outbound_queue,
+                &state.mac_address,
+                ringing_alarm,
                 now,
-                alarm,
-                &mut is_calculated_alarm_next_date_time,
+                outcome,
             );
-        } else {
-            sync_data_if_needed(
-                &mut is_calculated_alarm_next_date_time,
-                &mut alarm,
-                &mut configuration,
-                user_timezone_offset,
-                &mut ntp_synchronized,
-                ntp_sync_time,
+        }
+        if ENABLE_HOME_ASSISTANT_DISCOVERY && state.configuration.transport == Transport::Mqtt {
+            publish_home_assistant_if_necessary(
+                &mut home_assistant,
+                &state.mac_address,
+                &state.configuration,
+                &state.alarm,
+                must_buzz,
+                &state.local_alarms,
+                device,
+            );
+        }
+        if let (true, Some(ringing_alarm)) = (must_buzz, &state.ringing_alarm) {
+            buzz_buzz_buzz(
+                device,
                 now,
-                &mut wifi_driver,
-                &mut is_last_config_sync,
-                &mut cron_time,
-                &mac_address,
-                &mut i_am_alive_sent,
-                i_am_alive_cron_time,
+                ringing_alarm,
+                &mut state.is_calculated_alarm_next_date_time,
             );
+        } else {
+            if state.configuration.transport == Transport::Http {
+                rediscover_server_if_necessary(
+                    &mut server_discovery,
+                    &mut state.outbound_queue,
+                    device,
+                );
+            }
+            sync_data_if_needed(&mut state, now, device);
 
-            FreeRtos::delay_ms(100);
+            device.clock.delay_ms(100);
         }
     }
 }
 
-fn sync_data_if_needed(
-    is_calculated_alarm_next_date_time: &mut bool,
-    alarm: &mut DateTime<FixedOffset>,
-    configuration: &mut crate::dto::config_response::Configuration,
-    user_timezone_offset: FixedOffset,
-    ntp_synchronized: &mut bool,
-    ntp_sync_time: DateTime<FixedOffset>,
-    now: DateTime<FixedOffset>,
-    wifi_driver: &mut EspWifi<'static>,
-    is_last_config_sync: &mut bool,
-    cron_time: &mut DateTime<FixedOffset>,
-    mac_address: &String,
-    i_am_alive_sent: &mut bool,
-    i_am_alive_cron_time: u32,
-) {
+fn sync_data_if_needed(state: &mut LoopState, now: DateTime<FixedOffset>, device: &mut Device) {
     info!(
         "calculated buzz time (now => buzz time): {:?} => {:?}",
-        now, alarm
-    );
-
-    retrieve_config_if_necessary(
-        is_last_config_sync,
-        cron_time,
-        user_timezone_offset,
         now,
-        mac_address,
-        configuration,
-        wifi_driver,
-        is_calculated_alarm_next_date_time
+        state.alarm.as_ref().map(|alarm| alarm.date_time)
     );
 
+    match state.configuration.transport {
+        Transport::Http => retrieve_config_if_necessary(state, now, device),
+        Transport::Mqtt => sync_mqtt_if_necessary(
+            &mut state.mqtt_session,
+            &state.mac_address,
+            device,
+            &mut state.outbound_queue,
+        ),
+    }
+
+    if !state.is_calculated_alarm_next_date_time {
+        state.user_timezone = get_user_timezone(&state.configuration);
+    }
+
     calculate_alarm_next_date_time(
-        is_calculated_alarm_next_date_time,
-        alarm,
-        &*configuration,
-        user_timezone_offset,
+        &mut state.is_calculated_alarm_next_date_time,
+        &mut state.alarm,
+        &apply_local_alarms(&state.configuration, &state.local_alarms),
+        &state.user_timezone,
+        now,
     );
 
-    sync_system_clock_if_necessary(ntp_synchronized, ntp_sync_time, now, wifi_driver);
+    sync_system_clock_if_necessary(
+        &mut state.ntp_synchronized,
+        state.ntp_sync_time,
+        now,
+        device,
+    );
 
     if ENABLE_I_AM_ALIVE_ACK {
         send_i_am_alive_if_necessary(
-            i_am_alive_sent,
-            i_am_alive_cron_time,
+            &mut state.i_am_alive_sent,
+            state.i_am_alive_cron_time,
             now,
-            mac_address,
-            configuration,
-            wifi_driver,
+            &state.mac_address,
+            &mut state.configuration,
+            &mut state.outbound_queue,
         );
     }
+
+    deliver_outbound_messages(
+        &mut state.outbound_queue,
+        &state.mac_address,
+        state.configuration.transport,
+        device,
+    );
 }
 
 fn buzz_buzz_buzz(
-    buzzer1: &mut PinDriver<'_, esp_idf_svc::hal::gpio::Gpio5, esp_idf_svc::hal::gpio::Output>,
-    buzzer2: &mut PinDriver<'_, esp_idf_svc::hal::gpio::Gpio15, esp_idf_svc::hal::gpio::Output>,
+    device: &mut Device,
     now: DateTime<FixedOffset>,
-    alarm: DateTime<FixedOffset>,
+    alarm: &ScheduledAlarm,
     is_calculated_alarm_next_date_time: &mut bool,
 ) {
-    buzz(buzzer1, buzzer2);
-    warn!("bzzzzzzzz: {:?} => {:?}", now, alarm);
+    let elapsed_ms = (now - alarm.date_time).num_milliseconds().max(0) as u64;
+    let intensity = intensity_percent(alarm.escalation.as_ref(), elapsed_ms);
+    match &alarm.melody {
+        Some(melody) => play_melody(device, melody, elapsed_ms, &alarm.outputs, intensity),
+        None => buzz(device, alarm.pattern, &alarm.outputs, intensity),
+    }
+    warn!(
+        "bzzzzzzzz: {:?} => {:?} ({})",
+        now, alarm.date_time, alarm.description
+    );
     *is_calculated_alarm_next_date_time = false;
 }
@@ -1,13 +1,92 @@
-use esp_idf_svc::hal::{delay::FreeRtos, gpio::PinDriver};
+use crate::{
+    dto::config_cron_list_response::{BuzzerOutput, BuzzerPattern},
+    helper::{escalation_helper::silence_after_cycle_ms, melody_helper::Melody},
+    platform::{device::Device, tone::ToneOutput},
+};
 
+// longest slice of a note played in one call, so the snooze button is read
+const MAX_NOTE_SLICE_MILLISECONDS: u32 = 500;
+
+/// Plays one cycle of `pattern` on `outputs`, leaving every output low.
+/// Below full intensity the outputs are quieter and the cycle is followed by
+/// a silence.
 pub fn buzz(
-    buzzer1: &mut PinDriver<'_, esp_idf_svc::hal::gpio::Gpio5, esp_idf_svc::hal::gpio::Output>,
-    buzzer2: &mut PinDriver<'_, esp_idf_svc::hal::gpio::Gpio15, esp_idf_svc::hal::gpio::Output>,
+    device: &mut Device,
+    pattern: BuzzerPattern,
+    outputs: &[BuzzerOutput],
+    intensity_percent: u8,
 ) {
-    buzzer1.set_high().ok();
-    FreeRtos::delay_ms(100);
-    buzzer1.set_low().ok();
-    buzzer2.set_high().ok();
-    FreeRtos::delay_ms(100);
-    buzzer2.set_low().ok();
+    set_volume(device, outputs, intensity_percent);
+    let cycle_ms = match pattern {
+        BuzzerPattern::Alternate => {
+            for output in [BuzzerOutput::Buzzer1, BuzzerOutput::Buzzer2] {
+                // a missing output keeps its slot, so the rhythm stays the same
+                if outputs.contains(&output) {
+                    output_pin(device, output).set_high();
+                    device.clock.delay_ms(100);
+                    output_pin(device, output).set_low();
+                } else {
+                    device.clock.delay_ms(100);
+                }
+            }
+            200
+        }
+        BuzzerPattern::Together => pulse(device, outputs, 100, 100),
+        BuzzerPattern::Chime => pulse(device, outputs, 100, 900),
+        BuzzerPattern::Continuous => pulse(device, outputs, 1000, 0),
+    };
+    let silence_ms = silence_after_cycle_ms(intensity_percent, cycle_ms);
+    if silence_ms > 0 {
+        device.clock.delay_ms(silence_ms);
+    }
+}
+
+/// Plays the note of `melody` due `elapsed_ms` after the alarm started, then
+/// silences `outputs`: notes longer than a slice are played over several calls.
+pub fn play_melody(
+    device: &mut Device,
+    melody: &Melody,
+    elapsed_ms: u64,
+    outputs: &[BuzzerOutput],
+    intensity_percent: u8,
+) {
+    set_volume(device, outputs, intensity_percent);
+    let (frequency_hz, remaining_ms) = melody.note_at(elapsed_ms);
+    for output in outputs {
+        output_pin(device, *output).set_tone(frequency_hz);
+    }
+    device
+        .clock
+        .delay_ms(remaining_ms.min(MAX_NOTE_SLICE_MILLISECONDS));
+    for output in outputs {
+        output_pin(device, *output).set_tone(0);
+    }
+}
+
+fn set_volume(device: &mut Device, outputs: &[BuzzerOutput], intensity_percent: u8) {
+    for output in outputs {
+        output_pin(device, *output).set_volume(intensity_percent);
+    }
+}
+
+/// Returns the length of the cycle.
+fn pulse(device: &mut Device, outputs: &[BuzzerOutput], on_ms: u32, off_ms: u32) -> u32 {
+    for output in outputs {
+        output_pin(device, *output).set_high();
+    }
+    device.clock.delay_ms(on_ms);
+    for output in outputs {
+        output_pin(device, *output).set_low();
+    }
+    if off_ms > 0 {
+        device.clock.delay_ms(off_ms);
+    }
+    on_ms + off_ms
+}
+
+fn output_pin(device: &mut Device, output: BuzzerOutput) -> &mut dyn ToneOutput {
+    match output {
+        BuzzerOutput::Buzzer1 => &mut *device.buzzer1,
+        BuzzerOutput::Buzzer2 => &mut *device.buzzer2,
+    }
 }
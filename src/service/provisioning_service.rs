@@ -0,0 +1,97 @@
+use chrono::Duration;
+use log::{error, info, warn};
+
+use crate::config::config::{DEVICE_NAME, PROVISIONING_TIMEOUT_MINUTES};
+use crate::dto::wifi_settings::WifiSettings;
+use crate::helper::provisioning_helper::{
+    apply_setup_form, load_wifi_settings, parse_setup_form, provisioning_password,
+    provisioning_ssid, save_wifi_settings, saved_page, setup_page, SetupForm,
+};
+use crate::platform::api_server::{ApiRequest, ApiResponse};
+use crate::platform::device::Device;
+
+// lets the saved page reach the phone before the restart
+const RESTART_DELAY_MILLISECONDS: u32 = 2000;
+
+/// Answers a request of the setup portal, with the form to save when it is
+/// valid. Any other page shows the form, so that the captive
+/// portal check of the phones opens it.
+pub fn handle_setup_request(
+    request: &ApiRequest,
+    settings: &WifiSettings,
+) -> (ApiResponse, Option<SetupForm>) {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/setup") => match parse_setup_form(&request.body) {
+            Ok(form) => (
+                ApiResponse::html(200, saved_page(DEVICE_NAME, &form.credentials)),
+                Some(form),
+            ),
+            Err(message) => (
+                ApiResponse::html(400, setup_page(DEVICE_NAME, settings, Some(&message))),
+                None,
+            ),
+        },
+        ("GET", _) => (
+            ApiResponse::html(200, setup_page(DEVICE_NAME, settings, None)),
+            None,
+        ),
+        _ => (ApiResponse::error(405, "method not allowed"), None),
+    }
+}
+
+/// Turns the device into an access point serving the setup page, protected
+/// by the passphrase of `provisioning_password`. Once
+/// a network is saved the device restarts to use it; without saved networks
+/// the portal never gives up, otherwise it returns after
+/// `PROVISIONING_TIMEOUT_MINUTES` so that the saved networks are tried again.
+pub fn run_provisioning_portal(device: &mut Device) {
+    let mut settings = load_wifi_settings(&mut *device.storage);
+    let mac_address = device.wifi.mac_address();
+    let ssid = provisioning_ssid(&mac_address);
+    let password = provisioning_password(&mac_address);
+    if let Err(e) = device.wifi.start_access_point(&ssid, &password) {
+        error!("unable to start the setup access point: {:?}", e);
+        return;
+    }
+    if let Err(e) = device.portal.start() {
+        error!("unable to start the setup portal: {:?}", e);
+        return;
+    }
+    warn!(
+        "setup portal started, join the {} WiFi network with the passphrase of the device label",
+        ssid
+    );
+
+    let started = device.clock.now();
+    loop {
+        let mut posted_form = None;
+        device.portal.serve(&mut |request| {
+            let (response, form) = handle_setup_request(request, &settings);
+            if form.is_some() {
+                posted_form = form;
+            }
+            response
+        });
+        if let Some(form) = posted_form {
+            let ssid = form.credentials.ssid.clone();
+            apply_setup_form(&mut settings, form);
+            match save_wifi_settings(&mut *device.storage, &settings) {
+                Ok(()) => {
+                    info!("WiFi network {} saved", ssid);
+                    device.clock.delay_ms(RESTART_DELAY_MILLISECONDS);
+                    device.portal.stop();
+                    device.system.restart();
+                    return;
+                }
+                Err(e) => error!("unable to save the WiFi settings: {:?}", e),
+            }
+        }
+        let timeout = Duration::minutes(PROVISIONING_TIMEOUT_MINUTES as i64);
+        if !settings.networks.is_empty() && device.clock.now() - started > timeout {
+            warn!("setup portal timed out, trying the saved networks again");
+            device.portal.stop();
+            return;
+        }
+        device.clock.delay_ms(100);
+    }
+}
@@ -1,55 +1,93 @@
-use embedded_svc::wifi::ClientConfiguration;
-use embedded_svc::wifi::Configuration;
-use esp_idf_svc::hal::delay::FreeRtos;
-use esp_idf_svc::wifi::EspWifi;
-use esp_idf_svc::wifi::WifiDeviceId;
-use esp_idf_sys::EspError;
 use log::error;
+use log::info;
 use log::warn;
 
-use crate::config::config::WIFI_PASS;
-use crate::config::config::WIFI_SSID;
-pub fn reconnect_to_wifi_insistently_if_needed(wifi_driver: &mut EspWifi<'_>, one_shot: bool) {
-    while wifi_driver.is_connected().is_err() || !wifi_driver.is_connected().unwrap() {
-        warn!("reconnecting to WiFi...");
-        if connect_to_wifi(wifi_driver, one_shot).is_err() {
-            error!("failed to connect to the WiFi network");
+use crate::config::config::{PROVISIONING_AFTER_FAILED_CONNECTIONS, WIFI_FAILOVER_AFTER_FAILURES};
+use crate::dto::{wifi_credentials::WifiCredentials, wifi_settings::WifiSettings};
+use crate::helper::provisioning_helper::{load_wifi_settings, save_last_connected_network};
+use crate::helper::wifi_network_helper::rank_networks;
+use crate::platform::device::Device;
+use crate::service::provisioning_service::run_provisioning_portal;
+
+/// Connects to the best saved network in range, failing over to the next
+/// one after `WIFI_FAILOVER_AFTER_FAILURES` failed connections; `one_shot`
+/// tries each network once. Otherwise the setup portal starts when there is
+/// no saved network or after `PROVISIONING_AFTER_FAILED_CONNECTIONS` failed
+/// connections.
+pub fn reconnect_to_wifi_insistently_if_needed(device: &mut Device, one_shot: bool) {
+    let mut failed_connections = 0;
+    while !device.wifi.is_connected() {
+        let settings = load_wifi_settings(&mut *device.storage);
+        if settings.networks.is_empty() {
+            if one_shot {
+                error!("no WiFi network configured");
+            } else {
+                warn!("no WiFi network configured");
+                run_provisioning_portal(device);
+            }
+        } else {
+            let scanned = device.wifi.scan().unwrap_or_else(|e| {
+                warn!("unable to scan the WiFi networks: {}", e);
+                Vec::new()
+            });
+            let attempts = if one_shot {
+                1
+            } else {
+                WIFI_FAILOVER_AFTER_FAILURES
+            };
+            let ranked: Vec<WifiCredentials> = rank_networks(&settings, &scanned)
+                .into_iter()
+                .cloned()
+                .collect();
+            for credentials in ranked {
+                if try_network(device, &credentials, attempts, &mut failed_connections) {
+                    remember_network(device, &settings, &credentials.ssid);
+                    break;
+                }
+                warn!("failing over from WiFi {}", credentials.ssid);
+            }
         }
-        if one_shot {
+        if one_shot || device.wifi.is_connected() {
             break;
         }
-        FreeRtos::delay_ms(100);
+        if failed_connections >= PROVISIONING_AFTER_FAILED_CONNECTIONS {
+            run_provisioning_portal(device);
+            failed_connections = 0;
+        }
+        device.clock.delay_ms(100);
     }
 }
 
-pub fn connect_to_wifi(wifi_driver: &mut EspWifi<'_>, one_shot: bool) -> Result<(), EspError> {
-    wifi_driver.set_configuration(&Configuration::Client(ClientConfiguration {
-        ssid: WIFI_SSID.into(),
-        password: WIFI_PASS.into(),
-        ..Default::default()
-    }))?;
-
-    wifi_driver.start()?;
-    wifi_driver.connect()?;
-    let mut attempts = 0;
-    while !wifi_driver.is_connected()? {
-        let config = wifi_driver.get_configuration()?;
-        warn!("Waiting for connection instauration {:?}", config);
-        FreeRtos::delay_ms(100);
-        attempts += 1;
-        if one_shot && attempts > 5000 {
-            break;
-        }
-        if attempts > 300000 {
-            return Err(EspError::from(1).unwrap());
+/// Whether one of the `attempts` connections to the network succeeded.
+fn try_network(
+    device: &mut Device,
+    credentials: &WifiCredentials,
+    attempts: u32,
+    failed_connections: &mut u32,
+) -> bool {
+    for _ in 0..attempts {
+        warn!("reconnecting to WiFi {}...", credentials.ssid);
+        match device.wifi.connect(credentials) {
+            Ok(()) => return true,
+            Err(e) => {
+                error!("failed to connect to the WiFi network: {}", e);
+                *failed_connections += 1;
+            }
         }
     }
-    Ok(())
+    false
+}
+
+fn remember_network(device: &mut Device, settings: &WifiSettings, ssid: &str) {
+    info!("connected to WiFi {}", ssid);
+    if settings.last_connected.as_deref() == Some(ssid) {
+        return;
+    }
+    if let Err(e) = save_last_connected_network(&mut *device.storage, ssid) {
+        error!("unable to save the last connected WiFi network: {:?}", e);
+    }
 }
 
-pub fn get_mac_address(wifi: &mut EspWifi<'static>) -> String {
-    let mav = wifi.driver().get_mac(WifiDeviceId::Sta).unwrap();
-    let mac_address_obj = macaddr::MacAddr6::new(mav[0], mav[1], mav[2], mav[3], mav[4], mav[5]);
-    let mac_address_value = mac_address_obj.to_string();
-    mac_address_value
+pub fn get_mac_address(device: &mut Device) -> String {
+    device.wifi.mac_address()
 }
@@ -0,0 +1,57 @@
+use std::{cell::Cell, collections::VecDeque, rc::Rc};
+
+use chrono::{DateTime, Utc};
+
+use crate::platform::api_server::{ApiRequest, ApiResponse, ApiServer};
+
+/// Stand-in for the embedded HTTP server: the scheduled requests are
+/// received once the virtual clock reaches their time, and the responses
+/// are printed.
+pub struct ApiStub {
+    name: &'static str,
+    now: Rc<Cell<DateTime<Utc>>>,
+    scheduled: VecDeque<(DateTime<Utc>, ApiRequest)>,
+}
+
+impl ApiStub {
+    pub fn new(
+        name: &'static str,
+        now: Rc<Cell<DateTime<Utc>>>,
+        mut requests: Vec<(DateTime<Utc>, ApiRequest)>,
+    ) -> ApiStub {
+        requests.sort_by_key(|(at, _)| *at);
+        ApiStub {
+            name,
+            now,
+            scheduled: requests.into(),
+        }
+    }
+}
+
+impl ApiServer for ApiStub {
+    fn start(&mut self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    fn stop(&mut self) {}
+
+    fn serve(&mut self, handler: &mut dyn FnMut(&ApiRequest) -> ApiResponse) {
+        while self
+            .scheduled
+            .front()
+            .is_some_and(|(at, _)| *at <= self.now.get())
+        {
+            let (_, request) = self.scheduled.pop_front().unwrap();
+            let response = handler(&request);
+            // pages are too long to be printed
+            let body = match response.content_type.starts_with("text/html") {
+                true => format!("<{} bytes of HTML>", response.body.len()),
+                false => response.body,
+            };
+            println!(
+                "[{}] {} {} {} => {} {}",
+                self.name, request.method, request.path, request.body, response.status, body
+            );
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    cell::Cell,
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+
+use super::outages::Outages;
+
+use crate::{
+    config::config::HTTP_MAX_RESPONSE_SIZE,
+    dto::device_credentials::DeviceCredentials,
+    helper::device_auth_helper::{
+        sign_request, AUTHORIZATION_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER,
+    },
+    helper::response_body_helper::read_response_body,
+    platform::http::{HttpClient, HttpResponse, HttpTransportError},
+};
+
+/// Local stand-in for the Elisys server: every known URL answers with a
+/// fixed body, anything else fails like an unreachable host. Bodies carry
+/// an `ETag`, and a matching `If-None-Match` gets `304 Not Modified`.
+/// During an outage every request fails.
+#[derive(Default)]
+pub struct HttpStub {
+    routes: HashMap<String, String>,
+    outages: Outages,
+    authority: Option<StubAuthority>,
+}
+
+/// Kind of credentials the stub issues at registration.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StubCredentials {
+    Token,
+    Secret,
+}
+
+/// Issues credentials at each registration and requires them on the other
+/// requests, until they are revoked.
+struct StubAuthority {
+    server_url: String,
+    register_url: String,
+    kind: StubCredentials,
+    issued: Option<DeviceCredentials>,
+    registrations: u32,
+    now: Rc<Cell<DateTime<Utc>>>,
+    revocations: Vec<DateTime<Utc>>,
+}
+
+impl StubAuthority {
+    fn issue(&mut self) -> String {
+        self.registrations += 1;
+        let value = format!("simulated-{}", self.registrations);
+        let credentials = match self.kind {
+            StubCredentials::Token => DeviceCredentials {
+                token: Some(value),
+                secret: None,
+            },
+            StubCredentials::Secret => DeviceCredentials {
+                token: None,
+                secret: Some(value),
+            },
+        };
+        info!("[http stub] credentials issued: {:?}", credentials);
+        let body = serde_json::to_string(&credentials).unwrap();
+        self.issued = Some(credentials);
+        body
+    }
+
+    /// Whether the request carries the credentials issued last.
+    fn accepts(&mut self, url: &str, headers: &[(&str, &str)], payload: &[u8]) -> bool {
+        let now = self.now.get();
+        if self.revocations.iter().any(|at| *at <= now) {
+            self.revocations.retain(|at| *at > now);
+            warn!("[http stub] credentials revoked");
+            self.issued = None;
+        }
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| *value)
+        };
+        match self.issued.as_ref() {
+            Some(DeviceCredentials {
+                token: Some(token), ..
+            }) => header(AUTHORIZATION_HEADER) == Some(&format!("Bearer {}", token)),
+            Some(DeviceCredentials {
+                secret: Some(secret),
+                ..
+            }) => match header(TIMESTAMP_HEADER).and_then(|value| value.parse().ok()) {
+                Some(timestamp) => {
+                    header(SIGNATURE_HEADER) == Some(&sign_request(secret, timestamp, url, payload))
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+impl HttpStub {
+    pub fn new() -> HttpStub {
+        HttpStub {
+            routes: HashMap::new(),
+            outages: Outages::default(),
+            authority: None,
+        }
+    }
+
+    pub fn route(mut self, url: &str, body: String) -> HttpStub {
+        self.routes.insert(url.to_owned(), body);
+        self
+    }
+
+    pub fn outages(mut self, outages: Outages) -> HttpStub {
+        self.outages = outages;
+        self
+    }
+
+    /// Answers `register_url` with new credentials of that kind, and the
+    /// other routes of `server_url` with `401 Unauthorized` without them.
+    /// The credentials are revoked at the `revocations` times.
+    pub fn authentication(
+        mut self,
+        server_url: &str,
+        register_url: &str,
+        kind: StubCredentials,
+        now: Rc<Cell<DateTime<Utc>>>,
+        revocations: Vec<DateTime<Utc>>,
+    ) -> HttpStub {
+        self.authority = Some(StubAuthority {
+            server_url: server_url.to_owned(),
+            register_url: register_url.to_owned(),
+            kind,
+            issued: None,
+            registrations: 0,
+            now,
+            revocations,
+        });
+        self
+    }
+}
+
+impl HttpClient for HttpStub {
+    fn post(
+        &mut self,
+        url: &str,
+        headers: &[(&str, &str)],
+        payload: &[u8],
+    ) -> Result<HttpResponse, anyhow::Error> {
+        info!(
+            "[http stub] -> POST {} {:?} {}",
+            url,
+            headers,
+            String::from_utf8_lossy(payload)
+        );
+        let body = match self.routes.get(url) {
+            Some(body) if !self.outages.is_down() => body,
+            _ => {
+                return Err(HttpTransportError::Connection(format!(
+                    "[http stub] connection refused: {}",
+                    url
+                ))
+                .into())
+            }
+        };
+        if let Some(authority) = self.authority.as_mut() {
+            if url == authority.register_url {
+                return Ok(HttpResponse {
+                    status: 200,
+                    headers: Vec::new(),
+                    body: authority.issue(),
+                });
+            }
+            if url.starts_with(&authority.server_url) && !authority.accepts(url, headers, payload) {
+                info!("[http stub] <- 401 {}", url);
+                return Ok(HttpResponse {
+                    status: 401,
+                    headers: Vec::new(),
+                    body: "{\"error\": \"invalid device credentials\"}".to_owned(),
+                });
+            }
+        }
+        let mut hasher = DefaultHasher::new();
+        body.hash(&mut hasher);
+        let etag = format!("\"{:x}\"", hasher.finish());
+        let not_modified = headers
+            .iter()
+            .any(|(name, value)| name.eq_ignore_ascii_case("If-None-Match") && *value == etag);
+        if not_modified {
+            return Ok(HttpResponse {
+                status: 304,
+                headers: vec![("ETag".to_owned(), etag)],
+                body: String::new(),
+            });
+        }
+        // same size checks as on the device
+        let mut remaining = body.as_bytes();
+        let body = read_response_body(
+            |buf| {
+                let bytes_read = remaining.len().min(buf.len());
+                buf[..bytes_read].copy_from_slice(&remaining[..bytes_read]);
+                remaining = &remaining[bytes_read..];
+                Ok(bytes_read)
+            },
+            Some(body.len()),
+            HTTP_MAX_RESPONSE_SIZE,
+        )?;
+        Ok(HttpResponse {
+            status: 200,
+            headers: vec![("ETag".to_owned(), etag)],
+            body,
+        })
+    }
+}
@@ -0,0 +1,46 @@
+use crate::platform::mdns::{Mdns, MdnsService};
+
+/// Stand-in for the mDNS responder: the announcement of the device is
+/// printed and a browse finds the given servers.
+pub struct MdnsStub {
+    servers: Vec<MdnsService>,
+}
+
+impl MdnsStub {
+    pub fn new(servers: Vec<MdnsService>) -> MdnsStub {
+        MdnsStub { servers }
+    }
+}
+
+impl Mdns for MdnsStub {
+    fn advertise(
+        &mut self,
+        hostname: &str,
+        instance: &str,
+        service: &str,
+        protocol: &str,
+        port: u16,
+        txt: &[(&str, &str)],
+    ) -> Result<(), anyhow::Error> {
+        println!(
+            "[mdns stub] {}.local announces \"{}\" {}.{} port {} {:?}",
+            hostname, instance, service, protocol, port, txt
+        );
+        Ok(())
+    }
+
+    fn browse(
+        &mut self,
+        service: &str,
+        protocol: &str,
+        _timeout_ms: u32,
+    ) -> Result<Vec<MdnsService>, anyhow::Error> {
+        println!(
+            "[mdns stub] browsing {}.{}: {} found",
+            service,
+            protocol,
+            self.servers.len()
+        );
+        Ok(self.servers.clone())
+    }
+}
@@ -0,0 +1,12 @@
+pub mod api_stub;
+pub mod http_stub;
+pub mod mdns_stub;
+pub mod mqtt_stub;
+pub mod outages;
+pub mod runner;
+pub mod simulated_clock;
+pub mod simulated_gpio;
+pub mod simulated_system;
+pub mod simulated_wifi;
+pub mod tcp_mqtt;
+pub mod tls_stub;
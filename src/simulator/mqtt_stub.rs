@@ -0,0 +1,131 @@
+use std::{
+    cell::Cell,
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+};
+
+use chrono::{DateTime, Utc};
+
+use log::info;
+
+use super::outages::Outages;
+use crate::platform::{
+    http::HttpTransportError,
+    mqtt::{MqttClient, MqttLastWill, MqttMessage},
+};
+
+/// Local stand-in for the MQTT broker: a retained message is delivered when
+/// its topic is subscribed, and everything published is printed. During an
+/// outage the connection drops and the Last Will is printed. Scheduled
+/// messages are received once the virtual clock reaches their time.
+#[derive(Default)]
+pub struct MqttStub {
+    retained: HashMap<String, String>,
+    received: VecDeque<MqttMessage>,
+    last_will: Option<(String, String)>,
+    connected: bool,
+    outages: Outages,
+    now: Option<Rc<Cell<DateTime<Utc>>>>,
+    scheduled: VecDeque<(DateTime<Utc>, MqttMessage)>,
+}
+
+impl MqttStub {
+    pub fn new() -> MqttStub {
+        MqttStub::default()
+    }
+
+    pub fn retain(mut self, topic: &str, payload: String) -> MqttStub {
+        self.retained.insert(topic.to_owned(), payload);
+        self
+    }
+
+    pub fn outages(mut self, outages: Outages) -> MqttStub {
+        self.outages = outages;
+        self
+    }
+
+    pub fn schedule(
+        mut self,
+        now: Rc<Cell<DateTime<Utc>>>,
+        mut messages: Vec<(DateTime<Utc>, MqttMessage)>,
+    ) -> MqttStub {
+        messages.sort_by_key(|(at, _)| *at);
+        self.now = Some(now);
+        self.scheduled = messages.into();
+        self
+    }
+
+    fn not_connected() -> anyhow::Error {
+        HttpTransportError::Connection("[mqtt stub] not connected".to_owned()).into()
+    }
+}
+
+impl MqttClient for MqttStub {
+    fn connect(&mut self, client_id: &str, last_will: &MqttLastWill) -> Result<(), anyhow::Error> {
+        if self.outages.is_down() {
+            return Err(HttpTransportError::Connection(
+                "[mqtt stub] connection refused".to_owned(),
+            )
+            .into());
+        }
+        if !self.connected {
+            info!("[mqtt stub] {} connected", client_id);
+            self.connected = true;
+            self.last_will = Some((last_will.topic.clone(), last_will.payload.clone()));
+        }
+        Ok(())
+    }
+
+    fn is_connected(&mut self) -> bool {
+        if self.connected && self.outages.is_down() {
+            self.connected = false;
+            if let Some((topic, payload)) = self.last_will.take() {
+                info!("[mqtt stub] -> {} {} (last will, retained)", topic, payload);
+                self.retained.insert(topic, payload);
+            }
+        }
+        self.connected
+    }
+
+    fn subscribe(&mut self, topic: &str) -> Result<(), anyhow::Error> {
+        if !self.is_connected() {
+            return Err(MqttStub::not_connected());
+        }
+        info!("[mqtt stub] subscribed to {}", topic);
+        if let Some(payload) = self.retained.get(topic) {
+            self.received.push_back(MqttMessage {
+                topic: topic.to_owned(),
+                payload: payload.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    fn publish(&mut self, topic: &str, payload: &str, retain: bool) -> Result<(), anyhow::Error> {
+        if !self.is_connected() {
+            return Err(MqttStub::not_connected());
+        }
+        info!(
+            "[mqtt stub] -> {} {}{}",
+            topic,
+            payload,
+            if retain { " (retained)" } else { "" }
+        );
+        if retain {
+            self.retained.insert(topic.to_owned(), payload.to_owned());
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Option<MqttMessage> {
+        let now = self.now.as_ref().map(|now| now.get());
+        if let (Some(now), true) = (now, self.is_connected()) {
+            while self.scheduled.front().is_some_and(|(at, _)| *at <= now) {
+                let (_, message) = self.scheduled.pop_front().unwrap();
+                info!("[mqtt stub] <- {} {}", message.topic, message.payload);
+                self.received.push_back(message);
+            }
+        }
+        self.received.pop_front()
+    }
+}
@@ -0,0 +1,30 @@
+use std::{cell::Cell, rc::Rc};
+
+use chrono::{DateTime, Utc};
+
+/// Ranges of virtual time during which the simulated network is down.
+#[derive(Clone, Default)]
+pub struct Outages {
+    now: Option<Rc<Cell<DateTime<Utc>>>>,
+    ranges: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+}
+
+impl Outages {
+    pub fn new(
+        now: Rc<Cell<DateTime<Utc>>>,
+        ranges: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> Outages {
+        Outages {
+            now: Some(now),
+            ranges,
+        }
+    }
+
+    pub fn is_down(&self) -> bool {
+        self.now.as_ref().is_some_and(|now| {
+            self.ranges
+                .iter()
+                .any(|(from, until)| now.get() >= *from && now.get() < *until)
+        })
+    }
+}
@@ -0,0 +1,511 @@
+use std::{
+    cell::{Cell, RefCell},
+    env, fs,
+    rc::Rc,
+};
+
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use ed25519_dalek::{Signer, SigningKey, SECRET_KEY_LENGTH};
+use log::{LevelFilter, Log, Metadata, Record};
+
+use super::{
+    api_stub::ApiStub,
+    http_stub::{HttpStub, StubCredentials},
+    mdns_stub::MdnsStub,
+    mqtt_stub::MqttStub,
+    outages::Outages,
+    simulated_clock::SimulatedClock,
+    simulated_gpio::{SimulatedButtons, SimulatedBuzzer},
+    simulated_system::SimulatedSystem,
+    simulated_wifi::{
+        SimulatedNetwork, SimulatedWifi, SIMULATED_PASSWORD, SIMULATED_RSSI, SIMULATED_SSID,
+    },
+    tcp_mqtt::TcpMqtt,
+    tls_stub::{HttpsStub, TlsStub},
+};
+use crate::{
+    config::config::{
+        ALARM_CONSUMED_URL, ALARM_EVENT_URL, DEFAULT_CONFIGURATION_URI, DEFAULT_SERVER_URL,
+        DEFAULT_TIMEZONE, DEFAULT_TIMEZONE_NAME, DIAGNOSTIC_URL, REGISTER_DEVICE_URL,
+    },
+    dto::{
+        signed_configuration::SignedConfiguration, wifi_credentials::WifiCredentials,
+        wifi_settings::WifiSettings,
+    },
+    helper::discovery_helper::select_server_url,
+    helper::provisioning_helper::{rebase_server_url, save_wifi_settings},
+    helper::signature_helper::parse_public_key,
+    helper::timezone_helper::{get_user_timezone, parse_timezone, UserTimeZone},
+    helper::tls_helper::{configured_tls_settings, from_hex, parse_pin, to_hex, TlsSettings},
+    platform::{
+        api_server::ApiRequest,
+        device::Device,
+        http::RebasedHttpClient,
+        mdns::MdnsService,
+        mqtt::{MqttClient, MqttMessage},
+        storage::MemoryStorage,
+    },
+    service::{mqtt_service::mqtt_topic, orchestrator_service::orchestrate},
+    ConfigurationResponse,
+};
+
+const USAGE: &str = "usage: simulator [--config <configuration.json>] [--start <rfc3339>] \
+[--days <n>] [--step-ms <ms>] [--press <rfc3339>[/<ms>]]... \
+[--outage <rfc3339>/<minutes>]... [--mqtt-broker <host:port>] \
+[--mqtt-command <rfc3339>/<command>=<payload>]... \
+[--api-request '<rfc3339>/<method> <path> [<body>]']... \
+[--wifi-network <ssid>/<password>/<rssi>]... [--saved-network <ssid>/<password>[/<priority>]]... \
+[--mdns-server <instance>/<ip>:<port>]... [--tls-stub] [--tls-ca <ca.pem>] [--tls-pin <sha256>]... \
+[--require-https] [--auth token|secret [--revoke-auth <rfc3339>]...] \
+[--sign-config <secret key>] [--config-key <public key>] [--unprovisioned --portal-request '<rfc3339>/<method> <path> [<body>]'...] [--verbose]";
+
+const DEFAULT_PRESS_MILLISECONDS: i64 = 500;
+
+const MAC_ADDRESS: &str = "02:00:00:00:00:01";
+
+struct SimulatorOptions {
+    configuration_path: Option<String>,
+    start: DateTime<Utc>,
+    days: i64,
+    step_ms: u32,
+    presses: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+    outages: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+    mqtt_broker: Option<String>,
+    mqtt_commands: Vec<(DateTime<Utc>, String, String)>,
+    api_requests: Vec<(DateTime<Utc>, ApiRequest)>,
+    wifi_networks: Vec<SimulatedNetwork>,
+    saved_networks: Vec<WifiCredentials>,
+    mdns_servers: Vec<MdnsService>,
+    tls_stub: bool,
+    tls: TlsSettings,
+    auth: Option<StubCredentials>,
+    auth_revocations: Vec<DateTime<Utc>>,
+    signing_key: Option<SigningKey>,
+    configuration_public_key: Option<String>,
+    unprovisioned: bool,
+    portal_requests: Vec<(DateTime<Utc>, ApiRequest)>,
+    verbose: bool,
+}
+
+struct StdoutLogger;
+
+impl Log for StdoutLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            println!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StdoutLogger = StdoutLogger;
+
+/// Host entry point: runs `orchestrate` against a virtual clock, printing
+/// buzzers instead of GPIOs and answering HTTP calls from a local stub.
+pub fn simulate() {
+    let options = match parse_options(env::args().skip(1).collect()) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(if options.verbose {
+        LevelFilter::Info
+    } else {
+        LevelFilter::Error
+    });
+
+    let tls_stub = match options.tls_stub {
+        true => Some(TlsStub::start().unwrap_or_else(|e| {
+            eprintln!("unable to start the TLS stub: {}", e);
+            std::process::exit(2);
+        })),
+        false => None,
+    };
+    // the stub answers at the address of the announced server, else of the
+    // TLS stub, entered as in the setup page
+    let configured_server = tls_stub.as_ref().map(TlsStub::server_url);
+    let announced_server =
+        select_server_url(&options.mdns_servers, None).or_else(|| configured_server.clone());
+    let at = |url: &str| match &announced_server {
+        Some(server_url) => rebase_server_url(url, server_url),
+        None => url.to_owned(),
+    };
+    let mut http = HttpStub::new()
+        .route(&at(REGISTER_DEVICE_URL), "{}".to_owned())
+        .route(&at(ALARM_CONSUMED_URL), "{}".to_owned())
+        .route(&at(ALARM_EVENT_URL), "{}".to_owned())
+        .route(&at(DIAGNOSTIC_URL), "{}".to_owned());
+    let mut mqtt = MqttStub::new();
+    let mut timezone =
+        DEFAULT_TIMEZONE_NAME
+            .and_then(parse_timezone)
+            .unwrap_or(UserTimeZone::Fixed(
+                FixedOffset::east_opt(DEFAULT_TIMEZONE).unwrap(),
+            ));
+    if let Some(path) = &options.configuration_path {
+        let body = fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("unable to read {}: {}", path, e);
+            std::process::exit(2);
+        });
+        if let Ok(configuration) = serde_json::from_str::<ConfigurationResponse>(&body) {
+            timezone = get_user_timezone(&configuration);
+            http = http.route(&at(&configuration.i_am_alive_endpoint), "{}".to_owned());
+        }
+        let body = match &options.signing_key {
+            Some(signing_key) => {
+                println!(
+                    "[simulator] configuration signed, public key {}",
+                    to_hex(signing_key.verifying_key().as_bytes())
+                );
+                sign_configuration(signing_key, &body, MAC_ADDRESS, options.start)
+            }
+            None => body,
+        };
+        mqtt = mqtt.retain(&mqtt_topic(MAC_ADDRESS, "config"), body.clone());
+        http = http.route(&at(DEFAULT_CONFIGURATION_URI), body);
+    }
+
+    let now = Rc::new(Cell::new(options.start));
+    let outages = Outages::new(now.clone(), options.outages.clone());
+    http = http.outages(outages.clone());
+    if let Some(kind) = options.auth {
+        http = http.authentication(
+            &at(DEFAULT_SERVER_URL),
+            &at(REGISTER_DEVICE_URL),
+            kind,
+            now.clone(),
+            options.auth_revocations.clone(),
+        );
+    }
+    let mqtt: Box<dyn MqttClient> = match &options.mqtt_broker {
+        Some(address) => Box::new(TcpMqtt::new(address.clone())),
+        None => {
+            let commands = options
+                .mqtt_commands
+                .iter()
+                .map(|(at, command, payload)| {
+                    let message = MqttMessage {
+                        topic: mqtt_topic(MAC_ADDRESS, &format!("command/{}", command)),
+                        payload: payload.clone(),
+                    };
+                    (*at, message)
+                })
+                .collect();
+            Box::new(mqtt.outages(outages).schedule(now.clone(), commands))
+        }
+    };
+    let mut storage = MemoryStorage::new();
+    if !options.unprovisioned {
+        let mut networks = options.saved_networks;
+        if networks.is_empty() {
+            networks.push(WifiCredentials {
+                ssid: SIMULATED_SSID.to_owned(),
+                password: SIMULATED_PASSWORD.to_owned(),
+                priority: 0,
+                static_ip: None,
+            });
+        }
+        let settings = WifiSettings {
+            networks,
+            server_url: configured_server,
+            configuration_public_key: options.configuration_public_key,
+            ..Default::default()
+        };
+        save_wifi_settings(&mut storage, &settings).unwrap();
+    }
+    let mut wifi_networks = options.wifi_networks;
+    if wifi_networks.is_empty() {
+        wifi_networks.push(SimulatedNetwork {
+            ssid: SIMULATED_SSID.to_owned(),
+            password: SIMULATED_PASSWORD.to_owned(),
+            rssi: SIMULATED_RSSI,
+        });
+    }
+    let https =
+        HttpsStub::new(Box::new(http), options.tls, tls_stub.as_ref()).unwrap_or_else(|e| {
+            eprintln!("invalid --tls-ca: {}", e);
+            std::process::exit(2);
+        });
+    let server_url = Rc::new(RefCell::new(None));
+    let rings = Rc::new(Cell::new(0));
+    let mut device = Device {
+        clock: Box::new(SimulatedClock::new(now.clone(), options.step_ms)),
+        wifi: Box::new(SimulatedWifi::new(MAC_ADDRESS.to_owned(), wifi_networks)),
+        http: Box::new(RebasedHttpClient::new(Box::new(https), server_url.clone())),
+        server_url,
+        mdns: Box::new(MdnsStub::new(options.mdns_servers)),
+        mqtt,
+        buzzer1: Box::new(SimulatedBuzzer::new(
+            "buzzer1 (GPIO5)".to_owned(),
+            now.clone(),
+            timezone,
+            rings.clone(),
+        )),
+        buzzer2: Box::new(SimulatedBuzzer::new(
+            "buzzer2 (GPIO15)".to_owned(),
+            now.clone(),
+            timezone,
+            rings.clone(),
+        )),
+        buttons: Box::new(SimulatedButtons::new(now.clone(), options.presses.clone())),
+        storage: Box::new(storage),
+        api: Box::new(ApiStub::new("api stub", now.clone(), options.api_requests)),
+        portal: Box::new(ApiStub::new(
+            "portal stub",
+            now.clone(),
+            options.portal_requests,
+        )),
+        system: Box::new(SimulatedSystem),
+    };
+
+    let until = options.start + Duration::days(options.days);
+    println!(
+        "[simulator] from {} to {}",
+        timezone.local_time(&options.start),
+        timezone.local_time(&until)
+    );
+    orchestrate(&mut device, Some(until));
+    println!("[simulator] done, {} buzzer activations", rings.get());
+}
+
+fn parse_options(args: Vec<String>) -> Result<SimulatorOptions, String> {
+    let mut options = SimulatorOptions {
+        configuration_path: None,
+        start: Utc::now(),
+        days: 7,
+        step_ms: 1000,
+        presses: Vec::new(),
+        outages: Vec::new(),
+        mqtt_broker: None,
+        mqtt_commands: Vec::new(),
+        api_requests: Vec::new(),
+        wifi_networks: Vec::new(),
+        saved_networks: Vec::new(),
+        mdns_servers: Vec::new(),
+        tls_stub: false,
+        tls: configured_tls_settings()?,
+        auth: None,
+        auth_revocations: Vec::new(),
+        signing_key: None,
+        configuration_public_key: None,
+        unprovisioned: false,
+        portal_requests: Vec::new(),
+        verbose: false,
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--verbose" => options.verbose = true,
+            "--unprovisioned" => options.unprovisioned = true,
+            "--tls-stub" => options.tls_stub = true,
+            "--require-https" => options.tls.require_https = true,
+            "--config" | "--start" | "--days" | "--step-ms" | "--press" | "--outage"
+            | "--mqtt-broker" | "--mqtt-command" | "--api-request" | "--portal-request"
+            | "--wifi-network" | "--saved-network" | "--mdns-server" | "--tls-ca" | "--tls-pin"
+            | "--auth" | "--revoke-auth" | "--sign-config" | "--config-key" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("missing value for {}", arg))?;
+                let invalid = |_| format!("invalid value for {}: {}", arg, value);
+                match arg.as_str() {
+                    "--config" => options.configuration_path = Some(value.clone()),
+                    "--mqtt-broker" => options.mqtt_broker = Some(value.clone()),
+                    "--start" => {
+                        options.start = DateTime::parse_from_rfc3339(&value)
+                            .map_err(|_| format!("invalid value for {}: {}", arg, value))?
+                            .with_timezone(&Utc)
+                    }
+                    "--days" => options.days = value.parse().map_err(invalid)?,
+                    "--press" => options.presses.push(
+                        parse_press(&value)
+                            .ok_or_else(|| format!("invalid value for {}: {}", arg, value))?,
+                    ),
+                    "--mqtt-command" => options.mqtt_commands.push(
+                        parse_mqtt_command(&value)
+                            .ok_or_else(|| format!("invalid value for {}: {}", arg, value))?,
+                    ),
+                    "--api-request" => options.api_requests.push(
+                        parse_api_request(&value)
+                            .ok_or_else(|| format!("invalid value for {}: {}", arg, value))?,
+                    ),
+                    "--wifi-network" => options.wifi_networks.push(
+                        parse_wifi_network(&value)
+                            .ok_or_else(|| format!("invalid value for {}: {}", arg, value))?,
+                    ),
+                    "--saved-network" => options.saved_networks.push(
+                        parse_saved_network(&value)
+                            .ok_or_else(|| format!("invalid value for {}: {}", arg, value))?,
+                    ),
+                    "--mdns-server" => options.mdns_servers.push(
+                        parse_mdns_server(&value)
+                            .ok_or_else(|| format!("invalid value for {}: {}", arg, value))?,
+                    ),
+                    "--tls-ca" => {
+                        options.tls.ca_certificate = Some(
+                            fs::read_to_string(&value)
+                                .map_err(|e| format!("unable to read {}: {}", value, e))?,
+                        )
+                    }
+                    "--tls-pin" => options.tls.pins.push(parse_pin(&value)?),
+                    "--auth" => {
+                        options.auth = Some(match value.as_str() {
+                            "token" => StubCredentials::Token,
+                            "secret" => StubCredentials::Secret,
+                            _ => return Err(format!("invalid value for {}: {}", arg, value)),
+                        })
+                    }
+                    "--revoke-auth" => options.auth_revocations.push(
+                        DateTime::parse_from_rfc3339(&value)
+                            .map_err(|_| format!("invalid value for {}: {}", arg, value))?
+                            .with_timezone(&Utc),
+                    ),
+                    "--sign-config" => {
+                        options.signing_key = Some(
+                            parse_signing_key(&value)
+                                .ok_or_else(|| format!("invalid value for {}: {}", arg, value))?,
+                        )
+                    }
+                    "--config-key" => {
+                        parse_public_key(&value)?;
+                        options.configuration_public_key = Some(value.clone())
+                    }
+                    "--portal-request" => options.portal_requests.push(
+                        parse_api_request(&value)
+                            .ok_or_else(|| format!("invalid value for {}: {}", arg, value))?,
+                    ),
+                    "--outage" => options.outages.push(
+                        parse_outage(&value)
+                            .ok_or_else(|| format!("invalid value for {}: {}", arg, value))?,
+                    ),
+                    _ => options.step_ms = value.parse().map_err(invalid)?,
+                }
+            }
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    // the portal of a device without credentials waits forever
+    if options.unprovisioned && options.portal_requests.is_empty() {
+        return Err("--unprovisioned needs a --portal-request saving credentials".to_owned());
+    }
+    if options.auth.is_none() && !options.auth_revocations.is_empty() {
+        return Err("--revoke-auth needs --auth".to_owned());
+    }
+    Ok(options)
+}
+
+/// Parses the 64 hexadecimal digits of an Ed25519 secret key, as in RFC 8032.
+fn parse_signing_key(value: &str) -> Option<SigningKey> {
+    let bytes = <[u8; SECRET_KEY_LENGTH]>::try_from(from_hex(value.trim())?).ok()?;
+    Some(SigningKey::from_bytes(&bytes))
+}
+
+/// `configuration` as the server sends it when it signs the configurations,
+/// bound to the device with `mac_address` and issued at `issued_at`.
+fn sign_configuration(
+    signing_key: &SigningKey,
+    configuration: &str,
+    mac_address: &str,
+    issued_at: DateTime<Utc>,
+) -> String {
+    let configuration = match serde_json::from_str::<serde_json::Value>(configuration) {
+        Ok(serde_json::Value::Object(mut fields)) => {
+            fields.insert("macAddress".to_owned(), mac_address.into());
+            fields.insert("issuedAt".to_owned(), issued_at.to_rfc3339().into());
+            serde_json::Value::Object(fields).to_string()
+        }
+        // signed as it is, to check that the device rejects it
+        _ => configuration.to_owned(),
+    };
+    let signature = signing_key.sign(configuration.as_bytes());
+    serde_json::to_string(&SignedConfiguration {
+        signature: to_hex(&signature.to_bytes()),
+        configuration,
+    })
+    .unwrap()
+}
+
+/// Parses `<rfc3339>[/<milliseconds held>]`.
+fn parse_press(value: &str) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let (from, held) = match value.split_once('/') {
+        Some((from, held)) => (from, held.parse().ok()?),
+        None => (value, DEFAULT_PRESS_MILLISECONDS),
+    };
+    let from = DateTime::parse_from_rfc3339(from).ok()?.with_timezone(&Utc);
+    Some((from, from + Duration::milliseconds(held)))
+}
+
+/// Parses `<rfc3339>/<minutes without network>`.
+fn parse_outage(value: &str) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let (from, minutes) = value.split_once('/')?;
+    let from = DateTime::parse_from_rfc3339(from).ok()?.with_timezone(&Utc);
+    Some((from, from + Duration::minutes(minutes.parse().ok()?)))
+}
+
+/// Parses `<rfc3339>/<command>=<payload>`, e.g. `.../alarms=OFF`.
+fn parse_mqtt_command(value: &str) -> Option<(DateTime<Utc>, String, String)> {
+    let (at, command) = value.split_once('/')?;
+    let at = DateTime::parse_from_rfc3339(at).ok()?.with_timezone(&Utc);
+    let (command, payload) = command.split_once('=').unwrap_or((command, "PRESS"));
+    Some((at, command.to_owned(), payload.to_owned()))
+}
+
+/// Parses `<rfc3339>/<method> <path> [<body>]`, e.g. `.../GET /api/status`.
+fn parse_api_request(value: &str) -> Option<(DateTime<Utc>, ApiRequest)> {
+    let (at, request) = value.split_once('/')?;
+    let at = DateTime::parse_from_rfc3339(at).ok()?.with_timezone(&Utc);
+    let mut parts = request.splitn(3, ' ');
+    let method = parts.next()?.to_uppercase();
+    let path = parts.next()?.to_owned();
+    let body = parts.next().unwrap_or("").to_owned();
+    Some((at, ApiRequest { method, path, body }))
+}
+
+/// Parses `<ssid>/<password>/<rssi>`, e.g. `Office/secret-password/-70`.
+fn parse_wifi_network(value: &str) -> Option<SimulatedNetwork> {
+    let mut parts = value.rsplitn(3, '/');
+    let rssi = parts.next()?.parse().ok()?;
+    let password = parts.next()?.to_owned();
+    let ssid = parts.next()?.to_owned();
+    Some(SimulatedNetwork {
+        ssid,
+        password,
+        rssi,
+    })
+}
+
+/// Parses `<ssid>/<password>[/<priority>]`.
+fn parse_saved_network(value: &str) -> Option<WifiCredentials> {
+    let (ssid, rest) = value.split_once('/')?;
+    let (password, priority) = match rest.split_once('/') {
+        Some((password, priority)) => (password, priority.parse().ok()?),
+        None => (rest, 0),
+    };
+    Some(WifiCredentials {
+        ssid: ssid.to_owned(),
+        password: password.to_owned(),
+        priority,
+        static_ip: None,
+    })
+}
+
+/// Parses `<instance>/<ip>:<port>`, e.g. `elisys/10.0.0.9:8080`.
+fn parse_mdns_server(value: &str) -> Option<MdnsService> {
+    let (instance, address) = value.split_once('/')?;
+    let (ip, port) = address.split_once(':')?;
+    Some(MdnsService {
+        instance: instance.to_owned(),
+        hostname: instance.to_owned(),
+        addresses: vec![ip.parse().ok()?],
+        port: port.parse().ok()?,
+        txt: Vec::new(),
+    })
+}
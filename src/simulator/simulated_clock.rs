@@ -0,0 +1,50 @@
+use std::{cell::Cell, rc::Rc};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::platform::clock::Clock;
+
+/// Virtual clock: delays advance the shared time instead of sleeping.
+/// `min_step_ms` lets the simulator skip through a week in a few seconds.
+pub struct SimulatedClock {
+    now: Rc<Cell<DateTime<Utc>>>,
+    min_step_ms: u32,
+    boot: DateTime<Utc>,
+    synchronized_at: Option<DateTime<Utc>>,
+}
+
+impl SimulatedClock {
+    pub fn new(now: Rc<Cell<DateTime<Utc>>>, min_step_ms: u32) -> SimulatedClock {
+        let boot = now.get();
+        SimulatedClock {
+            now,
+            min_step_ms,
+            boot,
+            synchronized_at: None,
+        }
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.now.get()
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        let step = Duration::milliseconds(ms.max(self.min_step_ms) as i64);
+        self.now.set(self.now.get() + step);
+    }
+
+    fn synchronize(&mut self, _one_shot: bool) -> Result<(), String> {
+        self.synchronized_at = Some(self.now.get());
+        Ok(())
+    }
+
+    fn synchronized_at(&self) -> Option<DateTime<Utc>> {
+        self.synchronized_at
+    }
+
+    fn uptime_ms(&self) -> u64 {
+        (self.now.get() - self.boot).num_milliseconds() as u64
+    }
+}
@@ -0,0 +1,109 @@
+use std::{cell::Cell, rc::Rc};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::helper::timezone_helper::UserTimeZone;
+use crate::platform::{
+    gpio::{Button, ButtonProvider, OutputPin},
+    tone::ToneOutput,
+};
+
+/// Output pin that prints when the buzzer starts ringing, using the
+/// virtual time of the simulator.
+pub struct SimulatedBuzzer {
+    name: String,
+    now: Rc<Cell<DateTime<Utc>>>,
+    timezone: UserTimeZone,
+    last_high: Option<DateTime<Utc>>,
+    rings: Rc<Cell<u32>>,
+}
+
+impl SimulatedBuzzer {
+    pub fn new(
+        name: String,
+        now: Rc<Cell<DateTime<Utc>>>,
+        timezone: UserTimeZone,
+        rings: Rc<Cell<u32>>,
+    ) -> SimulatedBuzzer {
+        SimulatedBuzzer {
+            name,
+            now,
+            timezone,
+            last_high: None,
+            rings,
+        }
+    }
+}
+
+impl OutputPin for SimulatedBuzzer {
+    fn set_high(&mut self) {
+        let now = self.now.get();
+        let is_new_ring = self
+            .last_high
+            .map_or(true, |last_high| now - last_high > Duration::seconds(5));
+        if is_new_ring {
+            println!(
+                "[simulator] {} started buzzing at {}",
+                self.name,
+                self.timezone.local_time(&now)
+            );
+            self.rings.set(self.rings.get() + 1);
+        }
+        self.last_high = Some(now);
+    }
+
+    fn set_low(&mut self) {}
+}
+
+impl ToneOutput for SimulatedBuzzer {
+    fn set_tone(&mut self, frequency_hz: u32) {
+        if frequency_hz > 0 {
+            self.set_high();
+        }
+    }
+
+    fn set_volume(&mut self, _percent: u8) {}
+}
+
+/// Snooze button pressed at scheduled virtual times. A press shorter than
+/// the simulation step is still seen once, so short presses are never lost.
+pub struct SimulatedButton {
+    now: Rc<Cell<DateTime<Utc>>>,
+    presses: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+    last_poll: Option<DateTime<Utc>>,
+}
+
+impl Button for SimulatedButton {
+    fn is_pressed(&mut self) -> bool {
+        let now = self.now.get();
+        let last_poll = self.last_poll.replace(now);
+        self.presses.iter().any(|(from, to)| {
+            (*from <= now && now < *to)
+                || last_poll.is_some_and(|last_poll| last_poll < *from && *to <= now)
+        })
+    }
+}
+
+pub struct SimulatedButtons {
+    now: Rc<Cell<DateTime<Utc>>>,
+    presses: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+}
+
+impl SimulatedButtons {
+    pub fn new(
+        now: Rc<Cell<DateTime<Utc>>>,
+        presses: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> SimulatedButtons {
+        SimulatedButtons { now, presses }
+    }
+}
+
+impl ButtonProvider for SimulatedButtons {
+    fn button(&mut self, _gpio: i32) -> Option<Box<dyn Button>> {
+        Some(Box::new(SimulatedButton {
+            now: self.now.clone(),
+            presses: self.presses.clone(),
+            last_poll: None,
+        }))
+    }
+}
@@ -0,0 +1,9 @@
+use crate::platform::system::System;
+
+pub struct SimulatedSystem;
+
+impl System for SimulatedSystem {
+    fn restart(&mut self) {
+        println!("[simulator] restart requested, carrying on");
+    }
+}
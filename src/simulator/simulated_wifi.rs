@@ -0,0 +1,96 @@
+use anyhow::anyhow;
+
+use crate::config::config::DEVICE_NAME;
+use crate::dto::wifi_credentials::WifiCredentials;
+use crate::helper::static_ip_helper::dhcp_hostname;
+use crate::platform::wifi::{ScannedNetwork, Wifi};
+
+/// The network in range of the simulator unless others are given.
+pub const SIMULATED_SSID: &str = "Simulated";
+pub const SIMULATED_PASSWORD: &str = "simulated-password";
+pub const SIMULATED_RSSI: i32 = -55;
+
+/// A network in range of the simulator.
+pub struct SimulatedNetwork {
+    pub ssid: String,
+    pub password: String,
+    pub rssi: i32,
+}
+
+pub struct SimulatedWifi {
+    connected: Option<usize>,
+    mac_address: String,
+    networks: Vec<SimulatedNetwork>,
+}
+
+impl SimulatedWifi {
+    pub fn new(mac_address: String, networks: Vec<SimulatedNetwork>) -> SimulatedWifi {
+        SimulatedWifi {
+            connected: None,
+            mac_address,
+            networks,
+        }
+    }
+}
+
+impl Wifi for SimulatedWifi {
+    fn is_connected(&mut self) -> bool {
+        self.connected.is_some()
+    }
+
+    fn scan(&mut self) -> Result<Vec<ScannedNetwork>, anyhow::Error> {
+        Ok(self
+            .networks
+            .iter()
+            .map(|network| ScannedNetwork {
+                ssid: network.ssid.clone(),
+                rssi: network.rssi,
+            })
+            .collect())
+    }
+
+    fn connect(&mut self, credentials: &WifiCredentials) -> Result<(), anyhow::Error> {
+        let index = self
+            .networks
+            .iter()
+            .position(|network| network.ssid == credentials.ssid)
+            .ok_or_else(|| anyhow!("network {} not found", credentials.ssid))?;
+        if self.networks[index].password != credentials.password {
+            return Err(anyhow!("wrong password for {}", credentials.ssid));
+        }
+        self.connected = Some(index);
+        match &credentials.static_ip {
+            Some(static_ip) => println!(
+                "[simulator] joined {} as {} ({} via {}, DNS {:?})",
+                credentials.ssid,
+                static_ip.address,
+                static_ip.netmask,
+                static_ip.gateway,
+                static_ip.dns_servers
+            ),
+            None => println!(
+                "[simulator] joined {} with DHCP as {}",
+                credentials.ssid,
+                dhcp_hostname(DEVICE_NAME, &self.mac_address)
+            ),
+        }
+        Ok(())
+    }
+
+    fn start_access_point(&mut self, ssid: &str, password: &str) -> Result<(), anyhow::Error> {
+        println!(
+            "[simulator] access point {} started, passphrase {}",
+            ssid, password
+        );
+        self.connected = None;
+        Ok(())
+    }
+
+    fn mac_address(&mut self) -> String {
+        self.mac_address.clone()
+    }
+
+    fn rssi(&mut self) -> Option<i32> {
+        self.connected.map(|index| self.networks[index].rssi)
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+
+use crate::platform::{
+    http::HttpTransportError,
+    mqtt::{MqttClient, MqttLastWill, MqttMessage},
+};
+
+const KEEP_ALIVE_SECONDS: u16 = 60;
+
+/// Minimal MQTT 3.1.1 client over a plain TCP socket, to run the simulator
+/// against a real broker such as Mosquitto. Everything is sent and
+/// subscribed with QoS 0, except the Last Will.
+pub struct TcpMqtt {
+    address: String,
+    stream: Option<TcpStream>,
+    buffer: Vec<u8>,
+    last_sent: Instant,
+    next_packet_id: u16,
+}
+
+impl TcpMqtt {
+    pub fn new(address: String) -> TcpMqtt {
+        TcpMqtt {
+            address,
+            stream: None,
+            buffer: Vec::new(),
+            last_sent: Instant::now(),
+            next_packet_id: 1,
+        }
+    }
+
+    fn send(&mut self, packet: Vec<u8>) -> Result<(), anyhow::Error> {
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => return Err(connection_error("not connected to the MQTT broker")),
+        };
+        if let Err(e) = stream.write_all(&packet) {
+            self.stream = None;
+            return Err(connection_error(&e.to_string()));
+        }
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    fn read_available(&mut self) {
+        let mut chunk = [0u8; 1024];
+        while let Some(stream) = &mut self.stream {
+            match stream.read(&mut chunk) {
+                Ok(0) => {
+                    warn!("the MQTT broker closed the connection");
+                    self.stream = None;
+                }
+                Ok(bytes_read) => self.buffer.extend_from_slice(&chunk[..bytes_read]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("MQTT connection lost: {}", e);
+                    self.stream = None;
+                }
+            }
+        }
+    }
+}
+
+impl MqttClient for TcpMqtt {
+    fn connect(&mut self, client_id: &str, last_will: &MqttLastWill) -> Result<(), anyhow::Error> {
+        if self.stream.is_some() {
+            return Ok(());
+        }
+        let mut body = Vec::new();
+        push_string(&mut body, b"MQTT");
+        // protocol level 4, then clean session, will with QoS 1 and retained
+        body.extend_from_slice(&[4, 0x02 | 0x04 | 0x08 | 0x20]);
+        body.extend_from_slice(&KEEP_ALIVE_SECONDS.to_be_bytes());
+        push_string(&mut body, client_id.as_bytes());
+        push_string(&mut body, last_will.topic.as_bytes());
+        push_string(&mut body, last_will.payload.as_bytes());
+
+        let mut stream =
+            TcpStream::connect(&self.address).map_err(|e| connection_error(&e.to_string()))?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        stream.write_all(&packet(0x10, &body))?;
+        let mut connack = [0u8; 4];
+        stream.read_exact(&mut connack)?;
+        if connack[0] != 0x20 || connack[3] != 0 {
+            return Err(connection_error(&format!(
+                "connection refused by the MQTT broker, code {}",
+                connack[3]
+            )));
+        }
+        stream.set_nonblocking(true)?;
+        info!("connected to the MQTT broker {}", self.address);
+        self.stream = Some(stream);
+        self.buffer.clear();
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    fn is_connected(&mut self) -> bool {
+        self.read_available();
+        self.stream.is_some()
+    }
+
+    fn subscribe(&mut self, topic: &str) -> Result<(), anyhow::Error> {
+        let mut body = self.next_packet_id.to_be_bytes().to_vec();
+        self.next_packet_id = self.next_packet_id.wrapping_add(1).max(1);
+        push_string(&mut body, topic.as_bytes());
+        body.push(0);
+        self.send(packet(0x82, &body))
+    }
+
+    fn publish(&mut self, topic: &str, payload: &str, retain: bool) -> Result<(), anyhow::Error> {
+        let mut body = Vec::new();
+        push_string(&mut body, topic.as_bytes());
+        body.extend_from_slice(payload.as_bytes());
+        self.send(packet(0x30 | retain as u8, &body))
+    }
+
+    fn receive(&mut self) -> Option<MqttMessage> {
+        self.read_available();
+        if self.last_sent.elapsed() >= Duration::from_secs(KEEP_ALIVE_SECONDS as u64 / 2) {
+            // PINGREQ, the answer is skipped below like every other packet
+            let _ = self.send(vec![0xC0, 0]);
+        }
+        while let Some((header, start, end)) = next_packet(&self.buffer) {
+            let body = self.buffer[start..end].to_vec();
+            self.buffer.drain(..end);
+            if header >> 4 != 3 || body.len() < 2 {
+                continue;
+            }
+            let topic_length = u16::from_be_bytes([body[0], body[1]]) as usize;
+            let qos = (header >> 1) & 0x03;
+            let payload_start = 2 + topic_length + if qos > 0 { 2 } else { 0 };
+            if body.len() < payload_start {
+                continue;
+            }
+            return Some(MqttMessage {
+                topic: String::from_utf8_lossy(&body[2..2 + topic_length]).into_owned(),
+                payload: String::from_utf8_lossy(&body[payload_start..]).into_owned(),
+            });
+        }
+        None
+    }
+}
+
+fn connection_error(message: &str) -> anyhow::Error {
+    HttpTransportError::Connection(message.to_owned()).into()
+}
+
+fn push_string(buffer: &mut Vec<u8>, value: &[u8]) {
+    buffer.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(value);
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// Header byte and body range of the first complete packet of `buffer`.
+fn next_packet(buffer: &[u8]) -> Option<(u8, usize, usize)> {
+    let mut length = 0usize;
+    let mut multiplier = 1usize;
+    for (index, byte) in buffer.iter().enumerate().skip(1).take(4) {
+        length += (*byte as usize & 0x7F) * multiplier;
+        multiplier *= 128;
+        if byte & 0x80 == 0 {
+            let start = index + 1;
+            return (buffer.len() >= start + length).then_some((buffer[0], start, start + length));
+        }
+    }
+    None
+}
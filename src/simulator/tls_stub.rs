@@ -0,0 +1,209 @@
+use std::{
+    io::{self, BufReader},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::SystemTime,
+};
+
+use log::{error, info};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, ClientConnection, PrivateKey, RootCertStore, ServerConfig,
+    ServerConnection, ServerName,
+};
+
+use crate::{
+    helper::tls_helper::{check_pinned_certificate, check_url_scheme, CertificatePin, TlsSettings},
+    platform::http::{HttpClient, HttpResponse, HttpTransportError},
+};
+
+// test material of the stub, a certificate for localhost signed by tls_stub_ca.pem
+const TLS_STUB_CERTIFICATE: &str = include_str!("tls_stub_certificate.pem");
+const TLS_STUB_KEY: &str = include_str!("tls_stub_key.pem");
+
+/// Local TLS server presenting the stub certificate: it completes the
+/// handshakes, then closes the connections.
+pub struct TlsStub {
+    address: SocketAddr,
+}
+
+impl TlsStub {
+    pub fn start() -> Result<TlsStub, anyhow::Error> {
+        let certificates = parse_certificates(TLS_STUB_CERTIFICATE)?
+            .into_iter()
+            .map(Certificate)
+            .collect();
+        let key = rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(TLS_STUB_KEY.as_bytes()))?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("no key in the TLS stub key"))?;
+        let config = Arc::new(
+            ServerConfig::builder()
+                .with_safe_defaults()
+                .with_no_client_auth()
+                .with_single_cert(certificates, PrivateKey(key))?,
+        );
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        let address = listener.local_addr()?;
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let Ok(mut connection) = ServerConnection::new(config.clone()) else {
+                    continue;
+                };
+                // a handshake the client rejected fails here too
+                while connection.is_handshaking() {
+                    if connection.complete_io(&mut stream).is_err() {
+                        break;
+                    }
+                }
+                connection.send_close_notify();
+                let _ = connection.complete_io(&mut stream);
+            }
+        });
+        Ok(TlsStub { address })
+    }
+
+    pub fn server_url(&self) -> String {
+        format!("https://localhost:{}", self.address.port())
+    }
+}
+
+/// Checks the TLS settings against the `TlsStub` before each `https://`
+/// request, with the same pin check as on the device and the CA of
+/// `--tls-ca` instead of the CA bundle, then answers like `HttpStub`.
+pub struct HttpsStub {
+    inner: Box<dyn HttpClient>,
+    tls: TlsSettings,
+    address: Option<SocketAddr>,
+    verifier: Arc<SettingsVerifier>,
+}
+
+impl HttpsStub {
+    pub fn new(
+        inner: Box<dyn HttpClient>,
+        tls: TlsSettings,
+        stub: Option<&TlsStub>,
+    ) -> Result<HttpsStub, anyhow::Error> {
+        let ca = match &tls.ca_certificate {
+            None => None,
+            Some(pem) => {
+                let mut roots = RootCertStore::empty();
+                for certificate in parse_certificates(pem)? {
+                    roots.add(&Certificate(certificate))?;
+                }
+                Some(WebPkiVerifier::new(roots, None))
+            }
+        };
+        let verifier = Arc::new(SettingsVerifier {
+            pins: tls.pins.clone(),
+            ca,
+            rejection: Mutex::new(None),
+        });
+        Ok(HttpsStub {
+            inner,
+            tls,
+            address: stub.map(|stub| stub.address),
+            verifier,
+        })
+    }
+
+    fn handshake(&self, url: &str, address: SocketAddr) -> Result<(), String> {
+        let host = url
+            .trim_start_matches("https://")
+            .split(['/', ':'])
+            .next()
+            .unwrap_or_default();
+        let server_name = ServerName::try_from(host).map_err(|e| e.to_string())?;
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(self.verifier.clone())
+            .with_no_client_auth();
+        let mut connection =
+            ClientConnection::new(Arc::new(config), server_name).map_err(|e| e.to_string())?;
+        let mut stream = TcpStream::connect(address).map_err(|e| e.to_string())?;
+        *self.verifier.rejection.lock().unwrap() = None;
+        while connection.is_handshaking() {
+            if let Err(e) = connection.complete_io(&mut stream) {
+                let rejection = self.verifier.rejection.lock().unwrap().take();
+                return Err(rejection.unwrap_or_else(|| e.to_string()));
+            }
+        }
+        connection.send_close_notify();
+        let _ = connection.complete_io(&mut stream);
+        Ok(())
+    }
+}
+
+impl HttpClient for HttpsStub {
+    fn post(
+        &mut self,
+        url: &str,
+        headers: &[(&str, &str)],
+        payload: &[u8],
+    ) -> Result<HttpResponse, anyhow::Error> {
+        if let Err(message) = check_url_scheme(url, &self.tls) {
+            error!("{}", message);
+            return Err(HttpTransportError::Tls(message).into());
+        }
+        if let (Some(address), true) = (self.address, url.starts_with("https://")) {
+            match self.handshake(url, address) {
+                Ok(()) => info!("[tls stub] certificate of {} accepted", url),
+                Err(rejection) => {
+                    let message = format!("{}: {}", url, rejection);
+                    error!("{}", message);
+                    return Err(HttpTransportError::Tls(message).into());
+                }
+            }
+        }
+        self.inner.post(url, headers, payload)
+    }
+}
+
+/// The pins when there are some, else the CA of `--tls-ca`.
+struct SettingsVerifier {
+    pins: Vec<CertificatePin>,
+    ca: Option<WebPkiVerifier>,
+    // why the last server certificate was rejected
+    rejection: Mutex<Option<String>>,
+}
+
+impl ServerCertVerifier for SettingsVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let result = match (&self.ca, self.pins.is_empty()) {
+            (_, false) => check_pinned_certificate(&end_entity.0, &self.pins),
+            (Some(ca), true) => ca
+                .verify_server_cert(
+                    end_entity,
+                    intermediates,
+                    server_name,
+                    scts,
+                    ocsp_response,
+                    now,
+                )
+                .map(|_| ())
+                .map_err(|e| format!("certificate rejected with the CA certificate: {}", e)),
+            (None, true) => {
+                Err("the simulator has no CA bundle, give --tls-ca or --tls-pin".to_owned())
+            }
+        };
+        result
+            .map(|()| ServerCertVerified::assertion())
+            .map_err(|message| {
+                *self.rejection.lock().unwrap() = Some(message.clone());
+                rustls::Error::General(message)
+            })
+    }
+}
+
+fn parse_certificates(pem: &str) -> Result<Vec<Vec<u8>>, io::Error> {
+    rustls_pemfile::certs(&mut BufReader::new(pem.as_bytes()))
+}